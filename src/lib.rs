@@ -0,0 +1,783 @@
+// ======================================
+// Tic Tac Toe — game rules
+// Board state, move legality and win/draw detection, free of any terminal code
+// so the binary and tests can share them.
+// ======================================
+
+use std::fmt;
+
+// ======================================
+// CONSTANTS & TYPES
+// ======================================
+
+pub const PLAYER_X: char = 'X';
+pub const PLAYER_O: char = 'O';
+// Marker for a free cell; the front end shows the cell's number there instead.
+pub const EMPTY: char = ' ';
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Variant {
+    #[default]
+    Standard,
+    Torus, // rows and columns wrap, so diagonals continue across the edges
+}
+
+// Where a position stands. A winning line takes precedence over a full board.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameResult {
+    XWins(Vec<usize>),
+    OWins(Vec<usize>),
+    Draw,
+    Ongoing,
+}
+
+impl GameResult {
+    pub fn winner(&self) -> Option<char> {
+        match self {
+            GameResult::XWins(_) => Some(PLAYER_X),
+            GameResult::OWins(_) => Some(PLAYER_O),
+            GameResult::Draw | GameResult::Ongoing => None,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        *self != GameResult::Ongoing
+    }
+}
+
+// Why a hand-made position can't be played from.
+#[derive(Debug)]
+pub enum BoardError {
+    BadCounts,
+    AlreadyWon(char),
+    Full,
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoardError::BadCounts => write!(f, "X needs as many marks as O or one more"),
+            BoardError::AlreadyWon(mark) => write!(f, "{} has already won", mark),
+            BoardError::Full => write!(f, "the board is full"),
+        }
+    }
+}
+
+impl std::error::Error for BoardError {}
+
+// Why a written-out board such as "XO.XO..X." couldn't be read.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    NotSquare(usize),
+    BadCell(char),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::NotSquare(cells) => write!(f, "{} cells don't make a square board", cells),
+            ParseError::BadCell(c) => write!(f, "{:?} isn't X, O or . for an empty cell", c),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Why `GameState::validate_move` refused a move.
+#[derive(Debug, PartialEq)]
+pub enum MoveError {
+    OutOfRange(usize),
+    Occupied(usize),
+    GameOver,
+    WrongBoard(usize),   // ultimate: the move has to go in this small board
+    BoardDecided(usize), // ultimate: this small board is already won or full
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::OutOfRange(idx) => write!(f, "cell {} is off the board", idx + 1),
+            MoveError::Occupied(idx) => write!(f, "cell {} is already taken", idx + 1),
+            MoveError::GameOver => write!(f, "the game is already over"),
+            MoveError::WrongBoard(board) => write!(f, "the move has to go in board {}", board + 1),
+            MoveError::BoardDecided(board) => write!(f, "board {} is already decided", board + 1),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+// ======================================
+// GAME STATE
+// ======================================
+
+// One game in progress: the board, whose turn it is and how many marks are down.
+// Every change to the board during play goes through here.
+pub struct GameState {
+    pub board: Vec<char>,
+    pub current_player: char,
+    pub move_count: usize,
+    lines: Vec<Vec<usize>>,
+    // Cells placed since the game started, newest last; see `last_move`.
+    placed: Vec<usize>,
+}
+
+impl GameState {
+    pub fn new(size: usize, win_length: usize, variant: Variant) -> Self {
+        GameState {
+            board: empty_board(size),
+            current_player: PLAYER_X,
+            move_count: 0,
+            lines: win_lines(size, win_length, variant),
+            placed: Vec::new(),
+        }
+    }
+
+    // Starts from a hand-made position; the side to move follows from the counts.
+    pub fn from_board(
+        board: Vec<char>,
+        win_length: usize,
+        variant: Variant,
+    ) -> Result<Self, BoardError> {
+        let lines = win_lines(board_size(&board), win_length, variant);
+        let move_count = board.iter().filter(|&&c| c != EMPTY).count();
+        if move_count > 0 {
+            validate_board(&board, &lines)?;
+        }
+        Ok(GameState {
+            current_player: player_to_move(&board),
+            board,
+            move_count,
+            lines,
+            placed: Vec::new(),
+        })
+    }
+
+    pub fn lines(&self) -> &[Vec<usize>] {
+        &self.lines
+    }
+
+    // Whether the current player may play `idx`, and if not, why. The board is
+    // left alone either way.
+    pub fn validate_move(&self, idx: usize) -> Result<(), MoveError> {
+        if idx >= self.board.len() {
+            return Err(MoveError::OutOfRange(idx));
+        }
+        if self.result().is_over() {
+            return Err(MoveError::GameOver);
+        }
+        if self.board[idx] != EMPTY {
+            return Err(MoveError::Occupied(idx));
+        }
+        Ok(())
+    }
+
+    // Places the current player's mark and passes the turn.
+    pub fn apply_move(&mut self, idx: usize) -> Result<(), MoveError> {
+        self.validate_move(idx)?;
+        self.board[idx] = self.current_player;
+        self.move_count += 1;
+        self.placed.push(idx);
+        self.current_player = switch_player(self.current_player);
+        Ok(())
+    }
+
+    // Takes a mark back off the board; its owner is to move again.
+    pub fn clear_cell(&mut self, idx: usize) {
+        let mark = self.board[idx];
+        if mark != EMPTY {
+            self.board[idx] = EMPTY;
+            self.move_count -= 1;
+            self.current_player = mark;
+            self.placed.retain(|&cell| cell != idx);
+        }
+    }
+
+    // The cell played most recently, so the front end can mark it. Taking a
+    // move back makes the one before it the latest again; marks that were on a
+    // hand-made starting position don't count.
+    pub fn last_move(&self) -> Option<usize> {
+        self.placed.last().copied()
+    }
+
+    pub fn result(&self) -> GameResult {
+        game_result(&self.board, &self.lines)
+    }
+
+    // Same answer as `result` for a game that was still going before the mark
+    // on `last_move`, but only looks at the lines through that cell.
+    pub fn result_after(&self, last_move: usize) -> GameResult {
+        match check_winner_at(&self.board, &self.lines, last_move) {
+            Some((PLAYER_X, line)) => GameResult::XWins(line),
+            Some((_, line)) => GameResult::OWins(line),
+            None if is_draw(&self.board) => GameResult::Draw,
+            None => GameResult::Ongoing,
+        }
+    }
+}
+
+// ======================================
+// ULTIMATE
+// ======================================
+
+// Ultimate tic-tac-toe: nine small 3×3 boards in a 3×3 arrangement. The cell
+// played in one small board sends the opponent to the small board in the same
+// place, unless that one is already decided, in which case they may play in
+// any board still open. Winning a small board claims that square of the big
+// one, and three claimed squares in a row win the game.
+pub struct UltimateState {
+    pub boards: [[char; 9]; 9],
+    pub current_player: char,
+    // The small board the next move has to go in; None when any open one will do.
+    pub forced: Option<usize>,
+    lines: Vec<Vec<usize>>,
+}
+
+impl Default for UltimateState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UltimateState {
+    pub fn new() -> Self {
+        UltimateState {
+            boards: [[EMPTY; 9]; 9],
+            current_player: PLAYER_X,
+            forced: None,
+            lines: win_lines(3, 3, Variant::Standard),
+        }
+    }
+
+    // How small board `board` stands on its own.
+    pub fn board_result(&self, board: usize) -> GameResult {
+        game_result(&self.boards[board], &self.lines)
+    }
+
+    // The big board: the winner of each small board, EMPTY for one that is
+    // still open or ended drawn, so a drawn board counts for neither side.
+    pub fn meta(&self) -> [char; 9] {
+        std::array::from_fn(|board| self.board_result(board).winner().unwrap_or(EMPTY))
+    }
+
+    // Small boards the next move may go in.
+    pub fn playable_boards(&self) -> Vec<usize> {
+        if self.result().is_over() {
+            return Vec::new();
+        }
+        match self.forced {
+            Some(board) => vec![board],
+            None => (0..9)
+                .filter(|&board| !self.board_result(board).is_over())
+                .collect(),
+        }
+    }
+
+    // A won big board is the game; otherwise it's drawn once no small board
+    // is left open. The winning line holds small-board numbers.
+    pub fn result(&self) -> GameResult {
+        match check_winner(&self.meta(), &self.lines) {
+            Some((PLAYER_X, line)) => GameResult::XWins(line),
+            Some((_, line)) => GameResult::OWins(line),
+            None if (0..9).all(|board| self.board_result(board).is_over()) => GameResult::Draw,
+            None => GameResult::Ongoing,
+        }
+    }
+
+    pub fn validate_move(&self, board: usize, cell: usize) -> Result<(), MoveError> {
+        if board >= 9 || cell >= 9 {
+            return Err(MoveError::OutOfRange(board.max(cell)));
+        }
+        if self.result().is_over() {
+            return Err(MoveError::GameOver);
+        }
+        if let Some(forced) = self.forced
+            && forced != board
+        {
+            return Err(MoveError::WrongBoard(forced));
+        }
+        if self.board_result(board).is_over() {
+            return Err(MoveError::BoardDecided(board));
+        }
+        if self.boards[board][cell] != EMPTY {
+            return Err(MoveError::Occupied(cell));
+        }
+        Ok(())
+    }
+
+    // Places the current player's mark in `cell` of small board `board`, then
+    // passes the turn and works out where the opponent has to play.
+    pub fn apply_move(&mut self, board: usize, cell: usize) -> Result<(), MoveError> {
+        self.validate_move(board, cell)?;
+        self.boards[board][cell] = self.current_player;
+        self.forced = (!self.board_result(cell).is_over()).then_some(cell);
+        self.current_player = switch_player(self.current_player);
+        Ok(())
+    }
+}
+
+// ======================================
+// GAME LOGIC
+// ======================================
+
+pub fn empty_board(size: usize) -> Vec<char> {
+    vec![EMPTY; size * size]
+}
+
+// Reads a board row by row, top left first, with '.' for an empty cell. Only
+// the layout is checked here; `validate_board` says whether it can be played.
+pub fn parse_board(text: &str) -> Result<Vec<char>, ParseError> {
+    let board = text
+        .chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            PLAYER_X => Ok(PLAYER_X),
+            PLAYER_O => Ok(PLAYER_O),
+            '.' => Ok(EMPTY),
+            other => Err(ParseError::BadCell(other)),
+        })
+        .collect::<Result<Vec<char>, ParseError>>()?;
+    let size = board_size(&board);
+    if board.is_empty() || size * size != board.len() {
+        return Err(ParseError::NotSquare(board.len()));
+    }
+    Ok(board)
+}
+
+// Side length of a square board.
+pub fn board_size(board: &[char]) -> usize {
+    board.len().isqrt()
+}
+
+pub fn switch_player(current: char) -> char {
+    if current == PLAYER_X {
+        PLAYER_O
+    } else {
+        PLAYER_X
+    }
+}
+
+pub fn player_to_move(board: &[char]) -> char {
+    let x_count = board.iter().filter(|&&c| c == PLAYER_X).count();
+    let o_count = board.iter().filter(|&&c| c == PLAYER_O).count();
+    if x_count > o_count {
+        PLAYER_O
+    } else {
+        PLAYER_X
+    }
+}
+
+// Every cell is taken. On its own this says nothing about a winner; use
+// `game_result` to decide whether a game is over and how.
+pub fn is_draw(board: &[char]) -> bool {
+    board.iter().all(|&c| c != EMPTY)
+}
+
+// The single place that decides how a game ended, so a full board with a
+// winning line on it always counts as a win.
+pub fn game_result(board: &[char], lines: &[Vec<usize>]) -> GameResult {
+    match check_winner(board, lines) {
+        Some((PLAYER_X, line)) => GameResult::XWins(line),
+        Some((_, line)) => GameResult::OWins(line),
+        None if is_draw(board) => GameResult::Draw,
+        None => GameResult::Ongoing,
+    }
+}
+
+// Every line of `win_length` cells on a size × size board. Under torus rules the
+// lines wrap around the edges, so each cell starts a line in every direction.
+pub fn win_lines(size: usize, win_length: usize, variant: Variant) -> Vec<Vec<usize>> {
+    match variant {
+        Variant::Standard => grid_lines(size, size, win_length),
+        Variant::Torus => {
+            let mut lines: Vec<Vec<usize>> = Vec::new();
+            for start in 0..size * size {
+                let (row, col) = (start / size, start % size);
+                for (dr, dc) in [(0, 1), (1, 0), (1, 1), (1, size - 1)] {
+                    let line: Vec<usize> = (0..win_length)
+                        .map(|step| ((row + dr * step) % size) * size + (col + dc * step) % size)
+                        .collect();
+                    // Rows and columns as long as the board come round to themselves.
+                    let mut key = line.clone();
+                    key.sort_unstable();
+                    if !lines.iter().any(|other| {
+                        let mut other = other.clone();
+                        other.sort_unstable();
+                        other == key
+                    }) {
+                        lines.push(line);
+                    }
+                }
+            }
+            lines
+        }
+    }
+}
+
+// Every run of `length` cells in a row, column or diagonal of a width × height grid.
+pub fn grid_lines(width: usize, height: usize, length: usize) -> Vec<Vec<usize>> {
+    let mut lines = Vec::new();
+    let directions: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+    for row in 0..height as isize {
+        for col in 0..width as isize {
+            for (dr, dc) in directions {
+                let end_row = row + dr * (length as isize - 1);
+                let end_col = col + dc * (length as isize - 1);
+                if end_row < 0
+                    || end_row >= height as isize
+                    || end_col < 0
+                    || end_col >= width as isize
+                {
+                    continue;
+                }
+                lines.push(
+                    (0..length as isize)
+                        .map(|step| ((row + dr * step) * width as isize + col + dc * step) as usize)
+                        .collect(),
+                );
+            }
+        }
+    }
+    lines
+}
+
+// A position is playable if it could arise in a real game and isn't finished yet.
+pub fn validate_board(board: &[char], lines: &[Vec<usize>]) -> Result<(), BoardError> {
+    let x_count = board.iter().filter(|&&c| c == PLAYER_X).count();
+    let o_count = board.iter().filter(|&&c| c == PLAYER_O).count();
+    if x_count != o_count && x_count != o_count + 1 {
+        return Err(BoardError::BadCounts);
+    }
+    let result = game_result(board, lines);
+    match result.winner() {
+        Some(winner) => Err(BoardError::AlreadyWon(winner)),
+        None if result == GameResult::Draw => Err(BoardError::Full),
+        None => Ok(()),
+    }
+}
+
+pub fn check_winner(board: &[char], lines: &[Vec<usize>]) -> Option<(char, Vec<usize>)> {
+    lines.iter().find_map(|line| {
+        let mark = board[line[0]];
+        (mark != EMPTY && line.iter().all(|&idx| board[idx] == mark)).then(|| (mark, line.clone()))
+    })
+}
+
+// Only the lines through `last_move` can have been completed by it, so a board
+// that had no winner before needs nothing else checked. Loading arbitrary
+// positions still goes through `check_winner`.
+pub fn check_winner_at(
+    board: &[char],
+    lines: &[Vec<usize>],
+    last_move: usize,
+) -> Option<(char, Vec<usize>)> {
+    let mark = board[last_move];
+    if mark == EMPTY {
+        return None;
+    }
+    lines
+        .iter()
+        .filter(|line| line.contains(&last_move))
+        .find(|line| line.iter().all(|&idx| board[idx] == mark))
+        .map(|line| (mark, line.clone()))
+}
+
+// Drops `mark` into column `col`, landing on the lowest empty cell.
+// Returns the cell it landed in, or None when the column is full.
+pub fn drop_mark(cells: &mut [char], width: usize, col: usize, mark: char) -> Option<usize> {
+    let height = cells.len() / width;
+    let idx = (0..height)
+        .rev()
+        .map(|row| row * width + col)
+        .find(|&idx| cells[idx] == EMPTY)?;
+    cells[idx] = mark;
+    Some(idx)
+}
+
+// ======================================
+// TESTS
+// ======================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn board(cells: &str) -> Vec<char> {
+        parse_board(cells).unwrap()
+    }
+
+    fn small_board(cells: &str) -> [char; 9] {
+        board(cells).try_into().unwrap()
+    }
+
+    #[test]
+    fn parse_board_reads_marks_and_dots() {
+        let (x, o) = (PLAYER_X, PLAYER_O);
+        assert_eq!(
+            board("xO..X...o"),
+            [x, o, EMPTY, EMPTY, x, EMPTY, EMPTY, EMPTY, o]
+        );
+        assert_eq!(board("...."), empty_board(2));
+    }
+
+    #[test]
+    fn parse_board_refuses_other_layouts() {
+        assert_eq!(parse_board(""), Err(ParseError::NotSquare(0)));
+        assert_eq!(parse_board("XO.XO"), Err(ParseError::NotSquare(5)));
+        assert_eq!(parse_board("XO.XO-..."), Err(ParseError::BadCell('-')));
+    }
+
+    #[test]
+    fn validate_board_rejects_illegal_boards() {
+        let lines = win_lines(3, 3, Variant::Standard);
+        for cells in [
+            "XX.......",
+            "O........",
+            "XXXOO....",
+            "XXXOOO...",
+            "XOXXOOOXX",
+        ] {
+            assert!(
+                validate_board(&board(cells), &lines).is_err(),
+                "{} was accepted",
+                cells
+            );
+        }
+        for cells in [".........", "X........", "XO.......", "XO..X...."] {
+            assert!(
+                validate_board(&board(cells), &lines).is_ok(),
+                "{} was rejected",
+                cells
+            );
+        }
+    }
+
+    #[test]
+    fn a_full_board_with_a_line_is_a_win() {
+        let lines = win_lines(3, 3, Variant::Standard);
+        for (cells, expected) in [
+            // X's last mark both fills the board and completes the left column.
+            ("XOXXOOXXO", GameResult::XWins(vec![0, 3, 6])),
+            ("OXXXOOXXO", GameResult::OWins(vec![0, 4, 8])),
+            ("XOXXOOOXX", GameResult::Draw),
+            ("XO.......", GameResult::Ongoing),
+        ] {
+            assert_eq!(game_result(&board(cells), &lines), expected, "{}", cells);
+        }
+    }
+
+    #[test]
+    fn check_winner_at_agrees_with_the_full_scan() {
+        let mut rng = rand::rng();
+        for (size, win_length, variant) in [
+            (3, 3, Variant::Standard),
+            (3, 3, Variant::Torus),
+            (5, 4, Variant::Standard),
+        ] {
+            let lines = win_lines(size, win_length, variant);
+            for _ in 0..300 {
+                let mut board = empty_board(size);
+                let mut current = PLAYER_X;
+                while !game_result(&board, &lines).is_over() {
+                    let free: Vec<usize> =
+                        (0..board.len()).filter(|&i| board[i] == EMPTY).collect();
+                    let idx = free[rng.random_range(0..free.len())];
+                    board[idx] = current;
+                    assert_eq!(
+                        check_winner(&board, &lines),
+                        check_winner_at(&board, &lines, idx),
+                        "{:?} after cell {}",
+                        board.iter().collect::<String>(),
+                        idx + 1
+                    );
+                    current = switch_player(current);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn torus_lines_win_only_under_torus_rules() {
+        let standard = win_lines(3, 3, Variant::Standard);
+        let torus = win_lines(3, 3, Variant::Torus);
+        for cells in [".X...XX..", "..XX...X.", "X....X.X.", ".X.X....X"] {
+            let board = board(cells);
+            assert!(
+                check_winner(&board, &standard).is_none(),
+                "{} wins under standard rules",
+                cells
+            );
+            assert!(
+                check_winner(&board, &torus).is_some(),
+                "{} doesn't win under torus rules",
+                cells
+            );
+        }
+        for cells in ["XXX......", "O..O..O..", "X...X...X", "..O.O.O.."] {
+            assert!(
+                check_winner(&board(cells), &torus).is_some(),
+                "{} should still win under torus rules",
+                cells
+            );
+        }
+    }
+
+    #[test]
+    fn larger_boards_have_the_right_lines() {
+        // n - k + 1 starts per row and per column, (n - k + 1)² per diagonal direction.
+        for (size, length) in [(3, 3), (4, 3), (4, 4), (5, 4), (7, 5)] {
+            let starts = size - length + 1;
+            assert_eq!(
+                win_lines(size, length, Variant::Standard).len(),
+                2 * size * starts + 2 * starts * starts,
+                "{}x{} with {} in a row",
+                size,
+                size,
+                length
+            );
+        }
+        let lines = win_lines(4, 4, Variant::Standard);
+        for cells in ["XXXX............", "O...O...O...O...", "...X..X..X..X..."] {
+            assert!(
+                check_winner(&board(cells), &lines).is_some(),
+                "{} doesn't win on 4x4",
+                cells
+            );
+        }
+        assert!(
+            check_winner(&board("XXX............."), &lines).is_none(),
+            "three in a row won a four-in-a-row game"
+        );
+    }
+
+    #[test]
+    fn validate_move_says_why_a_move_is_refused() {
+        let mut state = GameState::new(3, 3, Variant::Standard);
+        state.apply_move(4).unwrap();
+        assert_eq!(state.validate_move(4), Err(MoveError::Occupied(4)));
+        assert_eq!(state.validate_move(9), Err(MoveError::OutOfRange(9)));
+        assert_eq!(state.validate_move(0), Ok(()));
+        // X takes the middle column; no cell is playable once the game is over.
+        for idx in [0, 1, 3, 7] {
+            state.apply_move(idx).unwrap();
+        }
+        assert_eq!(state.validate_move(8), Err(MoveError::GameOver));
+        // A refused move leaves the board and the turn as they were.
+        let before = (state.board.clone(), state.current_player);
+        assert!(state.apply_move(8).is_err());
+        assert_eq!((state.board.clone(), state.current_player), before);
+    }
+
+    #[test]
+    fn the_last_move_is_tracked_through_undo() {
+        let mut state = GameState::new(3, 3, Variant::Standard);
+        for idx in [4, 0] {
+            state.apply_move(idx).unwrap();
+        }
+        assert_eq!(state.last_move(), Some(0));
+        // Taking O's move back leaves X's centre as the latest again.
+        state.clear_cell(0);
+        assert_eq!(state.last_move(), Some(4));
+        let loaded = GameState::from_board(board("X...O...."), 3, Variant::Standard).unwrap();
+        assert_eq!(
+            loaded.last_move(),
+            None,
+            "a loaded position has no last move"
+        );
+    }
+
+    #[test]
+    fn drop_mark_stacks_from_the_bottom() {
+        let (width, height) = (7, 6);
+        let mut cells = vec![EMPTY; width * height];
+        for row in (0..height).rev() {
+            let mark = if row % 2 == 0 { PLAYER_X } else { PLAYER_O };
+            assert_eq!(drop_mark(&mut cells, width, 3, mark), Some(row * width + 3));
+        }
+        assert_eq!(
+            drop_mark(&mut cells, width, 3, PLAYER_X),
+            None,
+            "the column was full"
+        );
+        assert_eq!(cells.iter().filter(|&&c| c != EMPTY).count(), height);
+    }
+
+    #[test]
+    fn gravity_lines_win_in_every_direction() {
+        let (width, height) = (7, 6);
+        let lines = grid_lines(width, height, 4);
+        let (x, o) = (PLAYER_X, PLAYER_O);
+        // Drops in order; only the last one completes a line.
+        let games: [(&str, &[(char, usize)]); 3] = [
+            (
+                "vertical",
+                &[(x, 0), (o, 1), (x, 0), (o, 1), (x, 0), (o, 1), (x, 0)],
+            ),
+            (
+                "horizontal",
+                &[(x, 0), (o, 0), (x, 1), (o, 1), (x, 2), (o, 2), (x, 3)],
+            ),
+            (
+                "diagonal",
+                &[
+                    (x, 0),
+                    (o, 1),
+                    (x, 1),
+                    (o, 2),
+                    (o, 2),
+                    (x, 2),
+                    (o, 3),
+                    (x, 3),
+                    (o, 3),
+                    (x, 3),
+                ],
+            ),
+        ];
+        for (name, drops) in games {
+            let mut cells = vec![EMPTY; width * height];
+            for (turn, &(mark, col)) in drops.iter().enumerate() {
+                drop_mark(&mut cells, width, col, mark);
+                let winner = check_winner(&cells, &lines).map(|(mark, _)| mark);
+                let expected = (turn == drops.len() - 1).then_some(PLAYER_X);
+                assert_eq!(winner, expected, "{}: after drop {}", name, turn + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn ultimate_moves_send_the_opponent_on() {
+        let mut state = UltimateState::new();
+        state.apply_move(4, 0).unwrap();
+        assert_eq!(state.forced, Some(0));
+        assert_eq!(state.validate_move(3, 2), Err(MoveError::WrongBoard(0)));
+        // X takes the top row of board 1; O is then sent to that decided board,
+        // so may go anywhere still open.
+        let mut state = UltimateState::new();
+        for (board, cell) in [(0, 1), (1, 0), (0, 2), (2, 0), (0, 0)] {
+            state.apply_move(board, cell).unwrap();
+        }
+        assert_eq!(state.meta()[0], PLAYER_X);
+        assert_eq!(state.forced, None);
+        assert!(!state.playable_boards().contains(&0));
+        assert_eq!(state.validate_move(0, 3), Err(MoveError::BoardDecided(0)));
+        assert_eq!(state.validate_move(1, 0), Err(MoveError::Occupied(0)));
+    }
+
+    #[test]
+    fn ultimate_results_come_from_the_big_board() {
+        let mut state = UltimateState::new();
+        // A drawn small board counts for nobody on the big one.
+        state.boards[4] = small_board("XOXXOOOXX");
+        assert_eq!(state.meta()[4], EMPTY);
+        assert!(state.board_result(4).is_over());
+        // The top row of small boards wins the game.
+        let won = small_board("XXX......");
+        state.boards[0..3].fill(won);
+        assert_eq!(state.result(), GameResult::XWins(vec![0, 1, 2]));
+        // Every board decided with no line of them is a draw.
+        let mut full = UltimateState::new();
+        full.boards = [small_board("XOXXOOOXX"); 9];
+        full.boards[0] = won;
+        assert_eq!(full.result(), GameResult::Draw);
+    }
+}
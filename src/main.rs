@@ -1,499 +1,9275 @@
 // ======================================
 // Tic Tac Toe — Crossterm
-// PVP / AI (Easy/Hard), Winner Highlight, Scoreboard
+// PVP / AI (Easy/Hard/Impossible), Winner Highlight, Scoreboard
+// Rules and board state live in lib.rs; this file is the terminal front end.
 // Rust 2024 Edition
 // ======================================
 
+use arboard::Clipboard;
 use crossterm::{
-    cursor::MoveTo,
-    event::{read, Event, KeyCode},
+    cursor::{MoveTo, RestorePosition, SavePosition},
+    event::{
+        poll, read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent,
+        KeyEventKind, KeyModifiers, MouseButton, MouseEventKind,
+    },
     execute, queue,
-    style::{Color, Print, ResetColor, SetForegroundColor},
-    terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
+    style::{
+        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+    },
+    terminal::{disable_raw_mode, enable_raw_mode, size, Clear, ClearType},
 };
+use rand::rngs::StdRng;
 use rand::seq::IteratorRandom;
-use std::io::{stdout, Result, Stdout, Write};
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{stdout, Error, ErrorKind, IsTerminal, Result, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tic_tac_toe::{
+    board_size, check_winner, check_winner_at, drop_mark, empty_board, game_result, grid_lines,
+    is_draw, parse_board, player_to_move, switch_player, validate_board, win_lines, BoardError,
+    GameResult, GameState, MoveError, UltimateState, Variant, EMPTY, PLAYER_O, PLAYER_X,
+};
 
 // ======================================
 // CONSTANTS & TYPES
 // ======================================
 
-const PLAYER_X: char = 'X';
-const PLAYER_O: char = 'O';
-const EMPTY_CELLS: [char; 9] = ['1', '2', '3', '4', '5', '6', '7', '8', '9'];
+// Grid geometry: every board coordinate is derived from these and the board
+// size, through `cell_size`.
+const CELL_WIDTH: u16 = 5; // columns per cell, mark centred
+const CELL_HEIGHT: u16 = 1; // rows per cell, mark centred
+const BIG_CELL_WIDTH: u16 = 9; // --big-board: a 7 × 5 glyph with a column either side
+const BIG_CELL_HEIGHT: u16 = 5;
+const GRID_TOP: u16 = 3;
+const PANEL_GAP: u16 = 11; // between the grid and the info panel
+const PANEL_WIDTH: u16 = 44;
+// Rows kept free under the notes line for the quit summary or a copied board.
+const NOTES_ROOM: u16 = 6;
+const MAX_BOARD_SIZE: usize = 7;
+// Largest board the pruned search behind Impossible finishes on in a few seconds.
+const MAX_IMPOSSIBLE_SIZE: usize = 4;
+// Deepest lookahead offered for the Depth level, and the most on boards past
+// 4×4, where every extra move multiplies the positions searched.
+const MAX_SEARCH_DEPTH: usize = 6;
+const MAX_BIG_BOARD_DEPTH: usize = 3;
+// What `evaluate` gives a won position before the moves it took to get there
+// come off (a loss scores the negative). Taking the depth off makes a quicker
+// win score higher, so the search goes for a win in 2 over a win in 4 and puts
+// off a loss it can't avoid. It sits well clear of anything the line count for
+// an unfinished position can reach, so no such position looks like a win.
+const WIN_SCORE: i32 = 100_000;
+const USAGE: &str = "\
+Usage: TicTacToe [options]
+
+Game setup (~/.tictactoe.toml can set mode, difficulty, first, theme, size,
+dim-numbers, lang and csv; flags override it and the prompts ask for anything
+left out):
+  --mode friend|ai          skip the welcome screen and mode menu
+  --difficulty <level>      easy, safe, medium, hard, depth-<n>, impossible or
+                            adaptive
+                            (implies --mode ai); depth-<n> looks n moves ahead (1-6,
+                            1-3 past 4×4); impossible needs a board of 4×4 or smaller
+  --first player|computer   who moves first against the AI (implies --mode ai)
+  --names <x>,<o>           the two players' names, e.g. Ann,Bo (implies --mode friend)
+  --tournament <names>      winner-stays-on games for 3+ players, e.g. Ann,Bo,Cy
+  --first-to <n>            wins that make the tournament champion (3)
+  --time-attack <s>         each player gets s seconds for the whole game
+
+Board:
+  --size <n>                n×n board, 3 to 7
+  --connect <k>             marks in a row needed to win
+  --torus                   lines wrap around the edges
+  --gravity <W>x<H>         Connect-Four style drop game
+  --ultimate                ultimate tic-tac-toe: nine small boards in a big one
+  --start <cells>           begin from a position, e.g. XO.XO..X. (. = empty)
+
+Display and help:
+  --theme default|classic|monochrome|high-contrast
+  --marks <xo>              two characters to draw instead of X and O, e.g. #@
+  --grid ascii|box          grid drawn with | - + (the default) or │ ─ ┼
+  --big-board               marks five rows tall in block characters, for streaming
+  --dim-numbers             draw the numbers of free cells in faint grey
+  --no-color, --compact, --move-numbers, --announce, --show-threats, --quiet
+  --feedback beep|flash|silent  refused moves ring, flash red, or do neither
+  --no-confirm              leave after a game without a second question
+  --show-nodes, --explain, --analysis, --early-end, --coin-flip
+  --handicap [k], --undo-limit <n>, --log <file>, --bind action=key
+  --csv <file>              where each game is added as a CSV row (games.csv)
+  --think-time <ms>         show the computer thinking at least this long (600)
+  --keys arrows|wasd        cursor keys: arrows + Enter, or w/a/s/d + e
+  --wrap-cursor             the cursor leaves one edge and comes back at the other
+  --lang en|es              menus, prompts and banners in English or Spanish
+  --replay <file>           step through the last game in a record file
+  --seed <n>                seed every random choice, so a session can be replayed
+  --json                    no screen: cell numbers on stdin, one JSON line per game
+  --self-test, --help";
+
+// Terminal bell, rung on a rejected move and on a win (see --feedback).
+const BELL: char = '\x07';
+
+// How long a refused cell, or the grid, stays red with --feedback flash.
+const FLASH_TIME: Duration = Duration::from_millis(120);
+// First screen row of the --gravity grid, and of the --ultimate boards.
+const GRAVITY_TOP: u16 = 3;
+// Screen rows of the --ultimate boards (three rows of three small boards with
+// a line between each), and the column of their first cells.
+const ULTIMATE_ROWS: usize = 11;
+const ULTIMATE_LEFT: u16 = 2;
+// --big-board marks, drawn in block characters.
+const BIG_X: [&str; 5] = ["██   ██", " ██ ██ ", "  ███  ", " ██ ██ ", "██   ██"];
+const BIG_O: [&str; 5] = [" ▄███▄ ", "██   ██", "██   ██", "██   ██", " ▀███▀ "];
+// Set from --big-board before the first frame and never changed after, so the
+// geometry functions can read it without every caller passing the options.
+static BIG_CELLS: AtomicBool = AtomicBool::new(false);
+const SUPERSCRIPT_DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
 
 #[derive(Clone, Copy, PartialEq)]
 enum Difficulty {
     Easy,
+    // Random like Easy, but never a move that lets the opponent win straight
+    // after (see `get_ai_move_safe_random`).
+    Safe,
+    Medium,
     Hard,
+    // Searches this many moves ahead and judges the position it reaches there,
+    // for a strength between Hard and Impossible (see `get_ai_move_minimax_depth`).
+    Depth(usize),
+    Impossible,
+    // Stands for one of the levels above, picked before each game from how the
+    // human has done so far (see `pick_adaptive_difficulty`).
+    Adaptive,
 }
 
+const DIFFICULTIES: [Difficulty; 4] = [
+    Difficulty::Easy,
+    Difficulty::Medium,
+    Difficulty::Hard,
+    Difficulty::Impossible,
+];
+
 #[derive(Clone, Copy)]
 enum GameMode {
     Friend,
-    AI(Difficulty, bool), // (difficulty, player_first)
+    AI(Difficulty, bool, usize), // (difficulty, player_first, turns the computer passes)
+    AIvsAI(Difficulty, Difficulty), // (X's difficulty, O's difficulty), nobody at the keys
 }
 
-// ======================================
-// MAIN
-// ======================================
+// Keys for the actions that can be rebound; cell numbers always place a mark.
+#[derive(Clone, Copy)]
+struct Keymap {
+    copy_board: char,
+    replay: char,
+    read_board: char,
+    undo: char,
+    redo: char,
+    hint: char,
+    quit: char,
+    // Ending a game between two players early.
+    resign: char,
+    offer_draw: char,
+    // Cursor movement and placing on the cursor; Space places as well.
+    up: KeyCode,
+    down: KeyCode,
+    left: KeyCode,
+    right: KeyCode,
+    place: KeyCode,
+}
 
-fn main() -> Result<()> {
-    enable_raw_mode()?;
-    let mut stdout = stdout();
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            copy_board: 'c',
+            replay: 'r',
+            read_board: 'b',
+            undo: 'u',
+            redo: 'y',
+            hint: 'h',
+            quit: 'q',
+            resign: 'r',
+            offer_draw: 'o',
+            up: KeyCode::Up,
+            down: KeyCode::Down,
+            left: KeyCode::Left,
+            right: KeyCode::Right,
+            place: KeyCode::Enter,
+        }
+    }
+}
 
-    let mut score_player_x = 0;
-    let mut score_player_o = 0;
-    let mut score_draws = 0;
+impl Keymap {
+    // Whether two actions share a key, so that one of them could never be
+    // used. Keys are only compared with those read at the same time: the ones
+    // during a game, and the ones on the play-again prompt, whose y/n/s/a are
+    // fixed.
+    fn has_clash(&self) -> bool {
+        let in_game = [
+            KeyCode::Char(self.undo),
+            KeyCode::Char(self.redo),
+            KeyCode::Char(self.hint),
+            KeyCode::Char(self.quit),
+            KeyCode::Char(self.resign),
+            KeyCode::Char(self.offer_draw),
+            KeyCode::Char(self.read_board),
+            self.up,
+            self.down,
+            self.left,
+            self.right,
+            self.place,
+        ];
+        let after_game = [self.copy_board, self.replay, 'y', 'n', 's', 'a'];
+        let after_game = after_game.map(|key| key.to_ascii_lowercase());
+        has_repeat(&in_game) || has_repeat(&after_game)
+    }
 
-    loop {
-        let mut board = EMPTY_CELLS;
-        let mut current_player = PLAYER_X;
+    // --keys: the cursor keys of a built-in layout, other bindings kept.
+    fn with_movement(self, layout: &str) -> Option<Keymap> {
+        let [up, left, down, right, place] = match layout {
+            "arrows" => [
+                KeyCode::Up,
+                KeyCode::Left,
+                KeyCode::Down,
+                KeyCode::Right,
+                KeyCode::Enter,
+            ],
+            "wasd" => ['w', 'a', 's', 'd', 'e'].map(KeyCode::Char),
+            _ => return None,
+        };
+        let keymap = Keymap {
+            up,
+            down,
+            left,
+            right,
+            place,
+            ..self
+        };
+        (!keymap.has_clash()).then_some(keymap)
+    }
+}
 
-        show_welcome_screen(&mut stdout)?;
-        let game_mode = ask_game_mode(&mut stdout)?;
+// Whether any key appears twice.
+fn has_repeat<T: PartialEq>(keys: &[T]) -> bool {
+    keys.iter()
+        .enumerate()
+        .any(|(i, key)| keys[i + 1..].contains(key))
+}
 
-        // Game loop
-        loop {
-            draw_board(&board, &mut stdout)?;
-            print_turn_hint(&mut stdout, game_mode, current_player)?;
+// What a board redraw picks out besides the marks themselves.
+#[derive(Clone, Copy, Default)]
+struct Highlight<'a> {
+    cursor: Option<usize>,             // the arrow-key cursor, during play
+    line: Option<&'a [usize]>,         // the winning line, once the game is over
+    threats: &'a [(char, Vec<usize>)], // unfinished two-in-a-rows (--show-threats)
+    last: Option<usize>,               // the move just played, until the next one
+}
 
-            let pos = if is_human_turn(game_mode, current_player) {
-                get_human_move(&mut stdout, &board, current_player)?
-            } else {
-                match game_mode {
-                    GameMode::AI(difficulty, player_first) => {
-                        let _computer_mark = if player_first { PLAYER_O } else { PLAYER_X };
-                        match difficulty {
-                            Difficulty::Easy => get_ai_move_random(&board),
-                            Difficulty::Hard => get_ai_move_blocking(&board),
-                        }
-                    }
-                    GameMode::Friend => unreachable!(),
-                }
-            };
+impl Highlight<'_> {
+    fn look(&self, idx: usize) -> CellLook {
+        CellLook {
+            cursor: self.cursor == Some(idx),
+            winning: self.line.is_some_and(|line| line.contains(&idx)),
+            last: self.last == Some(idx),
+            threat: self
+                .threats
+                .iter()
+                .find(|(_, cells)| cells.contains(&idx))
+                .map(|&(mark, _)| mark),
+            refused: false,
+        }
+    }
+}
 
-            board[pos] = current_player;
+// How a single cell is drawn.
+#[derive(Clone, Copy, Default)]
+struct CellLook {
+    cursor: bool,
+    winning: bool,
+    last: bool,
+    threat: Option<char>, // part of an unfinished two-in-a-row by this side
+    refused: bool,        // a move just tried here was turned down (--feedback flash)
+}
 
-            if let Some((winner, line)) = check_winner(&board) {
-                draw_board_highlight(&board, &mut stdout, &line)?;
-                print_winner(&mut stdout, winner)?;
-                if winner == PLAYER_X {
-                    score_player_x += 1;
-                } else {
-                    score_player_o += 1;
-                }
-                break;
-            }
+// A key pressed on a human turn.
+enum Turn {
+    Place(usize),
+    Undo,
+    Redo,
+    TimedOut,
+    FlagFell, // the player's whole time for the game ran out (--time-attack)
+    Resized,  // the terminal changed size, so the screen needs drawing again
+    Quit,     // leave the game unfinished
+    Resign,
+    OfferDraw, // the other player is asked to accept
+}
 
-            if is_draw(&board) {
-                draw_board(&board, &mut stdout)?;
-                print_draw(&mut stdout)?;
-                score_draws += 1;
-                break;
-            }
+// How long a human turn may take: the per-move limit, and with --time-attack
+// what's left of each side's time for the whole game.
+#[derive(Default)]
+struct Timer {
+    deadline: Option<Instant>, // end of the per-move limit, set when a turn starts
+    // X's and O's time left, run down only on their own turns; None for the
+    // computer, which has all the time it wants.
+    clocks: Option<[Option<Duration>; 2]>,
+}
 
-            current_player = switch_player(current_player);
-        }
+impl Timer {
+    fn clock(&self, mark: char) -> Option<Duration> {
+        self.clocks
+            .and_then(|clocks| clocks[usize::from(mark == PLAYER_O)])
+    }
 
-        print_scoreboard(&mut stdout, score_player_x, score_player_o, score_draws)?;
-        if !ask_replay(&mut stdout)? {
-            break;
+    // Takes the time since `since` off the mover's clock and moves `since` on
+    // to now. True once the clock is down to zero.
+    fn run_down(&mut self, mark: char, since: &mut Instant) -> bool {
+        let now = Instant::now();
+        let spent = now - *since;
+        *since = now;
+        match self
+            .clocks
+            .as_mut()
+            .and_then(|clocks| clocks[usize::from(mark == PLAYER_O)].as_mut())
+        {
+            Some(left) => {
+                *left = left.saturating_sub(spent);
+                left.is_zero()
+            }
+            None => false,
         }
     }
 
-    disable_raw_mode()?;
-    Ok(())
+    // Until the move limit or the mover's clock runs out, whichever is sooner.
+    fn time_left(&self, mark: char) -> Option<Duration> {
+        let move_left = self
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        move_left.into_iter().chain(self.clock(mark)).min()
+    }
 }
 
-// ======================================
-// GAME FLOW
-// ======================================
-
-fn show_welcome_screen(stdout: &mut Stdout) -> Result<()> {
-    execute!(stdout, Clear(ClearType::All))?;
-    queue!(stdout, MoveTo(8, 0), Print("==== Welcome to Tic Tac Toe ===="))?;
-    queue!(stdout, MoveTo(8, 2), Print("Controls: press number keys 1–9 to place your mark."))?;
-    queue!(stdout, MoveTo(8, 3), Print("Win by getting 3 in a row (rows, columns, diagonals)."))?;
-    queue!(stdout, MoveTo(8, 5), Print("Press any key to continue..."))?;
-    stdout.flush()?;
-    read()?; // wait any key
-    Ok(())
+// Answer to the "result is decided" prompt.
+enum EarlyEnd {
+    Stop,
+    Continue,
+    AutoPlay,
 }
 
-fn ask_game_mode(stdout: &mut Stdout) -> Result<GameMode> {
-    execute!(stdout, Clear(ClearType::All))?;
-    queue!(stdout, MoveTo(0, 0), Print("Tic Tac Toe\n\n"))?;
-    queue!(stdout, MoveTo(0, 2), Print("Play with a friend (f) or AI (a)? "))?;
-    stdout.flush()?;
+// What the player picked on the post-game menu.
+enum PostGame {
+    Again,
+    Quit,
+    Replay,
+    Redraw,    // the screen was resized or covered by the help screen
+    SwapSides, // against the AI: play again with the other side moving first
+    Accuracy,  // between two players: how many of each one's moves were best
+}
 
-    loop {
-        if let Event::Key(event) = read()? {
-            if let KeyCode::Char(c) = event.code {
-                match c {
-                    'f' | 'F' => return Ok(GameMode::Friend),
-                    'a' | 'A' => {
-                        let difficulty = ask_difficulty(stdout)?;
-                        let player_first = ask_first_player(stdout)?;
-                        return Ok(GameMode::AI(difficulty, player_first));
-                    }
-                    _ => {
-                        queue!(stdout, Print("\nInvalid input. Type f or a: "))?;
-                        stdout.flush()?;
-                    }
-                }
-            }
-        }
-    }
+// Colours for everything drawn on screen, picked with --theme.
+#[derive(Clone, Copy)]
+struct Theme {
+    x: Color,
+    o: Color,
+    free: Color,      // numbers on empty cells
+    highlight: Color, // the winning line
+    grid: Color,
+    hint: Color,     // prompts, notices and banners
+    computer: Color, // the computer's turn and its thinking animation
+    muted: Color,    // move numbers and AI notes
+    alert: Color,    // errors and the last seconds of a countdown
+    cursor: Color,   // background of the arrow-key cursor
+    x_tint: Color,   // background of X's near-complete lines (--show-threats)
+    o_tint: Color,
+    // Plain text instead of emoji, and brackets where colour alone would mark a
+    // cell (the cursor, the winning line).
+    ascii: bool,
+    marks: [char; 2], // what X and O are shown as, --marks
 }
 
-fn ask_difficulty(stdout: &mut Stdout) -> Result<Difficulty> {
-    queue!(stdout, MoveTo(0, 4), Print("Select difficulty: (e)asy or (h)ard: "))?;
-    stdout.flush()?;
-    loop {
-        if let Event::Key(event) = read()? {
-            if let KeyCode::Char(c) = event.code {
-                match c {
-                    'e' | 'E' => return Ok(Difficulty::Easy),
-                    'h' | 'H' => return Ok(Difficulty::Hard),
-                    _ => {
-                        queue!(stdout, Print("\nInvalid input. Type e or h: "))?;
-                        stdout.flush()?;
-                    }
-                }
-            }
+impl Theme {
+    // Okabe-Ito colours from the 256-colour palette: orange X and blue O stay
+    // apart under red-green colour blindness, unlike the classic red and blue.
+    const SAFE: Theme = Theme {
+        x: Color::AnsiValue(208),
+        o: Color::AnsiValue(33),
+        free: Color::White,
+        highlight: Color::AnsiValue(36), // bluish green
+        grid: Color::Reset,
+        hint: Color::AnsiValue(227),
+        computer: Color::AnsiValue(175),
+        muted: Color::DarkGrey,
+        alert: Color::AnsiValue(166),
+        cursor: Color::DarkGrey,
+        x_tint: Color::AnsiValue(94),
+        o_tint: Color::AnsiValue(24),
+        ascii: false,
+        marks: [PLAYER_X, PLAYER_O],
+    };
+
+    const CLASSIC: Theme = Theme {
+        x: Color::Red,
+        o: Color::Blue,
+        free: Color::White,
+        highlight: Color::Green,
+        grid: Color::Reset,
+        hint: Color::Yellow,
+        computer: Color::Magenta,
+        muted: Color::DarkGrey,
+        alert: Color::Red,
+        cursor: Color::DarkGrey,
+        x_tint: Color::DarkRed,
+        o_tint: Color::DarkBlue,
+        ascii: false,
+        marks: [PLAYER_X, PLAYER_O],
+    };
+
+    // Shades of grey only; X and O are told apart by their glyphs.
+    const MONOCHROME: Theme = Theme {
+        x: Color::White,
+        o: Color::Grey,
+        free: Color::DarkGrey,
+        highlight: Color::White,
+        grid: Color::DarkGrey,
+        hint: Color::White,
+        computer: Color::Grey,
+        muted: Color::DarkGrey,
+        alert: Color::White,
+        cursor: Color::DarkGrey,
+        x_tint: Color::DarkGrey,
+        o_tint: Color::DarkGrey,
+        ascii: false,
+        marks: [PLAYER_X, PLAYER_O],
+    };
+
+    // Bright colours that stay apart for red/blue colour blindness.
+    const HIGH_CONTRAST: Theme = Theme {
+        x: Color::Yellow,
+        o: Color::Cyan,
+        free: Color::Grey,
+        highlight: Color::Green,
+        grid: Color::White,
+        hint: Color::White,
+        computer: Color::Magenta,
+        muted: Color::Grey,
+        alert: Color::Magenta,
+        cursor: Color::DarkMagenta,
+        x_tint: Color::DarkYellow,
+        o_tint: Color::DarkCyan,
+        ascii: false,
+        marks: [PLAYER_X, PLAYER_O],
+    };
+
+    // --no-color: the terminal's own colours and ASCII-only cues.
+    const NO_COLOR: Theme = Theme {
+        x: Color::Reset,
+        o: Color::Reset,
+        free: Color::Reset,
+        highlight: Color::Reset,
+        grid: Color::Reset,
+        hint: Color::Reset,
+        computer: Color::Reset,
+        muted: Color::Reset,
+        alert: Color::Reset,
+        cursor: Color::Reset,
+        x_tint: Color::Reset,
+        o_tint: Color::Reset,
+        ascii: true,
+        marks: [PLAYER_X, PLAYER_O],
+    };
+
+    fn named(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::SAFE),
+            "classic" => Some(Theme::CLASSIC),
+            "monochrome" => Some(Theme::MONOCHROME),
+            "high-contrast" => Some(Theme::HIGH_CONTRAST),
+            _ => None,
         }
     }
-}
 
-fn ask_first_player(stdout: &mut Stdout) -> Result<bool> {
-    queue!(
-        stdout,
-        MoveTo(0, 6),
-        Print("Who goes first? (p = player, c = computer): ")
-    )?;
-    stdout.flush()?;
-    loop {
-        if let Event::Key(event) = read()? {
-            if let KeyCode::Char(c) = event.code {
-                match c {
-                    'p' | 'P' => return Ok(true),
-                    'c' | 'C' => return Ok(false),
-                    _ => {
-                        queue!(stdout, Print("\nInvalid input. Type p or c: "))?;
-                        stdout.flush()?;
-                    }
-                }
-            }
+    fn mark(&self, cell: char) -> Color {
+        match cell {
+            PLAYER_X => self.x,
+            PLAYER_O => self.o,
+            _ => self.free,
         }
     }
-}
 
-fn is_human_turn(game_mode: GameMode, current: char) -> bool {
-    match game_mode {
-        GameMode::Friend => true,
-        GameMode::AI(_difficulty, player_first) => {
-            if player_first {
-                current == PLAYER_X
-            } else {
-                current == PLAYER_O
-            }
+    // The character a side is drawn with; the board itself always holds X and O.
+    fn glyph(&self, player: char) -> char {
+        match player {
+            PLAYER_X => self.marks[0],
+            PLAYER_O => self.marks[1],
+            other => other,
         }
     }
 }
 
-fn switch_player(current: char) -> char {
-    if current == PLAYER_X {
-        PLAYER_O
-    } else {
-        PLAYER_X
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::SAFE
     }
 }
 
-fn is_draw(board: &[char; 9]) -> bool {
-    board.iter().all(|&c| c == PLAYER_X || c == PLAYER_O)
+// Characters the grid is drawn with, picked with --grid. ASCII is the default
+// since box drawing needs a terminal font that has it.
+#[derive(Clone, Copy)]
+struct GridStyle {
+    vertical: char,
+    horizontal: char,
+    cross: char,
+    bottom: [char; 3], // left corner, tee and right corner under a --gravity grid
 }
 
-fn ask_replay(stdout: &mut Stdout) -> Result<bool> {
-    queue!(stdout, Print("\nPlay again? (y/n): "))?;
-    stdout.flush()?;
-    loop {
-        if let Event::Key(event) = read()? {
-            if let KeyCode::Char(c) = event.code {
-                match c {
-                    'y' | 'Y' => return Ok(true),
-                    'n' | 'N' => return Ok(false),
-                    _ => {
-                        queue!(stdout, Print("\nInvalid input. Type y or n: "))?;
-                        stdout.flush()?;
-                    }
-                }
-            }
+impl GridStyle {
+    const ASCII: GridStyle = GridStyle {
+        vertical: '|',
+        horizontal: '-',
+        cross: '+',
+        bottom: ['+', '+', '+'],
+    };
+
+    const BOX: GridStyle = GridStyle {
+        vertical: '│',
+        horizontal: '─',
+        cross: '┼',
+        bottom: ['└', '┴', '┘'],
+    };
+
+    fn named(name: &str) -> Option<GridStyle> {
+        match name {
+            "ascii" => Some(GridStyle::ASCII),
+            "box" => Some(GridStyle::BOX),
+            _ => None,
         }
     }
+
+    // The line between two rows of `cells` cells each `width` wide.
+    fn rule(&self, cells: usize, width: usize) -> String {
+        let segment = self.horizontal.to_string().repeat(width);
+        vec![segment; cells].join(&self.cross.to_string())
+    }
 }
 
-// ======================================
-// DRAWING
-// ======================================
+impl Default for GridStyle {
+    fn default() -> Self {
+        GridStyle::ASCII
+    }
+}
+
+// Which menu choice --mode makes.
+#[derive(Clone, Copy, PartialEq)]
+enum ModeFlag {
+    Friend,
+    Ai,
+}
 
-fn draw_board(board: &[char; 9], stdout: &mut Stdout) -> Result<()> {
-    execute!(stdout, Clear(ClearType::All))?;
-    queue!(stdout, MoveTo(0, 0), Print("Tic Tac Toe\n"))?;
-    queue!(stdout, MoveTo(0, 1), Print("========================\n"))?;
+// What a refused move and a win make happen besides the message, --feedback.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Feedback {
+    #[default]
+    Beep, // the terminal bell
+    Flash,  // the tried cell, or the grid lines, turn red for a moment
+    Silent, // --quiet
+}
 
-    for row in 0..3 {
-        if row > 0 {
-            queue!(stdout, MoveTo(0, (row as u16) * 2 + 2), Print("---+---+---"))?;
-        }
-        let y = (row as u16) * 2 + 3;
-        for col in 0..3 {
-            let idx = row * 3 + col;
-            let x = match col {
-                0 => 1,
-                1 => 5,
-                _ => 9,
-            } as u16;
-
-            let color = match board[idx] {
-                PLAYER_X => Color::Red,
-                PLAYER_O => Color::Blue,
-                _ => Color::White,
-            };
+// Defaults from ~/.tictactoe.toml, e.g.
+//
+//     mode = "ai"
+//     difficulty = "hard"
+//     first = "computer"
+//     theme = "classic"
+//     size = 4
+//     dim-numbers = true
+//     lang = "es"
+//     csv = "stats/games.csv"
+//
+// Each key takes the values of the flag of the same name, which overrides it.
+#[derive(Clone, Default)]
+struct Config {
+    mode: Option<ModeFlag>,
+    difficulty: Option<Difficulty>,
+    player_first: Option<bool>,
+    theme: Option<Theme>,
+    size: Option<usize>,
+    dim_numbers: bool,
+    language: Option<Language>,
+    csv_path: Option<String>,
+}
 
-            queue!(
-                stdout,
-                MoveTo(x, y),
-                SetForegroundColor(color),
-                Print(board[idx]),
-                ResetColor
-            )?;
+// Settings chosen on the command line.
+#[derive(Default)]
+struct Options {
+    keymap: Keymap,                  // --bind action=key
+    show_move_numbers: bool,         // --move-numbers
+    variant: Variant,                // --torus
+    early_end: bool,                 // --early-end
+    self_test: bool,                 // --self-test
+    log_path: Option<String>,        // --log <file>
+    csv_path: Option<String>,        // --csv <file>, games.csv when absent
+    replay_path: Option<String>,     // --replay <file>, a game record to step through
+    json: bool,                      // --json
+    show_nodes: bool,                // --show-nodes
+    explain: bool,                   // --explain
+    coin_flip: bool,                 // --coin-flip
+    show_threats: bool,              // --show-threats
+    analysis: bool,                  // --analysis
+    handicap: usize,                 // --handicap [k]
+    think_ms: Option<u64>,           // --think-time <ms>, shortest "thinking" animation
+    time_attack: Option<u64>,        // --time-attack <s>, each side's time for a game
+    announce: bool,                  // --announce
+    undo_limit: Option<usize>,       // --undo-limit <n>, unlimited when absent
+    compact: bool,                   // --compact
+    feedback: Feedback,              // --feedback beep|flash|silent, --quiet
+    quit_at_once: bool,              // --no-confirm, 'n' after a game quits without asking
+    gravity: Option<(usize, usize)>, // --gravity <W>x<H>, columns × rows
+    ultimate: bool,                  // --ultimate
+    connect: Option<usize>,          // --connect <k>, line length to win
+    size: Option<usize>,             // --size <n>, n × n board
+    start: Option<Vec<char>>,        // --start <cells>, position every game begins from
+    theme: Theme,                    // --theme <name>, --no-color
+    grid: GridStyle,                 // --grid ascii|box
+    big_board: bool,                 // --big-board, block-character marks
+    dim_numbers: bool,               // --dim-numbers, free cells' numbers in a faint grey
+    wrap_cursor: bool,               // --wrap-cursor, no hard edges for the cursor
+    language: Option<Language>,      // --lang en|es
+    seed: Option<u64>,               // --seed <n>, random when absent
+    names: Option<[String; 2]>,      // --names <x>,<o>
+    tournament: Option<Vec<String>>, // --tournament <names>, comma-separated
+    first_to: Option<u32>,           // --first-to <n>, wins that make a tournament champion
+    mode: Option<ModeFlag>,          // --mode friend|ai
+    difficulty: Option<Difficulty>,  // --difficulty <level>
+    player_first: Option<bool>,      // --first player|computer
+    show_help: bool,                 // --help
+}
 
-            if col < 2 {
-                let bar_x = match col {
-                    0 => 3,
-                    _ => 7,
-                } as u16;
-                queue!(stdout, MoveTo(bar_x, y), Print("|"))?;
-            }
-        }
+impl Options {
+    fn board_size(&self) -> usize {
+        self.size.unwrap_or(3)
     }
 
-    stdout.flush()?;
-    Ok(())
-}
+    // Full rows on small boards; four in a row once the board is bigger than that.
+    fn win_length(&self) -> usize {
+        self.win_length_on(self.board_size())
+    }
 
-fn draw_board_highlight(board: &[char; 9], stdout: &mut Stdout, line: &[usize; 3]) -> Result<()> {
-    execute!(stdout, Clear(ClearType::All))?;
-    queue!(stdout, MoveTo(0, 0), Print("Tic Tac Toe\n"))?;
-    queue!(stdout, MoveTo(0, 1), Print("========================\n"))?;
+    // The same for a board of another size, such as a replayed game's.
+    fn win_length_on(&self, size: usize) -> usize {
+        self.connect.unwrap_or(size.min(4)).clamp(2, size)
+    }
 
-    for row in 0..3 {
-        if row > 0 {
-            queue!(stdout, MoveTo(0, (row as u16) * 2 + 2), Print("---+---+---"))?;
-        }
-        let y = (row as u16) * 2 + 3;
-        for col in 0..3 {
-            let idx = row * 3 + col;
-            let x = match col {
-                0 => 1,
-                1 => 5,
-                _ => 9,
-            } as u16;
-
-            let color = if line.contains(&idx) {
-                Color::Green
-            } else {
-                match board[idx] {
-                    PLAYER_X => Color::Red,
-                    PLAYER_O => Color::Blue,
-                    _ => Color::White,
-                }
-            };
+    // In the directory the game was started from unless --csv says otherwise.
+    fn csv_path(&self) -> &str {
+        self.csv_path.as_deref().unwrap_or("games.csv")
+    }
 
-            queue!(
-                stdout,
-                MoveTo(x, y),
-                SetForegroundColor(color),
-                Print(board[idx]),
-                ResetColor
-            )?;
+    fn first_to(&self) -> u32 {
+        self.first_to.unwrap_or(3)
+    }
 
-            if col < 2 {
-                let bar_x = match col {
-                    0 => 3,
-                    _ => 7,
-                } as u16;
-                queue!(stdout, MoveTo(bar_x, y), Print("|"))?;
-            }
-        }
+    fn names(&self) -> Option<(&str, &str)> {
+        self.names.as_ref().map(|[x, o]| (x.as_str(), o.as_str()))
     }
 
-    stdout.flush()?;
-    Ok(())
+    // Even an instant reply is held back this long so the move can be seen coming.
+    fn think_time(&self) -> Duration {
+        Duration::from_millis(self.think_ms.unwrap_or(600))
+    }
 }
 
-fn print_turn_hint(stdout: &mut Stdout, mode: GameMode, current: char) -> Result<()> {
-    let hint = match mode {
-        GameMode::Friend => format!("Player {}, enter position (1-9): ", current),
-        GameMode::AI(_d, player_first) => {
-            if is_human_turn(mode, current) {
-                let you_mark = if player_first { PLAYER_X } else { PLAYER_O };
-                format!("Your turn ({}). Enter position (1-9): ", you_mark)
-            } else {
-                let comp_mark = if player_first { PLAYER_O } else { PLAYER_X };
-                format!("Computer's turn ({})...", comp_mark)
-            }
+// Which rule of the AI produced a move, for --explain, which words it with
+// the entry of `Strings::reasons` in the same place.
+#[derive(Clone, Copy, PartialEq)]
+enum MoveReason {
+    Win,
+    Block,
+    Random,
+    Search,
+    Fork,
+    DoubleThreat,
+    Force,
+    Position,
+    Book,
+    Lookahead,
+    Safe,
+}
+
+// The computer's chosen cell plus what it cost and why it was chosen.
+#[derive(Clone, Copy)]
+struct AiMove {
+    pos: usize,
+    nodes: u64,
+    reason: MoveReason,
+}
+
+// One side of a two-player game as it is shown: the name from --names or the
+// tournament, if any, and the mark as drawn in its colour.
+struct Player {
+    name: Option<String>,
+    mark: char,
+    color: Color,
+}
+
+impl Player {
+    // "Alice (X)" in hints and banners, or "Player X" without a name.
+    fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} ({})", name, self.mark),
+            None => fill(text().player, &[&self.mark]),
         }
-    };
+    }
 
-    let color = if is_human_turn(mode, current) {
-        Color::Yellow
-    } else {
-        Color::Magenta
-    };
+    // "Alice(X)" on the scoreboard, or just "X".
+    fn tag(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{}({})", name, self.mark),
+            None => self.mark.to_string(),
+        }
+    }
+}
 
-    queue!(
-        stdout,
-        MoveTo(0, 10),
-        SetForegroundColor(color),
-        Print(hint),
-        ResetColor
-    )?;
-    stdout.flush()?;
-    Ok(())
+// The X and O players, in that order.
+fn seat_players(names: Option<(&str, &str)>, theme: &Theme) -> [Player; 2] {
+    [
+        (PLAYER_X, names.map(|(x, _)| x)),
+        (PLAYER_O, names.map(|(_, o)| o)),
+    ]
+    .map(|(mark, name)| Player {
+        name: name.map(str::to_string),
+        mark: theme.glyph(mark),
+        color: theme.mark(mark),
+    })
 }
 
-fn print_winner(stdout: &mut Stdout, winner: char) -> Result<()> {
-    let color = if winner == PLAYER_X { Color::Red } else { Color::Blue };
-    queue!(
-        stdout,
-        MoveTo(0, 12),
-        SetForegroundColor(color),
-        Print(format!("\nPlayer {} wins! 🎉\n", winner)),
-        ResetColor
-    )?;
-    stdout.flush()?;
-    Ok(())
+fn seat(players: &[Player; 2], mark: char) -> &Player {
+    &players[usize::from(mark == PLAYER_O)]
 }
 
-fn print_draw(stdout: &mut Stdout) -> Result<()> {
-    queue!(
-        stdout,
-        MoveTo(0, 12),
-        SetForegroundColor(Color::Yellow),
-        Print("\nIt's a draw! 🤝\n"),
-        ResetColor
-    )?;
-    stdout.flush()?;
-    Ok(())
+// One finished game, kept for the end-of-session summary.
+#[derive(Clone, Copy)]
+struct GameRecord {
+    mode: GameMode,
+    winner: Option<char>,
+    moves: usize,
 }
 
-fn print_scoreboard(stdout: &mut Stdout, px: i32, po: i32, draws: i32) -> Result<()> {
-    queue!(
-        stdout,
-        SetForegroundColor(Color::Red),
-        Print(format!("X: {} ", px)),
-        SetForegroundColor(Color::Blue),
-        Print(format!("O: {} ", po)),
-        SetForegroundColor(Color::Yellow),
-        Print(format!("Draws: {}\n", draws)),
-        ResetColor
-    )?;
-    stdout.flush()?;
-    Ok(())
+// A best-of-N match: it ends once a side has a majority of the games or all of
+// them have been played. Draws use up a game but don't count toward clinching.
+#[derive(Clone, Copy)]
+struct Series {
+    mode: GameMode,
+    length: u32,
+    played: u32,
+    wins_x: u32,
+    wins_o: u32,
 }
 
-// ======================================
-// INPUT
-// ======================================
+impl Series {
+    fn new(mode: GameMode, length: u32) -> Self {
+        Series {
+            mode,
+            length,
+            played: 0,
+            wins_x: 0,
+            wins_o: 0,
+        }
+    }
 
-fn get_human_move(stdout: &mut Stdout, board: &[char; 9], _player: char) -> Result<usize> {
-    stdout.flush()?;
-    loop {
-        if let Event::Key(event) = read()? {
-            if let KeyCode::Char(c) = event.code {
-                if let Some(d) = c.to_digit(10) {
-                    let idx = (d - 1) as usize;
-                    if idx < 9 && board[idx] != PLAYER_X && board[idx] != PLAYER_O {
-                        return Ok(idx);
-                    }
-                }
-            }
+    fn record(&mut self, winner: Option<char>) {
+        self.played += 1;
+        match winner {
+            Some(PLAYER_X) => self.wins_x += 1,
+            Some(_) => self.wins_o += 1,
+            None => {}
         }
-        queue!(stdout, Print("\nInvalid input or cell occupied. Try again: "))?;
-        stdout.flush()?;
     }
-}
 
-// ======================================
-// AI
-// ======================================
+    fn is_over(&self) -> bool {
+        let needed = self.length / 2 + 1;
+        self.wins_x >= needed || self.wins_o >= needed || self.played >= self.length
+    }
 
-fn get_ai_move_random(board: &[char; 9]) -> usize {
-    let mut rng = rand::thread_rng();
-    board
-        .iter()
-        .enumerate()
-        .filter(|&(_, &c)| c != PLAYER_X && c != PLAYER_O)
-        .map(|(i, _)| i)
-        .choose(&mut rng)
-        .unwrap()
+    // None while it's still running or when it finished level.
+    fn winner(&self) -> Option<char> {
+        if !self.is_over() || self.wins_x == self.wins_o {
+            None
+        } else if self.wins_x > self.wins_o {
+            Some(PLAYER_X)
+        } else {
+            Some(PLAYER_O)
+        }
+    }
 }
 
-fn get_ai_move_blocking(board: &[char; 9]) -> usize {
-    let wins = [
-        [0, 1, 2],
-        [3, 4, 5],
-        [6, 7, 8],
-        [0, 3, 6],
-        [1, 4, 7],
-        [2, 5, 8],
-        [0, 4, 8],
-        [2, 4, 6],
-    ];
+// Winner stays on: the player at the table takes on the next one in the queue
+// and the loser joins the back of it. On a draw the holder keeps the table.
+// The first player to `target` wins is champion.
+#[derive(Clone)]
+struct Tournament {
+    players: Vec<String>,
+    wins: Vec<u32>,
+    target: u32,
+    holder: usize,
+    queue: VecDeque<usize>, // waiting players, next challenger first
+}
 
-    for &mark in &[PLAYER_O, PLAYER_X] {
-        for line in &wins {
-            let cells = [board[line[0]], board[line[1]], board[line[2]]];
-            let count_mark = cells.iter().filter(|&&c| c == mark).count();
-            let empties: Vec<usize> = line
-                .iter()
-                .cloned()
-                .filter(|&i| board[i] != PLAYER_X && board[i] != PLAYER_O)
-                .collect();
-            if count_mark == 2 && !empties.is_empty() {
-                return empties[0];
-            }
+impl Tournament {
+    fn new(players: Vec<String>, target: u32) -> Self {
+        Tournament {
+            wins: vec![0; players.len()],
+            target,
+            holder: 0,
+            queue: (1..players.len()).collect(),
+            players,
         }
     }
 
-    get_ai_move_random(board)
+    // (X, O) for the coming match. The challenger moves first, so the holder
+    // doesn't keep the first move as well as the table.
+    fn pairing(&self) -> (usize, usize) {
+        (self.queue[0], self.holder)
+    }
+
+    fn names(&self) -> (&str, &str) {
+        let (x, o) = self.pairing();
+        (&self.players[x], &self.players[o])
+    }
+
+    // Moves the players on after a match and says how it went, e.g. "Carol beat Alice."
+    fn record(&mut self, winner: Option<char>) -> String {
+        let (x, o) = self.pairing();
+        self.queue.pop_front();
+        let (winner, loser) = match winner {
+            Some(PLAYER_X) => (x, o),
+            Some(_) => (o, x),
+            None => {
+                self.queue.push_back(x);
+                return fill(text().drew, &[&self.players[x], &self.players[o]]);
+            }
+        };
+        self.wins[winner] += 1;
+        self.holder = winner;
+        self.queue.push_back(loser);
+        fill(text().beat, &[&self.players[winner], &self.players[loser]])
+    }
+
+    fn champion(&self) -> Option<&str> {
+        (0..self.players.len())
+            .find(|&player| self.wins[player] >= self.target)
+            .map(|player| self.players[player].as_str())
+    }
 }
 
-// ======================================
-// GAME LOGIC
-// ======================================
+// The quickest human win so far, timed from the first frame of the game to its
+// winning move. Computer wins and positions the computer finished don't count.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FastestWin {
+    millis: u64,
+    moves: usize,
+}
 
-fn check_winner(board: &[char; 9]) -> Option<(char, [usize; 3])> {
-    let wins = [
-        [0, 1, 2],
-        [3, 4, 5],
-        [6, 7, 8],
-        [0, 3, 6],
-        [1, 4, 7],
-        [2, 5, 8],
-        [0, 4, 8],
-        [2, 4, 6],
-    ];
+// Win/draw counter; can't go negative and stops at u32::MAX instead of wrapping.
+#[derive(Clone, Copy, Default)]
+struct Score(u32);
 
-    for &line in &wins {
-        if board[line[0]] == board[line[1]]
-            && board[line[1]] == board[line[2]]
-            && (board[line[0]] == PLAYER_X || board[line[0]] == PLAYER_O)
-        {
-            return Some((board[line[0]], line));
-        }
+impl Score {
+    fn increment(&mut self) {
+        self.0 = self.0.saturating_add(1);
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "Easy"),
+            Difficulty::Safe => write!(f, "Safe"),
+            Difficulty::Medium => write!(f, "Medium"),
+            Difficulty::Hard => write!(f, "Hard"),
+            Difficulty::Depth(depth) => write!(f, "Depth-{}", depth),
+            Difficulty::Impossible => write!(f, "Impossible"),
+            Difficulty::Adaptive => write!(f, "Adaptive"),
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Screen positions of the text drawn around the board.
+#[derive(Clone, Copy)]
+struct Layout {
+    hint: (u16, u16),
+    result: (u16, u16),
+    prompt: (u16, u16),
+    scores: (u16, u16),
+    notes: (u16, u16),         // free space for extra output such as a copied board
+    announce: (u16, u16),      // single line describing the last move
+    timer: (u16, u16),         // seconds left for the move, with a time limit
+    panel: Option<(u16, u16)>, // move log beside the board, wide terminals only
+    controls: (u16, u16),      // heading of that panel
+}
+
+// ======================================
+// MAIN
+// ======================================
+
+fn main() -> Result<()> {
+    let mut options = match parse_options() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if options.show_help {
+        return ignore_lost_output(writeln!(stdout(), "{}", USAGE));
+    }
+    if options.self_test {
+        std::process::exit(if run_self_test() { 0 } else { 1 });
+    }
+    // After the self-test, whose checks expect the English text.
+    let _ = LANGUAGE.set(options.language.unwrap_or_default());
+    // Every random choice in the session comes from this one generator, so the
+    // same seed and the same moves play the same games again.
+    let seed = *options.seed.get_or_insert_with(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    if options.json {
+        // stdout is kept for the results.
+        eprintln!("Seed: {}", seed);
+        return ignore_lost_output(run_json(&options, &mut rng));
+    }
+    // Escapes would only garble a file or pipe, so play line by line instead.
+    if !stdout().is_terminal() {
+        if options.gravity.is_some()
+            || options.ultimate
+            || options.replay_path.is_some()
+            || options.tournament.is_some()
+        {
+            eprintln!("--gravity, --ultimate, --replay and --tournament need a terminal");
+            std::process::exit(2);
+        }
+        return ignore_lost_output(
+            writeln!(stdout(), "Seed: {}", seed).and_then(|()| run_plain(&options, &mut rng)),
+        );
+    }
+    let _ = HELP.set(help_screen(&options));
+    // The gravity grid and the ultimate boards have renderers of their own.
+    BIG_CELLS.store(
+        options.big_board && options.gravity.is_none() && !options.ultimate,
+        Ordering::Relaxed,
+    );
+    // Checked before the screen is taken over, so a bad file is reported plainly.
+    let saved_game = match &options.replay_path {
+        Some(path) => match load_saved_game(path, &options) {
+            Ok(game) => Some(game),
+            Err(message) => {
+                eprintln!("Can't replay {}: {}", path, message);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // A missing or unreadable score file just means starting from zero.
+    let mut scores = load_scores().unwrap_or_default();
+    let guard = TerminalGuard::new()?;
+    let input = &mut Terminal;
+    let result = match (&saved_game, options.gravity) {
+        (Some((state, history)), _) => watch_replay(
+            &mut stdout(),
+            input,
+            &options,
+            &state.board,
+            history,
+            state.lines(),
+            0,
+        ),
+        (None, Some((width, height))) => run_gravity(&options, width, height, input),
+        (None, None) if options.ultimate => run_ultimate(&options, input),
+        (None, None) => run(&mut stdout(), &options, &mut scores, &mut rng, input),
+    };
+    let saved = save_scores(scores);
+    drop(guard);
+    if let Err(err) = saved {
+        eprintln!("Couldn't save the scoreboard: {}", err);
+    }
+    // Shown again on the way out, since the welcome screen is long gone by then.
+    if saved_game.is_none() && options.gravity.is_none() && !options.ultimate {
+        ignore_lost_output(writeln!(stdout(), "Seed: {}", seed))?;
+    }
+    ignore_lost_output(result)
+}
+
+// A vanished output consumer leaves nobody to report to.
+fn ignore_lost_output(result: Result<()>) -> Result<()> {
+    match result {
+        Err(err) if matches!(err.kind(), ErrorKind::Interrupted | ErrorKind::BrokenPipe) => Ok(()),
+        other => other,
+    }
+}
+
+// Restores the terminal on every way out of the game, including write errors
+// and panics, so a failed draw never leaves the shell in raw mode.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> Result<Self> {
+        enable_raw_mode()?;
+        // From here on dropping the guard undoes raw mode, also when mouse
+        // capture fails below.
+        let guard = TerminalGuard;
+        execute!(stdout(), EnableMouseCapture)?;
+        // The panic message is printed before unwinding reaches the guard, so
+        // put the terminal back first or it comes out as a staircase.
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+// Safe to run twice: after a panic both the hook and the guard call it.
+fn restore_terminal() {
+    // Best effort: the output may already be gone.
+    let _ = execute!(stdout(), ResetColor, DisableMouseCapture);
+    let _ = disable_raw_mode();
+}
+
+// `scores` carries the lifetime totals in and out, updated after every game.
+fn run<W: Write>(
+    stdout: &mut W,
+    options: &Options,
+    scores: &mut (Score, Score, Score),
+    rng: &mut StdRng,
+    input: &mut dyn InputSource,
+) -> Result<()> {
+    let board_size = options.board_size();
+    let lines = win_lines(board_size, options.win_length(), options.variant);
+
+    let (mut score_player_x, mut score_player_o, mut score_draws) = *scores;
+    // Opened on first use; kept alive so the copied board stays pasteable.
+    let mut clipboard: Option<Clipboard> = None;
+    let mut records: Vec<GameRecord> = Vec::new();
+    // Cleared after the first write error so the warning only shows once.
+    let mut log_path = options.log_path.clone();
+    // Same for the move record; games are only saved while this holds. Games
+    // played by --self-test leave every file in the home directory alone.
+    let mut record_games = !options.self_test;
+    // And for the CSV export.
+    let mut csv_path = (!options.self_test).then(|| options.csv_path());
+    let mut series: Option<Series> = None;
+    // Per-move limit for human turns, asked along with the mode.
+    let mut time_limit: Option<Duration> = None;
+    // The human's wins and losses in adaptive games this session.
+    let mut adaptive_record = (0, 0);
+    // Set by "swap sides" after a game: the next one skips the menus.
+    let mut rematch: Option<GameMode> = None;
+    let mut fastest_win = if options.self_test {
+        None
+    } else {
+        load_fastest_win()
+    };
+    let mut tournament = options
+        .tournament
+        .clone()
+        .map(|players| Tournament::new(players, options.first_to()));
+    // The scoreboard counts by mark across games, so only --names go on it;
+    // tournament players change sides from match to match.
+    let table = seat_players(options.names(), &options.theme);
+    if options.big_board {
+        warn_if_board_too_big(stdout, input, options)?;
+    }
+
+    'games: loop {
+        let mut start = options
+            .start
+            .clone()
+            .unwrap_or_else(|| empty_board(board_size));
+        let mut history: Vec<(char, usize)> = Vec::new();
+
+        // Games inside an unfinished series go straight to the board.
+        let game_mode = match series {
+            _ if let Some(mode) = rematch.take() => mode,
+            Some(current) if !current.is_over() => current.mode,
+            // --mode on the command line: a single untimed game, no menus.
+            _ if options.mode.is_some() => preset_game_mode(stdout, input, options)?,
+            _ => {
+                show_welcome_screen(stdout, input, options)?;
+                let mode = ask_game_mode(stdout, input, options, &lines, &mut start)?;
+                // The AI setup takes one more question than the other modes.
+                let row = if matches!(mode, GameMode::AI(..)) {
+                    10
+                } else {
+                    8
+                };
+                let length = ask_series_length(stdout, input, row)?;
+                series = (length > 1).then(|| Series::new(mode, length));
+                // Every move comes out of the game's time already.
+                if options.time_attack.is_none() {
+                    time_limit = ask_time_limit(stdout, input, row + 2)?;
+                }
+                mode
+            }
+        };
+        let chosen_mode = game_mode;
+        let game_mode = resolve_adaptive(chosen_mode, adaptive_record, board_size);
+        let adaptive_note = match (chosen_mode, game_mode) {
+            (GameMode::AI(Difficulty::Adaptive, ..), GameMode::AI(level, ..)) => {
+                Some(fill(text().adaptive_note, &[&level_name(level)]))
+            }
+            _ => None,
+        };
+        // The editor only hands back positions that passed validation.
+        let mut state = GameState::from_board(start, options.win_length(), options.variant)
+            .map_err(Error::other)?;
+        if options.coin_flip && matches!(game_mode, GameMode::Friend) && state.move_count == 0 {
+            state.current_player = flip_coin(stdout, input, options, rng)?;
+        }
+        let mut early_end_declined = false;
+        // Set from the decided-position prompt: both sides play perfectly from here.
+        let mut autoplay = false;
+        let mut last_ai_move: Option<AiMove> = None;
+        // Moves taken back, newest last; cleared by any fresh move.
+        let mut redo_stack: Vec<(char, usize)> = Vec::new();
+        let mut undo_depth = 0;
+        // One-off message under the turn hint, e.g. when there is nothing to undo.
+        let mut notice: Option<&str> = adaptive_note.as_deref();
+        // Cell under the arrow-key cursor, starting in the middle of the board.
+        let mut cursor = board_size / 2 * board_size + board_size / 2;
+        // When the current human turn runs out, and the game clocks; kept across
+        // redraws after a resize.
+        let mut timer = Timer {
+            clocks: options
+                .time_attack
+                .map(|secs| {
+                    [PLAYER_X, PLAYER_O].map(|mark| {
+                        is_human_turn(game_mode, mark).then_some(Duration::from_secs(secs))
+                    })
+                })
+                .filter(|clocks| clocks.iter().any(Option::is_some)),
+            ..Timer::default()
+        };
+        // Computer turns skipped so far under the free-move handicap.
+        let mut passes = 0;
+        // Said after the winner, kept for when the result is shown again.
+        let mut win_note: Option<String> = None;
+        let started = Instant::now();
+        let players = seat_players(
+            tournament
+                .as_ref()
+                .map(Tournament::names)
+                .or(options.names()),
+            &options.theme,
+        );
+
+        // Game loop, ends with the winner (None for a draw)
+        let winner = loop {
+            let layout = current_layout(board_size);
+            let scores = (score_player_x, score_player_o, score_draws);
+            let human_turn = !autoplay && is_human_turn(game_mode, state.current_player);
+            let shown_cursor = human_turn.then_some(cursor);
+            render_board(
+                &state.board,
+                stdout,
+                &history,
+                options,
+                Highlight {
+                    cursor: shown_cursor,
+                    last: state.last_move(),
+                    ..Highlight::default()
+                },
+            )?;
+            draw_info_panel(stdout, layout, &history, scores, &table, &options.theme)?;
+            print_turn_hint(
+                stdout,
+                layout,
+                game_mode,
+                state.current_player,
+                board_size * board_size,
+                &players,
+                &options.theme,
+            )?;
+            if options.announce {
+                print_announcement(stdout, layout, &history, board_size, &options.theme)?;
+            }
+            if let Some(ai_move) = last_ai_move {
+                print_ai_notes(stdout, layout, options, ai_move)?;
+            }
+            if let Some(clocks) = timer.clocks {
+                let mover = state.current_player;
+                print_clocks(stdout, layout, clocks, mover, &options.theme)?;
+            }
+            if let Some(text) = notice.take() {
+                let (x, y) = layout.hint;
+                queue!(
+                    stdout,
+                    MoveTo(x, y + 1),
+                    SetForegroundColor(options.theme.hint),
+                    Print(text),
+                    ResetColor,
+                    MoveTo(x, y)
+                )?;
+            }
+
+            let pos = if autoplay {
+                pause_for_input(input, Duration::from_millis(600))?;
+                best_move(&state.board, state.current_player, &lines)
+            } else if human_turn {
+                if let Some(limit) = time_limit {
+                    timer.deadline.get_or_insert_with(|| Instant::now() + limit);
+                }
+                let turn =
+                    get_human_move(stdout, input, options, &state, &mut cursor, &mut timer, rng)?;
+                // The clock keeps running while a resignation or draw offer is asked.
+                if !matches!(turn, Turn::Resized | Turn::Resign | Turn::OfferDraw) {
+                    timer.deadline = None;
+                }
+                match turn {
+                    Turn::Place(idx) => {
+                        redo_stack.clear();
+                        undo_depth = 0;
+                        idx
+                    }
+                    Turn::Undo => {
+                        if options.undo_limit.is_some_and(|limit| undo_depth >= limit) {
+                            notice = Some(text().undo_limit);
+                        } else if undo_step(&mut state, &mut history, &mut redo_stack, game_mode) {
+                            undo_depth += 1;
+                            last_ai_move = None;
+                        } else {
+                            notice = Some(text().nothing_to_undo);
+                        }
+                        continue;
+                    }
+                    Turn::Redo => {
+                        if redo_step(&mut state, &mut history, &mut redo_stack, game_mode) {
+                            undo_depth -= 1;
+                            last_ai_move = None;
+                        } else {
+                            notice = Some(text().nothing_to_redo);
+                        }
+                        continue;
+                    }
+                    Turn::TimedOut => {
+                        redo_stack.clear();
+                        undo_depth = 0;
+                        notice = Some(text().time_up);
+                        get_ai_move_random(&state.board, rng)
+                    }
+                    Turn::Resized => continue,
+                    Turn::FlagFell => {
+                        let player = state.current_player;
+                        render_board(
+                            &state.board,
+                            stdout,
+                            &history,
+                            options,
+                            Highlight::default(),
+                        )?;
+                        draw_info_panel(stdout, layout, &history, scores, &table, &options.theme)?;
+                        if let Some(clocks) = timer.clocks {
+                            print_clocks(stdout, layout, clocks, player, &options.theme)?;
+                        }
+                        let label = seat(&players, player).label();
+                        win_note = Some(fill(text().lost_on_time, &[&label]));
+                        print_winner(
+                            stdout,
+                            layout,
+                            seat(&players, switch_player(player)),
+                            win_note.as_deref(),
+                            options,
+                        )?;
+                        break Some(switch_player(player));
+                    }
+                    // Nothing is scored for an abandoned game, and a series it
+                    // belonged to is called off. Started from --mode there is
+                    // no menu to go back to, so the session ends.
+                    Turn::Quit if options.mode.is_some() => {
+                        let layout = current_layout(board_size);
+                        clear_screen(stdout, options)?;
+                        let y = print_stats(stdout, layout, &records)?;
+                        print_session_stats(stdout, (layout.notes.0, y), &records, &options.theme)?;
+                        break 'games;
+                    }
+                    Turn::Quit => {
+                        series = None;
+                        continue 'games;
+                    }
+                    // Ending early is agreed between two people; the computer
+                    // plays every game out.
+                    Turn::Resign | Turn::OfferDraw if !matches!(game_mode, GameMode::Friend) => {
+                        notice = Some(text().two_players_only);
+                        continue;
+                    }
+                    Turn::Resign => {
+                        let player = state.current_player;
+                        let question =
+                            fill(text().really_resign, &[&seat(&players, player).label()]);
+                        if !ask_yes_no(stdout, input, layout.hint, &question)? {
+                            continue;
+                        }
+                        let winner = switch_player(player);
+                        render_board(
+                            &state.board,
+                            stdout,
+                            &history,
+                            options,
+                            Highlight::default(),
+                        )?;
+                        draw_info_panel(stdout, layout, &history, scores, &table, &options.theme)?;
+                        win_note = Some(fill(text().resigned, &[&seat(&players, player).label()]));
+                        print_winner(
+                            stdout,
+                            layout,
+                            seat(&players, winner),
+                            win_note.as_deref(),
+                            options,
+                        )?;
+                        break Some(winner);
+                    }
+                    Turn::OfferDraw => {
+                        let theme = &options.theme;
+                        let question = fill(
+                            text().offers_draw,
+                            &[
+                                &seat(&players, state.current_player).label(),
+                                &seat(&players, switch_player(state.current_player)).label(),
+                            ],
+                        );
+                        if !ask_yes_no(stdout, input, layout.hint, &question)? {
+                            notice = Some(text().draw_declined);
+                            continue;
+                        }
+                        render_board(
+                            &state.board,
+                            stdout,
+                            &history,
+                            options,
+                            Highlight::default(),
+                        )?;
+                        draw_info_panel(stdout, layout, &history, scores, &table, theme)?;
+                        print_draw(stdout, layout, theme)?;
+                        break None;
+                    }
+                }
+            } else {
+                if let GameMode::AI(_, _, free_moves) = game_mode
+                    && passes < free_moves
+                {
+                    passes += 1;
+                    state.current_player = switch_player(state.current_player);
+                    notice = Some(text().computer_passes);
+                    continue;
+                }
+                // Watching two AIs already pauses before every move.
+                let think = match game_mode {
+                    GameMode::AIvsAI(..) => Duration::ZERO,
+                    _ => options.think_time(),
+                };
+                let difficulty = match game_mode {
+                    GameMode::AI(difficulty, player_first, _) => {
+                        let computer_mark = if player_first { PLAYER_O } else { PLAYER_X };
+                        // Handicap: the computer's first k moves are random picks.
+                        let computer_moves = history
+                            .iter()
+                            .filter(|&&(mark, _)| mark == computer_mark)
+                            .count();
+                        if computer_moves < options.handicap {
+                            Difficulty::Easy
+                        } else {
+                            difficulty
+                        }
+                    }
+                    GameMode::AIvsAI(x_difficulty, o_difficulty) => {
+                        // A pause before each move so the game can be followed.
+                        pause_for_input(input, Duration::from_millis(600))?;
+                        if state.current_player == PLAYER_X {
+                            x_difficulty
+                        } else {
+                            o_difficulty
+                        }
+                    }
+                    GameMode::Friend => unreachable!(),
+                };
+                let mut redraw = |stdout: &mut W| -> Result<Layout> {
+                    let layout = current_layout(board_size);
+                    let highlight = Highlight {
+                        last: state.last_move(),
+                        ..Highlight::default()
+                    };
+                    render_board(&state.board, stdout, &history, options, highlight)?;
+                    draw_info_panel(stdout, layout, &history, scores, &table, &options.theme)?;
+                    print_turn_hint(
+                        stdout,
+                        layout,
+                        game_mode,
+                        state.current_player,
+                        board_size * board_size,
+                        &players,
+                        &options.theme,
+                    )?;
+                    Ok(layout)
+                };
+                // The search gets a generator of its own, seeded from the
+                // session's, so a seeded session still picks the same moves.
+                let (board, lines) = (state.board.clone(), lines.clone());
+                let mark = state.current_player;
+                let mut search_rng = StdRng::seed_from_u64(rng.random());
+                let search =
+                    move || pick_ai_move(&board, &lines, difficulty, mark, &mut search_rng);
+                let ai_move = wait_for_ai_move(
+                    stdout,
+                    input,
+                    layout,
+                    search,
+                    think,
+                    &options.theme,
+                    &mut redraw,
+                )?;
+                last_ai_move = Some(ai_move);
+                ai_move.pos
+            };
+
+            let mover = state.current_player;
+            state.apply_move(pos).map_err(Error::other)?;
+            history.push((mover, pos));
+            let layout = current_layout(board_size);
+            let scores = (score_player_x, score_player_o, score_draws);
+
+            let threats = if options.show_threats {
+                all_two_in_a_rows(&state.board, &lines)
+            } else {
+                Vec::new()
+            };
+
+            match state.result_after(pos) {
+                GameResult::Ongoing => {}
+                GameResult::Draw => {
+                    render_board(
+                        &state.board,
+                        stdout,
+                        &history,
+                        options,
+                        Highlight {
+                            threats: &threats,
+                            ..Highlight::default()
+                        },
+                    )?;
+                    draw_info_panel(stdout, layout, &history, scores, &table, &options.theme)?;
+                    if options.announce {
+                        print_announcement(stdout, layout, &history, board_size, &options.theme)?;
+                    }
+                    print_draw(stdout, layout, &options.theme)?;
+                    break None;
+                }
+                GameResult::XWins(line) | GameResult::OWins(line) => {
+                    let winner = mover;
+                    // Games between two AIs, watched or played out, don't wait for it.
+                    let delay = if autoplay || matches!(game_mode, GameMode::AIvsAI(..)) {
+                        Duration::ZERO
+                    } else {
+                        Duration::from_millis(120)
+                    };
+                    sweep_winning_line(
+                        &state.board,
+                        stdout,
+                        &history,
+                        options,
+                        Highlight {
+                            line: Some(line.as_slice()),
+                            threats: &threats,
+                            ..Highlight::default()
+                        },
+                        delay,
+                    )?;
+                    draw_info_panel(stdout, layout, &history, scores, &table, &options.theme)?;
+                    if options.announce {
+                        print_announcement(stdout, layout, &history, board_size, &options.theme)?;
+                    }
+                    // Wins played out by the computer on the human's behalf don't count.
+                    if !autoplay && is_human_turn(game_mode, winner) {
+                        let win = FastestWin {
+                            millis: started.elapsed().as_millis() as u64,
+                            moves: history.len(),
+                        };
+                        win_note = beat_fastest_win(&mut fastest_win, win);
+                        if win_note.is_some()
+                            && !options.self_test
+                            && let Err(err) = save_fastest_win(win)
+                        {
+                            let (x, y) = layout.notes;
+                            queue!(
+                                stdout,
+                                MoveTo(x, y),
+                                SetForegroundColor(options.theme.alert),
+                                Print(fill(text().record_not_saved, &[&err.kind()])),
+                                ResetColor
+                            )?;
+                        }
+                    }
+                    print_winner(
+                        stdout,
+                        layout,
+                        seat(&players, winner),
+                        win_note.as_deref(),
+                        options,
+                    )?;
+                    break Some(winner);
+                }
+            }
+
+            // Against the perfect computer, offer to stop once the result can no longer
+            // change. Working that out takes an unpruned search, so 3×3 only.
+            if options.early_end
+                && board_size == 3
+                && !early_end_declined
+                && let GameMode::AI(Difficulty::Impossible, player_first, _) = game_mode
+            {
+                let human = if player_first { PLAYER_X } else { PLAYER_O };
+                let (best, worst) = result_range(
+                    &mut state.board.clone(),
+                    state.current_player,
+                    human,
+                    &lines,
+                );
+                if best == worst {
+                    render_board(
+                        &state.board,
+                        stdout,
+                        &history,
+                        options,
+                        Highlight::default(),
+                    )?;
+                    draw_info_panel(stdout, layout, &history, scores, &table, &options.theme)?;
+                    match ask_end_early(stdout, input, layout, best, &options.theme)? {
+                        EarlyEnd::Stop => {
+                            render_board(
+                                &state.board,
+                                stdout,
+                                &history,
+                                options,
+                                Highlight::default(),
+                            )?;
+                            draw_info_panel(
+                                stdout,
+                                layout,
+                                &history,
+                                scores,
+                                &table,
+                                &options.theme,
+                            )?;
+                            let winner = match best {
+                                1 => Some(human),
+                                -1 => Some(switch_player(human)),
+                                _ => None,
+                            };
+                            match winner {
+                                Some(winner) => print_winner(
+                                    stdout,
+                                    layout,
+                                    seat(&players, winner),
+                                    None,
+                                    options,
+                                )?,
+                                None => print_draw(stdout, layout, &options.theme)?,
+                            }
+                            break winner;
+                        }
+                        EarlyEnd::Continue => early_end_declined = true,
+                        EarlyEnd::AutoPlay => {
+                            early_end_declined = true;
+                            autoplay = true;
+                            last_ai_move = None;
+                        }
+                    }
+                }
+            }
+        };
+
+        match winner {
+            Some(PLAYER_X) => score_player_x.increment(),
+            Some(_) => score_player_o.increment(),
+            None => score_draws.increment(),
+        }
+        *scores = (score_player_x, score_player_o, score_draws);
+        if let Some(series) = &mut series {
+            series.record(winner);
+        }
+        let match_result = tournament.as_mut().map(|current| current.record(winner));
+        record_adaptive(&mut adaptive_record, chosen_mode, winner, board_size);
+        let record = GameRecord {
+            mode: game_mode,
+            winner,
+            moves: history.len(),
+        };
+        records.push(record);
+
+        let layout = current_layout(board_size);
+        if let Some(path) = &log_path
+            && let Err(err) = append_result_line(path, &format_result_line(&record, &state.board))
+        {
+            let (x, y) = layout.notes;
+            queue!(
+                stdout,
+                MoveTo(x, y),
+                SetForegroundColor(options.theme.alert),
+                Print(fill(text().logging_off, &[&err.kind()])),
+                ResetColor
+            )?;
+            log_path = None;
+        }
+        if record_games && let Err(err) = write_game_record(&state.board, &history, winner) {
+            let (x, y) = layout.notes;
+            queue!(
+                stdout,
+                MoveTo(x, y),
+                SetForegroundColor(options.theme.alert),
+                Print(fill(text().game_record_off, &[&err.kind()])),
+                ResetColor
+            )?;
+            record_games = false;
+        }
+        if let Some(path) = csv_path
+            && let Err(err) = write_csv_row(path, &record)
+        {
+            let (x, y) = layout.notes;
+            queue!(
+                stdout,
+                MoveTo(x, y),
+                SetForegroundColor(options.theme.alert),
+                Print(fill(text().csv_off, &[&err.kind()])),
+                ResetColor
+            )?;
+            csv_path = None;
+        }
+        print_scoreboard(
+            stdout,
+            layout,
+            score_player_x,
+            score_player_o,
+            score_draws,
+            &table,
+            &options.theme,
+        )?;
+        if let Some(series) = &series {
+            print_series(stdout, layout, series, &options.theme)?;
+        }
+        // The solver behind the analysis is only fast enough on 3×3.
+        if options.analysis && board_size == 3 {
+            print_analysis(stdout, layout, &state.board, &history, &lines)?;
+        }
+        // Tournament games go from the board to the standings and on to the
+        // next match, with no replay menu in between.
+        if let (Some(current), Some(result)) = (&mut tournament, &match_result) {
+            if !show_standings(stdout, input, layout, options, current, result)? {
+                break 'games;
+            }
+            if current.champion().is_some() {
+                *current = Tournament::new(current.players.clone(), current.target);
+            }
+            continue 'games;
+        }
+        loop {
+            let post_game = ask_replay(
+                stdout,
+                input,
+                layout,
+                options,
+                &state.board,
+                &mut clipboard,
+                chosen_mode,
+            )?;
+            match post_game {
+                PostGame::Again => break,
+                PostGame::SwapSides => {
+                    if let GameMode::AI(difficulty, player_first, free_moves) = chosen_mode {
+                        let swapped = GameMode::AI(difficulty, !player_first, free_moves);
+                        // A series carries on with the sides swapped.
+                        if let Some(series) = &mut series {
+                            series.mode = swapped;
+                        }
+                        rematch = Some(swapped);
+                    }
+                    break;
+                }
+                PostGame::Quit => {
+                    let y = print_stats(stdout, layout, &records)?;
+                    print_session_stats(stdout, (layout.notes.0, y), &records, &options.theme)?;
+                    break 'games;
+                }
+                PostGame::Accuracy => {
+                    print_accuracy(stdout, layout, &state.board, &history, &lines, &players)?;
+                }
+                PostGame::Replay | PostGame::Redraw => {
+                    if matches!(post_game, PostGame::Replay) {
+                        let step = history.len();
+                        watch_replay(stdout, input, options, &state.board, &history, &lines, step)?;
+                    }
+                    // Put the final position back before showing the menu again.
+                    let layout = current_layout(board_size);
+                    let line = match state.result() {
+                        GameResult::XWins(line) | GameResult::OWins(line) => Some(line),
+                        GameResult::Draw | GameResult::Ongoing => None,
+                    };
+                    let threats = if options.show_threats {
+                        all_two_in_a_rows(&state.board, &lines)
+                    } else {
+                        Vec::new()
+                    };
+                    render_board(
+                        &state.board,
+                        stdout,
+                        &history,
+                        options,
+                        Highlight {
+                            line: line.as_deref(),
+                            threats: &threats,
+                            ..Highlight::default()
+                        },
+                    )?;
+                    let scores = (score_player_x, score_player_o, score_draws);
+                    draw_info_panel(stdout, layout, &history, scores, &table, &options.theme)?;
+                    match winner {
+                        Some(winner) => print_winner(
+                            stdout,
+                            layout,
+                            seat(&players, winner),
+                            win_note.as_deref(),
+                            options,
+                        )?,
+                        None => print_draw(stdout, layout, &options.theme)?,
+                    }
+                    print_scoreboard(
+                        stdout,
+                        layout,
+                        score_player_x,
+                        score_player_o,
+                        score_draws,
+                        &table,
+                        &options.theme,
+                    )?;
+                    if let Some(series) = &series {
+                        print_series(stdout, layout, series, &options.theme)?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// ======================================
+// TEXT
+// ======================================
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Language {
+    #[default]
+    English,
+    Spanish,
+}
+
+// The words on the menus, prompts, banners and scoreboard, one table per
+// language. `{}` marks where `fill` puts a value, in order. Keys stay the same
+// letters in every language so the controls don't move.
+struct Strings {
+    welcome: &'static str,
+    win_rule: &'static str,
+    torus_rule: &'static str,
+    controls: &'static str,
+    control_lines: [&'static str; 11],
+    press_any_key: &'static str,
+    help_title: &'static str,
+    help_numbering: &'static str,
+    help_digits: &'static str,
+    help_columns: &'static str,
+    help_ultimate: &'static str,
+    colours: &'static str,
+    legend: [&'static str; 7], // X, O, winning line, cursor, hints, computer, alerts
+    seed: &'static str,
+    mode_menu: &'static str,
+    mode_menu_ready: &'static str,
+    select_difficulty: &'static str,
+    difficulty_of: &'static str,
+    levels: [&'static str; 7], // easy, safe, medium, hard, depth, impossible, adaptive
+    depth_prompt: &'static str,
+    first_player: &'static str,
+    free_moves: &'static str,
+    series_length: &'static str,
+    time_limit: &'static str,
+    invalid: &'static str,
+    or: &'static str, // before the last item of a list
+    player: &'static str,
+    friend_turn: &'static str,
+    your_turn: &'static str,
+    computer_turn: &'static str,
+    ai_turn: &'static str,
+    thinking: &'static str,
+    wins: &'static str,
+    draw: &'static str,
+    draws: &'static str,
+    play_again: &'static str,
+    really_quit: &'static str,
+    swap_sides: &'static str,
+    accuracy: &'static str,
+    copy: &'static str,
+    replay: &'static str,
+    title: &'static str,
+    ai_game_title: &'static str,
+    gravity_title: &'static str,
+    ultimate_title: &'static str,
+    coin_flip: &'static str,
+    goes_first: &'static str,
+    decided: &'static str,
+    outcomes: [&'static str; 3], // you win, you lose, draw
+    try_again: &'static str,
+    column_full: &'static str,
+    choose_column: &'static str,
+    choose_board: &'static str,
+    choose_cell: &'static str,
+    one_to_nine: &'static str,
+    board_decided: &'static str,
+    cell_taken: &'static str,
+    undo_limit: &'static str,
+    nothing_to_undo: &'static str,
+    nothing_to_redo: &'static str,
+    time_up: &'static str,
+    lost_on_time: &'static str,
+    two_players_only: &'static str,
+    really_resign: &'static str,
+    resigned: &'static str,
+    offers_draw: &'static str,
+    draw_declined: &'static str,
+    computer_passes: &'static str,
+    series_winner: &'static str,
+    series_drawn: &'static str,
+    series_score: &'static str,
+    first_record: &'static str,
+    new_record: &'static str,
+    copied: &'static str,
+    no_clipboard: &'static str,
+    level_names: [&'static str; 7], // the levels as named in running text; Depth's {} is how far it looks
+    adaptive_note: &'static str,
+    record_not_saved: &'static str,
+    logging_off: &'static str,
+    game_record_off: &'static str,
+    csv_off: &'static str,
+    computer_plays: &'static str,
+    not_a_cell: &'static str,
+    move_refused: &'static str,
+    retry: &'static str,
+    off_board: &'static str,
+    taken: &'static str,
+    game_over: &'static str,
+    wrong_board: &'static str,
+    editor_hint: &'static str,
+    cant_start: &'static str,
+    bad_counts: &'static str,
+    already_won: &'static str,
+    board_full: &'static str,
+    replay_step: &'static str,
+    replay_start: &'static str,
+    replay_controls: &'static str,
+    big_board_window: &'static str,
+    big_board_anyway: &'static str,
+    boards_heading: &'static str,
+    panel_controls: &'static str,
+    moves_heading: &'static str,
+    moves_earlier: &'static str,
+    nodes: &'static str,
+    explain: &'static str,
+    reasons: [&'static str; 11], // in MoveReason order
+    cell_at: &'static str,
+    placed_at: &'static str,
+    empty_cell: &'static str,
+    board_row: &'static str,
+    seconds_left: &'static str,
+    ascii_badges: [&'static str; 3], // for the win, draw and champion emoji
+    accuracy_line: &'static str,
+    key_moment: &'static str,
+    analysis_rows: [&'static str; 2], // the headings of the two rows of alternatives
+    outcome_letters: [&'static str; 3], // win, loss, draw
+    no_mistakes: &'static str,
+    series_running: &'static str,
+    drew: &'static str,
+    beat: &'static str,
+    standings_key: &'static str,
+    tournament_one: &'static str,
+    tournament_title: &'static str,
+    champion: &'static str,
+    new_tournament: &'static str,
+    next_match: &'static str,
+    next_match_key: &'static str,
+    games_played: &'static str,
+    beat_level: &'static str,
+    win_rates: &'static str,
+    streak: &'static str,
+    no_streak: &'static str,
+    longest_streak: &'static str,
+}
+
+const ENGLISH: Strings = Strings {
+    welcome: "==== Welcome to Tic Tac Toe ====",
+    win_rule: "Win by getting {} in a row (rows, columns, diagonals).",
+    torus_rule: "Torus rules: diagonals wrap around the board edges.",
+    controls: "Controls:",
+    control_lines: [
+        "1–{}  place your mark",
+        "{}  move the cursor and place",
+        "click  place your mark with the mouse",
+        "{} / {}  take back / replay a move",
+        "{}  flash a suggested move",
+        "{} / Esc  leave the game for the menu",
+        "{} / {}  resign / offer a draw (two players)",
+        "{}  copy the final board (after a game)",
+        "{}  replay the game just played",
+        "{}  read the whole board aloud (--announce)",
+        "?    show help, at any prompt",
+    ],
+    press_any_key: "Press any key to continue...",
+    help_title: "==== Help (any key to go back) ====",
+    help_numbering: "Cells are numbered 1 to {} row by row, starting at the top left.",
+    help_digits: "A number that could take a second digit waits for it or for Enter.",
+    help_columns: "Columns are numbered 1 to {} from the left; marks drop to the bottom.",
+    help_ultimate:
+        "Boards, and the cells in each, are numbered 1 to 9 like the cells of one board.",
+    colours: "Colours:",
+    legend: [
+        "X's marks",
+        "O's marks",
+        "the winning line",
+        "the cursor",
+        "prompts and your turn",
+        "the computer's turn",
+        "errors and the last seconds of a timer",
+    ],
+    seed: "Seed {} (--seed {} plays this session again)",
+    mode_menu: "Friend (f), AI (a) or watch AI vs AI (d)? Or (e) set up a position first: ",
+    mode_menu_ready: "Position ready. Friend (f), AI (a) or watch AI vs AI (d)? ",
+    select_difficulty: "Select difficulty",
+    difficulty_of: "{}'s difficulty",
+    levels: [
+        "(e)asy",
+        "(s)afe",
+        "(m)edium",
+        "(h)ard",
+        "(d)epth",
+        "(i)mpossible",
+        "(a)daptive",
+    ],
+    depth_prompt: "Moves to look ahead (1-{}): ",
+    first_player: "Who goes first? (p = player, c = computer): ",
+    free_moves: "Free moves before the computer plays? (0 to 3): ",
+    series_length: "Best of how many games? (1, 3, 5 or 7): ",
+    time_limit: "Time per move? (u)nlimited, (1) 5s, (2) 10s or (3) 30s: ",
+    invalid: "Invalid input. Type {}: ",
+    or: "or",
+    player: "Player {}",
+    friend_turn: "{}, enter position (1-{}): ",
+    your_turn: "Your turn ({}). Enter position (1-{}): ",
+    computer_turn: "Computer's turn ({})...",
+    ai_turn: "{} AI's turn ({})...",
+    thinking: "Computer is thinking",
+    wins: "{} wins!",
+    draw: "It's a draw!",
+    draws: "Draws",
+    play_again: "Play again? ({}): ",
+    really_quit: "Really quit? (y/n): ",
+    swap_sides: "s = swap sides",
+    accuracy: "a = accuracy",
+    copy: "{} = copy",
+    replay: "{} = replay",
+    title: "Tic Tac Toe",
+    ai_game_title: "Tic Tac Toe — AI game",
+    gravity_title: "Tic Tac Toe — gravity",
+    ultimate_title: "Tic Tac Toe — ultimate",
+    coin_flip: "Flipping a coin to pick who starts...",
+    goes_first: "{} goes first! Press any key...",
+    decided: "Decided: {}. End now? (y/n, a = auto): ",
+    outcomes: ["you win", "you lose", "draw"],
+    try_again: "Invalid input. Try again: ",
+    column_full: "Invalid input or column full. Try again: ",
+    choose_column: "{}, choose a column (1-{}): ",
+    choose_board: "{}, choose a board (1-9): ",
+    choose_cell: "{}, board {}: choose a cell (1-9): ",
+    one_to_nine: "Type a number from 1 to 9.",
+    board_decided: "Board {} is already decided.",
+    cell_taken: "Cell {} of board {} is taken.",
+    undo_limit: "Undo limit reached.",
+    nothing_to_undo: "Nothing to undo.",
+    nothing_to_redo: "Nothing to redo.",
+    time_up: "Time's up: a random move was played.",
+    lost_on_time: "{} lost on time.",
+    two_players_only: "Resigning and draws are for two-player games.",
+    really_resign: "{}, really resign? (y/n): ",
+    resigned: "{} resigned.",
+    offers_draw: "{} offers a draw. {}, accept? (y/n): ",
+    draw_declined: "Draw declined.",
+    computer_passes: "The computer passes: a free move for you.",
+    series_winner: "Series winner: {}",
+    series_drawn: "Series drawn",
+    series_score: "{} ({}-{} over best of {})",
+    first_record: "First win on record: {}.",
+    new_record: "New record! {}, was {}.",
+    copied: "Board copied to clipboard.",
+    no_clipboard: "No clipboard, board text:",
+    level_names: [
+        "Easy",
+        "Safe",
+        "Medium",
+        "Hard",
+        "Depth-{}",
+        "Impossible",
+        "Adaptive",
+    ],
+    adaptive_note: "Adaptive: playing {} this game.",
+    record_not_saved: "Record not saved: {}",
+    logging_off: "Logging off: {}",
+    game_record_off: "Game record off: {}",
+    csv_off: "CSV export off: {}",
+    computer_plays: "Computer ({}) plays {}.",
+    not_a_cell: "Not a cell number: {}",
+    move_refused: "Move refused. {}",
+    retry: "{} Try again: ",
+    off_board: "Cell {} is off the board.",
+    taken: "Cell {} is already taken.",
+    game_over: "The game is already over.",
+    wrong_board: "The move has to go in board {}.",
+    editor_hint: "Editor: cell numbers cycle a cell, Enter plays, Esc clears",
+    cant_start: "Can't start: {}.",
+    bad_counts: "X needs as many marks as O or one more",
+    already_won: "{} has already won",
+    board_full: "the board is full",
+    replay_step: "Replay {}/{}: {} took {}",
+    replay_start: "Replay 0/{}: starting position",
+    replay_controls: "←/→ or Space step, Esc or {} = done",
+    big_board_window: "The big board needs a window of at least {}×{}; this one is {}×{}.",
+    big_board_anyway: "Make the window bigger, or press any key to play anyway.",
+    boards_heading: "Boards",
+    panel_controls: "Controls: type a cell number",
+    moves_heading: "Moves:",
+    moves_earlier: "Moves ({} earlier):",
+    nodes: "Positions evaluated by AI: {}",
+    explain: "Computer took {}: {}",
+    reasons: [
+        "completing its own line",
+        "blocking your line",
+        "no threats, so any free cell",
+        "best result against every reply",
+        "taking the cell you could fork from",
+        "opening two lines at once",
+        "making you block instead of forking",
+        "no threats, so the strongest free square",
+        "the book reply to this opening",
+        "best position a few moves ahead",
+        "any free cell that leaves you no winning reply",
+    ],
+    cell_at: "row {}, column {}",
+    placed_at: "{} placed at {}.",
+    empty_cell: "empty",
+    board_row: "Row {}: {}.",
+    seconds_left: "{}s left",
+    ascii_badges: ["(win)", "(draw)", "(champion)"],
+    accuracy_line: "{} played {}/{} optimal, {} {}/{}",
+    key_moment: "Key moment: move {}, {} played {} ({} -> {})",
+    analysis_rows: ["Cell", "Result"],
+    outcome_letters: ["W", "L", "D"],
+    no_mistakes: "No mistakes: every move kept the best result.",
+    series_running: "Series: {} {} - {} {}, best of {}",
+    drew: "{} and {} drew.",
+    beat: "{} beat {}.",
+    standings_key: "Press any key for the standings...",
+    tournament_one: "Tournament — first to 1 win",
+    tournament_title: "Tournament — first to {} wins",
+    champion: "{} is the champion! {}",
+    new_tournament: "n = new tournament, {} = quit: ",
+    next_match: "Next: {} ({}) vs {} ({}), then {}",
+    next_match_key: "Press any key for the next match, {} to stop: ",
+    games_played: "Games played: {}",
+    beat_level: "Beat {} in {} of {} games",
+    win_rates: "Wins: {} {}% · {} {}% · {} moves a game",
+    streak: "{} won {} in a row",
+    no_streak: "none",
+    longest_streak: "Longest streak: {}",
+};
+
+const SPANISH: Strings = Strings {
+    welcome: "==== Bienvenido al Tres en Raya ====",
+    win_rule: "Gana quien consiga {} en línea (filas, columnas o diagonales).",
+    torus_rule: "Reglas de toro: las diagonales siguen por los bordes del tablero.",
+    controls: "Controles:",
+    control_lines: [
+        "1–{}  pon tu marca",
+        "{}  mueve el cursor y coloca",
+        "clic  pon tu marca con el ratón",
+        "{} / {}  deshaz / rehaz una jugada",
+        "{}  muestra una jugada sugerida",
+        "{} / Esc  deja la partida y vuelve al menú",
+        "{} / {}  abandona / ofrece tablas (dos jugadores)",
+        "{}  copia el tablero final (tras la partida)",
+        "{}  repite la partida recién jugada",
+        "{}  lee el tablero en voz alta (--announce)",
+        "?    muestra la ayuda, en cualquier momento",
+    ],
+    press_any_key: "Pulsa cualquier tecla para continuar...",
+    help_title: "==== Ayuda (cualquier tecla para volver) ====",
+    help_numbering: "Las casillas van del 1 al {} por filas, empezando arriba a la izquierda.",
+    help_digits: "Un número que admite otra cifra la espera, o espera a Enter.",
+    help_columns: "Las columnas van del 1 al {} desde la izquierda; las marcas caen al fondo.",
+    help_ultimate:
+        "Los tableros, y las casillas de cada uno, van del 1 al 9 como en un solo tablero.",
+    colours: "Colores:",
+    legend: [
+        "las marcas de X",
+        "las marcas de O",
+        "la línea ganadora",
+        "el cursor",
+        "avisos y tu turno",
+        "el turno del ordenador",
+        "errores y los últimos segundos del reloj",
+    ],
+    seed: "Semilla {} (--seed {} repite esta sesión)",
+    mode_menu: "¿Amigo (f), IA (a) o ver IA contra IA (d)? O (e) prepara antes una posición: ",
+    mode_menu_ready: "Posición lista. ¿Amigo (f), IA (a) o ver IA contra IA (d)? ",
+    select_difficulty: "Elige la dificultad",
+    difficulty_of: "Dificultad de {}",
+    levels: [
+        "fácil (e)",
+        "seguro (s)",
+        "medio (m)",
+        "difícil (h)",
+        "profundidad (d)",
+        "imposible (i)",
+        "adaptativo (a)",
+    ],
+    depth_prompt: "Jugadas a prever (1-{}): ",
+    first_player: "¿Quién empieza? (p = jugador, c = ordenador): ",
+    free_moves: "¿Jugadas libres antes de que juegue el ordenador? (0 a 3): ",
+    series_length: "¿Al mejor de cuántas partidas? (1, 3, 5 o 7): ",
+    time_limit: "¿Tiempo por jugada? (u) sin límite, (1) 5s, (2) 10s o (3) 30s: ",
+    invalid: "Entrada no válida. Escribe {}: ",
+    or: "o",
+    player: "Jugador {}",
+    friend_turn: "{}, elige casilla (1-{}): ",
+    your_turn: "Tu turno ({}). Elige casilla (1-{}): ",
+    computer_turn: "Turno del ordenador ({})...",
+    ai_turn: "Turno de la IA {} ({})...",
+    thinking: "El ordenador está pensando",
+    wins: "¡{} gana!",
+    draw: "¡Empate!",
+    draws: "Empates",
+    play_again: "¿Otra partida? ({}): ",
+    really_quit: "¿Salir de verdad? (y/n): ",
+    swap_sides: "s = cambiar de lado",
+    accuracy: "a = precisión",
+    copy: "{} = copiar",
+    replay: "{} = repetir",
+    title: "Tres en Raya",
+    ai_game_title: "Tres en Raya — contra la IA",
+    gravity_title: "Tres en Raya — gravedad",
+    ultimate_title: "Tres en Raya — definitivo",
+    coin_flip: "Lanzando una moneda para ver quién empieza...",
+    goes_first: "¡Empieza {}! Pulsa cualquier tecla...",
+    decided: "Decidida: {}. ¿Terminar ya? (y/n, a = auto): ",
+    outcomes: ["ganas", "pierdes", "empate"],
+    try_again: "Entrada no válida. Inténtalo de nuevo: ",
+    column_full: "Entrada no válida o columna llena. Inténtalo de nuevo: ",
+    choose_column: "{}, elige columna (1-{}): ",
+    choose_board: "{}, elige tablero (1-9): ",
+    choose_cell: "{}, tablero {}: elige casilla (1-9): ",
+    one_to_nine: "Escribe un número del 1 al 9.",
+    board_decided: "El tablero {} ya está decidido.",
+    cell_taken: "La casilla {} del tablero {} está ocupada.",
+    undo_limit: "Has llegado al límite de deshacer.",
+    nothing_to_undo: "No hay nada que deshacer.",
+    nothing_to_redo: "No hay nada que rehacer.",
+    time_up: "Se acabó el tiempo: se jugó una casilla al azar.",
+    lost_on_time: "{} pierde por tiempo.",
+    two_players_only: "Abandonar y ofrecer tablas es solo para dos jugadores.",
+    really_resign: "{}, ¿abandonar de verdad? (y/n): ",
+    resigned: "{} abandona.",
+    offers_draw: "{} ofrece tablas. {}, ¿aceptas? (y/n): ",
+    draw_declined: "Tablas rechazadas.",
+    computer_passes: "El ordenador pasa: una jugada libre para ti.",
+    series_winner: "Gana la serie: {}",
+    series_drawn: "Serie empatada",
+    series_score: "{} ({}-{} al mejor de {})",
+    first_record: "Primera victoria registrada: {}.",
+    new_record: "¡Nuevo récord! {}, antes {}.",
+    copied: "Tablero copiado al portapapeles.",
+    no_clipboard: "Sin portapapeles, texto del tablero:",
+    level_names: [
+        "Fácil",
+        "Seguro",
+        "Medio",
+        "Difícil",
+        "Profundidad-{}",
+        "Imposible",
+        "Adaptativo",
+    ],
+    adaptive_note: "Adaptativo: esta partida juega {}.",
+    record_not_saved: "Récord no guardado: {}",
+    logging_off: "Registro desactivado: {}",
+    game_record_off: "Historial de partidas desactivado: {}",
+    csv_off: "Exportación CSV desactivada: {}",
+    computer_plays: "El ordenador ({}) juega {}.",
+    not_a_cell: "No es un número de casilla: {}",
+    move_refused: "Jugada rechazada. {}",
+    retry: "{} Inténtalo de nuevo: ",
+    off_board: "La casilla {} está fuera del tablero.",
+    taken: "La casilla {} ya está ocupada.",
+    game_over: "La partida ya ha terminado.",
+    wrong_board: "La jugada tiene que ir en el tablero {}.",
+    editor_hint: "Editor: los números cambian una casilla, Enter juega, Esc borra",
+    cant_start: "No se puede empezar: {}.",
+    bad_counts: "X necesita tantas marcas como O o una más",
+    already_won: "{} ya ha ganado",
+    board_full: "el tablero está lleno",
+    replay_step: "Repetición {}/{}: {} tomó {}",
+    replay_start: "Repetición 0/{}: posición inicial",
+    replay_controls: "←/→ o Espacio avanzan, Esc o {} = salir",
+    big_board_window: "El tablero grande necesita una ventana de al menos {}×{}; esta es de {}×{}.",
+    big_board_anyway: "Agranda la ventana o pulsa cualquier tecla para jugar igualmente.",
+    boards_heading: "Tableros",
+    panel_controls: "Controles: escribe un número de casilla",
+    moves_heading: "Jugadas:",
+    moves_earlier: "Jugadas ({} anteriores):",
+    nodes: "Posiciones evaluadas por la IA: {}",
+    explain: "El ordenador tomó {}: {}",
+    reasons: [
+        "completando su propia línea",
+        "bloqueando tu línea",
+        "sin amenazas, así que cualquier casilla libre",
+        "el mejor resultado contra cada respuesta",
+        "tomando la casilla desde la que podrías hacer doble amenaza",
+        "abriendo dos líneas a la vez",
+        "obligándote a bloquear en vez de hacer doble amenaza",
+        "sin amenazas, así que la casilla libre más fuerte",
+        "la respuesta de libro a esta apertura",
+        "la mejor posición a unas jugadas vista",
+        "una casilla libre que no te deja jugada ganadora",
+    ],
+    cell_at: "fila {}, columna {}",
+    placed_at: "{} colocada en {}.",
+    empty_cell: "vacía",
+    board_row: "Fila {}: {}.",
+    seconds_left: "quedan {}s",
+    ascii_badges: ["(victoria)", "(empate)", "(campeón)"],
+    accuracy_line: "{} jugó {}/{} óptimas, {} {}/{}",
+    key_moment: "Momento clave: jugada {}, {} jugó {} ({} -> {})",
+    analysis_rows: ["Casilla", "Resultado"],
+    outcome_letters: ["G", "P", "E"],
+    no_mistakes: "Sin errores: cada jugada mantuvo el mejor resultado.",
+    series_running: "Serie: {} {} - {} {}, al mejor de {}",
+    drew: "{} y {} empatan.",
+    beat: "{} gana a {}.",
+    standings_key: "Pulsa cualquier tecla para ver la clasificación...",
+    tournament_one: "Torneo — gana quien llegue a 1 victoria",
+    tournament_title: "Torneo — gana quien llegue a {} victorias",
+    champion: "¡{} es campeón! {}",
+    new_tournament: "n = nuevo torneo, {} = salir: ",
+    next_match: "Siguiente: {} ({}) contra {} ({}), luego {}",
+    next_match_key: "Pulsa cualquier tecla para la siguiente partida, {} para parar: ",
+    games_played: "Partidas jugadas: {}",
+    beat_level: "Ganaste a {} en {} de {} partidas",
+    win_rates: "Victorias: {} {}% · {} {}% · {} jugadas por partida",
+    streak: "{} ganó {} seguidas",
+    no_streak: "ninguna",
+    longest_streak: "Racha más larga: {}",
+};
+
+// Set from --lang or the config file before the first frame, like BIG_CELLS,
+// so that drawing code can reach the words without being passed the options.
+static LANGUAGE: OnceLock<Language> = OnceLock::new();
+
+fn text() -> &'static Strings {
+    match LANGUAGE.get() {
+        Some(Language::Spanish) => &SPANISH,
+        _ => &ENGLISH,
+    }
+}
+
+fn parse_language(code: &str) -> Option<Language> {
+    match code {
+        "en" => Some(Language::English),
+        "es" => Some(Language::Spanish),
+        _ => None,
+    }
+}
+
+// `template` with each `{}` replaced by the next of `values`.
+fn fill(template: &str, values: &[&dyn fmt::Display]) -> String {
+    let mut filled = String::new();
+    for (i, part) in template.split("{}").enumerate() {
+        if i > 0
+            && let Some(value) = values.get(i - 1)
+        {
+            filled.push_str(&value.to_string());
+        }
+        filled.push_str(part);
+    }
+    filled
+}
+
+// A level as it is named in running text, e.g. "Depth-3".
+fn level_name(difficulty: Difficulty) -> String {
+    let [easy, safe, medium, hard, depth, impossible, adaptive] = text().level_names;
+    match difficulty {
+        Difficulty::Easy => easy.to_string(),
+        Difficulty::Safe => safe.to_string(),
+        Difficulty::Medium => medium.to_string(),
+        Difficulty::Hard => hard.to_string(),
+        Difficulty::Depth(plies) => fill(depth, &[&plies]),
+        Difficulty::Impossible => impossible.to_string(),
+        Difficulty::Adaptive => adaptive.to_string(),
+    }
+}
+
+// Why the rules refused a move, as a sentence.
+fn move_error_text(err: &MoveError) -> String {
+    match *err {
+        MoveError::OutOfRange(idx) => fill(text().off_board, &[&(idx + 1)]),
+        MoveError::Occupied(idx) => fill(text().taken, &[&(idx + 1)]),
+        MoveError::GameOver => text().game_over.to_string(),
+        MoveError::WrongBoard(board) => fill(text().wrong_board, &[&(board + 1)]),
+        MoveError::BoardDecided(board) => fill(text().board_decided, &[&(board + 1)]),
+    }
+}
+
+// Why a set-up position can't be played from.
+fn board_error_text(err: &BoardError) -> String {
+    match *err {
+        BoardError::BadCounts => text().bad_counts.to_string(),
+        BoardError::AlreadyWon(mark) => fill(text().already_won, &[&mark]),
+        BoardError::Full => text().board_full.to_string(),
+    }
+}
+
+// `line` cut to what fits between column `x` and the right edge of the
+// terminal, ending in "…" when something had to go. Translations run longer
+// than the English the layout was made for.
+fn fit(line: &str, x: u16) -> String {
+    match size() {
+        Ok((width, _)) => truncate(line, width.saturating_sub(x) as usize),
+        Err(_) => line.to_string(),
+    }
+}
+
+// At most `room` characters of `line`, the last of them "…" if it was cut.
+fn truncate(line: &str, room: usize) -> String {
+    if room == 0 || line.chars().count() <= room {
+        return line.to_string();
+    }
+    let mut cut: String = line.chars().take(room - 1).collect();
+    cut.push('…');
+    cut
+}
+
+// ======================================
+// GAME FLOW
+// ======================================
+
+// Fails with a message for unknown flags and for values that don't parse.
+fn parse_options() -> std::result::Result<Options, String> {
+    let mut options = Options::default();
+    let config = load_config();
+    if let Some(theme) = config.theme {
+        options.theme = theme;
+    }
+    // Applied after the loop so a later --theme doesn't reset them.
+    let mut marks = None;
+    // Read once the board size and rules are known.
+    let mut start = None;
+    // NO_COLOR in the environment (https://no-color.org) works like --no-color.
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        options.theme = Theme::NO_COLOR;
+    }
+    let mut args = std::env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--move-numbers" => options.show_move_numbers = true,
+            "--torus" => options.variant = Variant::Torus,
+            "--early-end" => options.early_end = true,
+            "--self-test" => options.self_test = true,
+            "--log" => options.log_path = Some(flag_value(&mut args, "--log", Some)?),
+            "--csv" => options.csv_path = Some(flag_value(&mut args, "--csv", Some)?),
+            "--replay" => options.replay_path = Some(flag_value(&mut args, "--replay", Some)?),
+            "--show-nodes" => options.show_nodes = true,
+            "--explain" => options.explain = true,
+            "--coin-flip" => options.coin_flip = true,
+            "--show-threats" => options.show_threats = true,
+            "--analysis" => options.analysis = true,
+            "--announce" => options.announce = true,
+            "--compact" => options.compact = true,
+            "--quiet" => options.feedback = Feedback::Silent,
+            "--no-confirm" => options.quit_at_once = true,
+            "--big-board" => options.big_board = true,
+            "--dim-numbers" => options.dim_numbers = true,
+            "--wrap-cursor" => options.wrap_cursor = true,
+            "--ultimate" => options.ultimate = true,
+            "--lang" => {
+                options.language = Some(flag_value(&mut args, "--lang", |code| {
+                    parse_language(&code)
+                })?);
+            }
+            "--seed" => options.seed = Some(flag_value(&mut args, "--seed", |n| n.parse().ok())?),
+            "--names" => {
+                options.names = Some(flag_value(&mut args, "--names", |names| {
+                    parse_names(&names)
+                })?);
+            }
+            "--tournament" => {
+                options.tournament = Some(flag_value(&mut args, "--tournament", |names| {
+                    parse_players(&names)
+                })?);
+            }
+            "--first-to" => {
+                options.first_to = Some(flag_value(&mut args, "--first-to", |n| {
+                    n.parse().ok().filter(|&n| n >= 1)
+                })?);
+            }
+            "--gravity" => {
+                options.gravity = Some(flag_value(&mut args, "--gravity", |size| {
+                    parse_grid_size(&size)
+                })?);
+            }
+            "--connect" => {
+                options.connect = Some(flag_value(&mut args, "--connect", |k| {
+                    k.parse().ok().filter(|&k| k >= 2)
+                })?);
+            }
+            "--size" => {
+                options.size = Some(flag_value(&mut args, "--size", |n| parse_size(&n))?);
+            }
+            "--no-color" => options.theme = Theme::NO_COLOR,
+            "--theme" => {
+                options.theme = flag_value(&mut args, "--theme", |name| Theme::named(&name))?
+            }
+            "--grid" => {
+                options.grid = flag_value(&mut args, "--grid", |name| GridStyle::named(&name))?
+            }
+            "--time-attack" => {
+                options.time_attack = Some(flag_value(&mut args, "--time-attack", |secs| {
+                    secs.parse().ok().filter(|&secs| secs > 0)
+                })?);
+            }
+            "--think-time" => {
+                options.think_ms =
+                    Some(flag_value(&mut args, "--think-time", |ms| ms.parse().ok())?);
+            }
+            "--undo-limit" => {
+                options.undo_limit =
+                    Some(flag_value(&mut args, "--undo-limit", |n| n.parse().ok())?);
+            }
+            "--feedback" => {
+                options.feedback =
+                    flag_value(&mut args, "--feedback", |name| parse_feedback(&name))?;
+            }
+            "--mode" => {
+                options.mode = Some(flag_value(&mut args, "--mode", |mode| parse_mode(&mode))?);
+            }
+            "--difficulty" => {
+                options.difficulty = Some(flag_value(&mut args, "--difficulty", |level| {
+                    parse_difficulty(&level)
+                })?);
+            }
+            "--first" => {
+                options.player_first =
+                    Some(flag_value(&mut args, "--first", |side| parse_first(&side))?);
+            }
+            "--marks" => marks = Some(flag_value(&mut args, "--marks", |pair| parse_marks(&pair))?),
+            "--start" => start = Some(flag_value(&mut args, "--start", Some)?),
+            "--json" => options.json = true,
+            "--help" | "-h" => options.show_help = true,
+            "--handicap" => {
+                // k is optional, so whatever follows is only taken if it's a number.
+                let k = args.peek().and_then(|k| k.parse().ok());
+                if k.is_some() {
+                    args.next();
+                }
+                options.handicap = k.unwrap_or(1);
+            }
+            "--keys" => {
+                let keymap = options.keymap;
+                options.keymap =
+                    flag_value(&mut args, "--keys", |layout| keymap.with_movement(&layout))?;
+            }
+            "--bind" => {
+                let keymap = options.keymap;
+                options.keymap =
+                    flag_value(&mut args, "--bind", |binding| bind_key(keymap, &binding))?;
+            }
+            other => return Err(format!("Unknown option {}", other)),
+        }
+    }
+    if options.tournament.is_some() {
+        if options.mode == Some(ModeFlag::Ai)
+            || options.difficulty.is_some()
+            || options.player_first.is_some()
+        {
+            return Err("--tournament is for people only, without --mode ai".to_string());
+        }
+        if options.gravity.is_some() || options.json {
+            return Err("--tournament doesn't work with --gravity or --json".to_string());
+        }
+        // Every match is a two-player game.
+        options.mode = Some(ModeFlag::Friend);
+    } else if options.first_to.is_some() {
+        return Err("--first-to needs --tournament".to_string());
+    }
+    if options.ultimate {
+        if options.mode == Some(ModeFlag::Ai)
+            || options.difficulty.is_some()
+            || options.player_first.is_some()
+        {
+            return Err("--ultimate is for two players, without --mode ai".to_string());
+        }
+        if options.gravity.is_some() || options.tournament.is_some() || options.json {
+            return Err(
+                "--ultimate doesn't work with --gravity, --tournament or --json".to_string(),
+            );
+        }
+        if options.size.is_some() || start.is_some() || options.variant == Variant::Torus {
+            return Err(
+                "--ultimate plays on its own boards, without --size, --start or --torus"
+                    .to_string(),
+            );
+        }
+    }
+    if options.time_attack.is_some()
+        && (options.gravity.is_some() || options.ultimate || options.json)
+    {
+        return Err("--time-attack doesn't work with --gravity, --ultimate or --json".to_string());
+    }
+    // No line that long fits on the grid, so the game could only be drawn.
+    if let (Some(k), Some((width, height))) = (options.connect, options.gravity)
+        && k > width.max(height)
+    {
+        return Err(format!(
+            "--connect {} can't fit on a {}×{} --gravity board; go up to {}",
+            k,
+            width,
+            height,
+            width.max(height)
+        ));
+    }
+    if options.names.is_some() {
+        if options.mode == Some(ModeFlag::Ai)
+            || options.difficulty.is_some()
+            || options.player_first.is_some()
+        {
+            return Err("--names is for two players, without --mode ai".to_string());
+        }
+        if options.tournament.is_some() || options.json {
+            return Err("--names doesn't work with --tournament or --json".to_string());
+        }
+        options.mode = Some(ModeFlag::Friend);
+    }
+    // The config file fills in what the command line left out. Its AI settings
+    // give way to --mode friend, and its size to the size of a --start position.
+    options.mode = options.mode.or(config.mode);
+    if options.mode != Some(ModeFlag::Friend) {
+        options.difficulty = options.difficulty.or(config.difficulty);
+        options.player_first = options.player_first.or(config.player_first);
+    }
+    if start.is_none() {
+        options.size = options.size.or(config.size);
+    }
+    options.language = options.language.or(config.language);
+    options.csv_path = options.csv_path.or(config.csv_path);
+    // The AI settings only make sense for an AI game, which they then imply.
+    if options.difficulty.is_some() || options.player_first.is_some() {
+        match options.mode {
+            Some(ModeFlag::Friend) => {
+                return Err("--difficulty and --first need --mode ai".to_string());
+            }
+            _ => options.mode = Some(ModeFlag::Ai),
+        }
+    }
+    if options.difficulty == Some(Difficulty::Impossible)
+        && options.board_size() > MAX_IMPOSSIBLE_SIZE
+    {
+        return Err("--difficulty impossible only works on boards up to 4×4".to_string());
+    }
+    if let Some(Difficulty::Depth(depth)) = options.difficulty
+        && depth > max_search_depth(options.board_size())
+    {
+        return Err(format!(
+            "--difficulty depth-{} is too deep for this board; go up to depth-{}",
+            depth,
+            max_search_depth(options.board_size())
+        ));
+    }
+    if let Some(marks) = marks {
+        options.theme.marks = marks;
+    }
+    // The numbers stay as a guide to the keys but step back behind the marks.
+    if options.dim_numbers || config.dim_numbers {
+        options.theme.free = options.theme.muted;
+    }
+    if let Some(text) = start {
+        options.start =
+            Some(parse_start(&text, &mut options).map_err(|e| format!("--start: {}", e))?);
+    }
+    Ok(options)
+}
+
+// The board size follows from the position unless --size gave one already.
+fn parse_start(text: &str, options: &mut Options) -> std::result::Result<Vec<char>, String> {
+    if options.gravity.is_some() {
+        return Err("not available with --gravity".to_string());
+    }
+    let board = parse_board(text).map_err(|e| e.to_string())?;
+    let size = board_size(&board);
+    match options.size {
+        Some(wanted) if wanted != size => {
+            return Err(format!(
+                "{} cells don't fit a {}×{} board",
+                board.len(),
+                wanted,
+                wanted
+            ));
+        }
+        _ if !(3..=MAX_BOARD_SIZE).contains(&size) => {
+            return Err(format!("boards go from 3×3 to {0}×{0}", MAX_BOARD_SIZE));
+        }
+        _ => options.size = Some(size),
+    }
+    let lines = win_lines(size, options.win_length(), options.variant);
+    validate_board(&board, &lines).map_err(|e| e.to_string())?;
+    Ok(board)
+}
+
+fn parse_mode(mode: &str) -> Option<ModeFlag> {
+    match mode {
+        "friend" => Some(ModeFlag::Friend),
+        "ai" => Some(ModeFlag::Ai),
+        _ => None,
+    }
+}
+
+fn parse_feedback(name: &str) -> Option<Feedback> {
+    match name {
+        "beep" => Some(Feedback::Beep),
+        "flash" => Some(Feedback::Flash),
+        "silent" => Some(Feedback::Silent),
+        _ => None,
+    }
+}
+
+// A level's name, or depth-<n> for the Depth level searching n moves ahead.
+fn parse_difficulty(level: &str) -> Option<Difficulty> {
+    if let Some(depth) = level.to_ascii_lowercase().strip_prefix("depth-") {
+        return depth
+            .parse()
+            .ok()
+            .filter(|depth| (1..=MAX_SEARCH_DEPTH).contains(depth))
+            .map(Difficulty::Depth);
+    }
+    DIFFICULTIES
+        .into_iter()
+        .chain([Difficulty::Safe, Difficulty::Adaptive])
+        .find(|d| d.to_string().eq_ignore_ascii_case(level))
+}
+
+// Whether the human moves first: "player" or "computer".
+fn parse_first(side: &str) -> Option<bool> {
+    match side {
+        "player" => Some(true),
+        "computer" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_size(n: &str) -> Option<usize> {
+    n.parse().ok().filter(|n| (3..=MAX_BOARD_SIZE).contains(n))
+}
+
+// The `key = value` lines of a config file; blank lines and # comments are
+// skipped. Values are quoted strings, except the size, which is a number, and
+// dim-numbers, which is true or false.
+fn parse_config(text: &str) -> std::result::Result<Config, String> {
+    let mut config = Config::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bad_line = || format!("line {}: {}", number + 1, line);
+        let (key, value) = line.split_once('=').ok_or_else(bad_line)?;
+        let value = value.trim();
+        let text = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'));
+        let parsed = match key.trim() {
+            "mode" => text
+                .and_then(parse_mode)
+                .map(|mode| config.mode = Some(mode)),
+            "difficulty" => text
+                .and_then(parse_difficulty)
+                .map(|level| config.difficulty = Some(level)),
+            "first" => text
+                .and_then(parse_first)
+                .map(|first| config.player_first = Some(first)),
+            "theme" => text
+                .and_then(Theme::named)
+                .map(|theme| config.theme = Some(theme)),
+            "size" => parse_size(value).map(|size| config.size = Some(size)),
+            "dim-numbers" => value.parse().ok().map(|dim| config.dim_numbers = dim),
+            "lang" => text
+                .and_then(parse_language)
+                .map(|language| config.language = Some(language)),
+            "csv" => text
+                .filter(|path| !path.is_empty())
+                .map(|path| config.csv_path = Some(path.to_string())),
+            _ => None,
+        };
+        parsed.ok_or_else(bad_line)?;
+    }
+    Ok(config)
+}
+
+// "Alice,Bob,Carol" → the three names. A tournament needs at least three
+// players, each named once, and short names so the standings line up.
+fn parse_players(names: &str) -> Option<Vec<String>> {
+    let players: Vec<String> = names
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .collect();
+    let unique: HashSet<&String> = players.iter().collect();
+    let fits = players
+        .iter()
+        .all(|name| !name.is_empty() && name.chars().count() <= 16);
+    (players.len() >= 3 && unique.len() == players.len() && fits).then_some(players)
+}
+
+// "Alice,Bob" → X's and O's names: two different ones, short enough that both
+// fit on the scoreboard line of the side panel.
+fn parse_names(names: &str) -> Option<[String; 2]> {
+    let names: Vec<String> = names
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .collect();
+    let [x, o]: [String; 2] = names.try_into().ok()?;
+    let fits = |name: &String| !name.is_empty() && name.chars().count() <= 10;
+    (fits(&x) && fits(&o) && x != o).then_some([x, o])
+}
+
+// "#@" → ['#', '@'] for X and O. Digits are refused since free cells show their
+// numbers, as are blanks and a repeated mark.
+fn parse_marks(pair: &str) -> Option<[char; 2]> {
+    let mut chars = pair.chars();
+    let (Some(x), Some(o), None) = (chars.next(), chars.next(), chars.next()) else {
+        return None;
+    };
+    let usable = |c: char| !c.is_ascii_digit() && !c.is_whitespace() && c != '.';
+    (usable(x) && usable(o) && x != o).then_some([x, o])
+}
+
+// The argument after `flag`, run through `parse`; missing or unparsable
+// values become an error naming the flag.
+fn flag_value<T>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+    parse: impl FnOnce(String) -> Option<T>,
+) -> std::result::Result<T, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("{} needs a value", flag))?;
+    parse(value.clone()).ok_or_else(|| format!("Invalid value for {}: {}", flag, value))
+}
+
+// --json: games without the terminal interface for scripts. Human moves are
+// cell numbers, one per line on stdin; each finished game prints a single JSON
+// line on stdout and complaints go to stderr, so stdout stays parseable. Ends
+// at the end of input. Scores and game records are left alone.
+fn run_json(options: &Options, rng: &mut StdRng) -> Result<()> {
+    let chosen_mode = scripted_mode(options);
+    let mut input = std::io::stdin().lines();
+    let mut out = stdout();
+    let mut adaptive_record = (0, 0);
+    loop {
+        let game_mode = resolve_adaptive(chosen_mode, adaptive_record, options.board_size());
+        let mut state = starting_state(options)?;
+        let mut history: Vec<(char, usize)> = Vec::new();
+        while !state.result().is_over() {
+            let pos = if is_human_turn(game_mode, state.current_player) {
+                let Some(line) = input.next() else {
+                    return Ok(());
+                };
+                match parse_line_move(&line?, &state) {
+                    Ok(pos) => pos,
+                    Err(message) => {
+                        eprintln!("{}", message);
+                        continue;
+                    }
+                }
+            } else {
+                let GameMode::AI(difficulty, ..) = game_mode else {
+                    unreachable!("only AI games have computer turns here");
+                };
+                pick_ai_move(
+                    &state.board,
+                    state.lines(),
+                    difficulty,
+                    state.current_player,
+                    rng,
+                )
+                .pos
+            };
+            let mover = state.current_player;
+            match state.apply_move(pos) {
+                Ok(()) => history.push((mover, pos)),
+                Err(err) => eprintln!("Move refused: {}", err),
+            }
+        }
+        let winner = state.result().winner();
+        record_adaptive(
+            &mut adaptive_record,
+            chosen_mode,
+            winner,
+            options.board_size(),
+        );
+        writeln!(out, "{}", format_json_result(game_mode, &history, winner))?;
+        out.flush()?;
+    }
+}
+
+// Used instead of the screen when stdout isn't a terminal, e.g. in CI or with
+// the output saved to a file: the board is printed as plain lines, numbers in
+// the free cells, after every move, and moves are read one per line from stdin
+// as with --json. Each game starts as the last one ends, until the input does.
+// Scores and game records are left alone.
+fn run_plain(options: &Options, rng: &mut StdRng) -> Result<()> {
+    let chosen_mode = scripted_mode(options);
+    let theme = &options.theme;
+    let players = seat_players(options.names(), theme);
+    let mut input = std::io::stdin().lines();
+    let mut out = stdout();
+    let mut adaptive_record = (0, 0);
+    loop {
+        let game_mode = resolve_adaptive(chosen_mode, adaptive_record, options.board_size());
+        let mut state = starting_state(options)?;
+        let cells = state.board.len();
+        writeln!(
+            out,
+            "{}",
+            numbered_board_text(&state.board, theme, options.grid)
+        )?;
+        while !state.result().is_over() {
+            let player = state.current_player;
+            let pos = if is_human_turn(game_mode, player) {
+                match game_mode {
+                    GameMode::Friend => write!(
+                        out,
+                        "{}",
+                        fill(
+                            text().friend_turn,
+                            &[&seat(&players, player).label(), &cells]
+                        )
+                    )?,
+                    _ => write!(
+                        out,
+                        "{}",
+                        fill(text().your_turn, &[&theme.glyph(player), &cells])
+                    )?,
+                }
+                out.flush()?;
+                let Some(line) = input.next() else {
+                    writeln!(out)?;
+                    return Ok(());
+                };
+                match parse_line_move(&line?, &state) {
+                    Ok(pos) => pos,
+                    Err(message) => {
+                        writeln!(out, "{}", message)?;
+                        continue;
+                    }
+                }
+            } else {
+                let GameMode::AI(difficulty, ..) = game_mode else {
+                    unreachable!("only AI games have computer turns here");
+                };
+                let pos = pick_ai_move(&state.board, state.lines(), difficulty, player, rng).pos;
+                writeln!(
+                    out,
+                    "{}",
+                    fill(text().computer_plays, &[&theme.glyph(player), &(pos + 1)])
+                )?;
+                pos
+            };
+            state.apply_move(pos).map_err(Error::other)?;
+            writeln!(
+                out,
+                "\n{}",
+                numbered_board_text(&state.board, theme, options.grid)
+            )?;
+        }
+        let winner = state.result().winner();
+        match winner {
+            Some(mark) => writeln!(
+                out,
+                "{}\n",
+                fill(text().wins, &[&seat(&players, mark).label()])
+            )?,
+            None => writeln!(out, "{}\n", text().draw)?,
+        }
+        record_adaptive(
+            &mut adaptive_record,
+            chosen_mode,
+            winner,
+            options.board_size(),
+        );
+    }
+}
+
+// The game --json and the plain renderer play: against the computer with
+// --mode ai (Hard and player-first unless set), otherwise two players.
+fn scripted_mode(options: &Options) -> GameMode {
+    match options.mode {
+        Some(ModeFlag::Ai) => GameMode::AI(
+            options.difficulty.unwrap_or(Difficulty::Hard),
+            options.player_first.unwrap_or(true),
+            0,
+        ),
+        _ => GameMode::Friend,
+    }
+}
+
+fn starting_state(options: &Options) -> Result<GameState> {
+    match &options.start {
+        // Checked when the options were read.
+        Some(board) => GameState::from_board(board.clone(), options.win_length(), options.variant)
+            .map_err(Error::other),
+        None => Ok(GameState::new(
+            options.board_size(),
+            options.win_length(),
+            options.variant,
+        )),
+    }
+}
+
+// A cell number typed on a line of its own, checked against the position.
+fn parse_line_move(line: &str, state: &GameState) -> std::result::Result<usize, String> {
+    let Some(pos) = line
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|cell| cell.checked_sub(1))
+    else {
+        return Err(fill(text().not_a_cell, &[&format!("{:?}", line.trim())]));
+    };
+    state
+        .validate_move(pos)
+        .map(|()| pos)
+        .map_err(|err| fill(text().move_refused, &[&move_error_text(&err)]))
+}
+
+// {"winner":"X","moves":[4,0,8],"mode":"ai-hard"}: winner null for a draw,
+// moves as 0-based cells in the order played.
+fn format_json_result(mode: GameMode, history: &[(char, usize)], winner: Option<char>) -> String {
+    let winner = match winner {
+        Some(mark) => format!("\"{}\"", mark),
+        None => "null".to_string(),
+    };
+    let moves: Vec<String> = history.iter().map(|&(_, idx)| idx.to_string()).collect();
+    let mode = match mode {
+        GameMode::Friend => "friend".to_string(),
+        GameMode::AI(difficulty, ..) => format!("ai-{}", difficulty).to_lowercase(),
+        GameMode::AIvsAI(..) => "ai-vs-ai".to_string(),
+    };
+    format!(
+        "{{\"winner\":{},\"moves\":[{}],\"mode\":\"{}\"}}",
+        winner,
+        moves.join(","),
+        mode
+    )
+}
+
+// Hot-seat game on a width × height grid where marks drop to the lowest free
+// cell of the chosen column; first to connect `--connect` (default 4) wins.
+fn run_gravity(
+    options: &Options,
+    width: usize,
+    height: usize,
+    input: &mut dyn InputSource,
+) -> Result<()> {
+    let lines = grid_lines(width, height, options.connect.unwrap_or(4));
+    let mut stdout = stdout();
+    let layout = grid_layout(height);
+    let players = seat_players(options.names(), &options.theme);
+
+    let mut score_player_x = Score::default();
+    let mut score_player_o = Score::default();
+    let mut score_draws = Score::default();
+
+    loop {
+        let mut cells = vec![EMPTY; width * height];
+        let mut current_player = PLAYER_X;
+
+        let winner = loop {
+            draw_gravity_board(&mut stdout, options, &cells, width, None)?;
+            let (x, y) = layout.hint;
+            queue!(
+                stdout,
+                MoveTo(x, y),
+                SetForegroundColor(options.theme.hint),
+                Print(fill(
+                    text().choose_column,
+                    &[&seat(&players, current_player).label(), &width]
+                )),
+                ResetColor
+            )?;
+
+            let Some(col) = get_column(&mut stdout, input, &cells, width)? else {
+                continue;
+            };
+            drop_mark(&mut cells, width, col, current_player);
+
+            match game_result(&cells, &lines) {
+                GameResult::Ongoing => {}
+                GameResult::Draw => {
+                    draw_gravity_board(&mut stdout, options, &cells, width, None)?;
+                    print_draw(&mut stdout, layout, &options.theme)?;
+                    break None;
+                }
+                GameResult::XWins(line) | GameResult::OWins(line) => {
+                    draw_gravity_board(&mut stdout, options, &cells, width, Some(&line))?;
+                    print_winner(
+                        &mut stdout,
+                        layout,
+                        seat(&players, current_player),
+                        None,
+                        options,
+                    )?;
+                    break Some(current_player);
+                }
+            }
+            current_player = switch_player(current_player);
+        };
+
+        match winner {
+            Some(PLAYER_X) => score_player_x.increment(),
+            Some(_) => score_player_o.increment(),
+            None => score_draws.increment(),
+        }
+        print_scoreboard(
+            &mut stdout,
+            layout,
+            score_player_x,
+            score_player_o,
+            score_draws,
+            &players,
+            &options.theme,
+        )?;
+
+        let (x, y) = layout.prompt;
+        queue!(
+            stdout,
+            MoveTo(x, y),
+            Print(fill(text().play_again, &[&"y/n"]))
+        )?;
+        stdout.flush()?;
+        loop {
+            if let Input::Key(event) = next_input(input)?
+                && let KeyCode::Char(c) = event.code
+            {
+                match c {
+                    'y' | 'Y' => break,
+                    'n' | 'N' => return Ok(()),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+// Hot-seat ultimate tic-tac-toe (see `UltimateState`). A digit picks the small
+// board when the last move left a free choice, then another picks the cell;
+// Esc takes back the choice of board.
+fn run_ultimate(options: &Options, input: &mut dyn InputSource) -> Result<()> {
+    let mut stdout = stdout();
+    let layout = grid_layout(ULTIMATE_ROWS);
+    let players = seat_players(options.names(), &options.theme);
+
+    let mut score_player_x = Score::default();
+    let mut score_player_o = Score::default();
+    let mut score_draws = Score::default();
+
+    loop {
+        let mut state = UltimateState::new();
+        let mut chosen: Option<usize> = None;
+        let mut error: Option<String> = None;
+
+        let winner = loop {
+            let board = state.forced.or(chosen);
+            let active = board.map_or_else(|| state.playable_boards(), |board| vec![board]);
+            draw_ultimate_board(&mut stdout, options, &state, &active, None)?;
+            let label = seat(&players, state.current_player).label();
+            let hint = match board {
+                Some(board) => fill(text().choose_cell, &[&label, &(board + 1)]),
+                None => fill(text().choose_board, &[&label]),
+            };
+            let (x, y) = layout.hint;
+            queue!(
+                stdout,
+                MoveTo(x, y),
+                SetForegroundColor(options.theme.hint),
+                Print(fit(&hint, x)),
+                ResetColor
+            )?;
+            if let Some(message) = &error {
+                queue!(
+                    stdout,
+                    MoveTo(x, y + 1),
+                    SetForegroundColor(options.theme.alert),
+                    Print(fit(message, x)),
+                    ResetColor
+                )?;
+            }
+            stdout.flush()?;
+
+            let Input::Key(event) = next_input(input)? else {
+                continue;
+            };
+            error = None;
+            let digit = match event.code {
+                KeyCode::Char(c) => c.to_digit(10).filter(|&d| d >= 1).map(|d| d as usize - 1),
+                KeyCode::Esc | KeyCode::Backspace => {
+                    chosen = None;
+                    continue;
+                }
+                _ => None,
+            };
+            let Some(pick) = digit else {
+                error = Some(text().one_to_nine.to_string());
+                continue;
+            };
+            let Some(board) = board else {
+                if active.contains(&pick) {
+                    chosen = Some(pick);
+                } else {
+                    error = Some(fill(text().board_decided, &[&(pick + 1)]));
+                }
+                continue;
+            };
+            if let Err(err) = state.apply_move(board, pick) {
+                error = Some(match err {
+                    MoveError::Occupied(cell) => {
+                        fill(text().cell_taken, &[&(cell + 1), &(board + 1)])
+                    }
+                    MoveError::BoardDecided(board) => fill(text().board_decided, &[&(board + 1)]),
+                    MoveError::WrongBoard(board) => fill(text().wrong_board, &[&(board + 1)]),
+                    other => move_error_text(&other),
+                });
+                continue;
+            }
+            chosen = None;
+
+            match state.result() {
+                GameResult::Ongoing => {}
+                GameResult::Draw => {
+                    draw_ultimate_board(&mut stdout, options, &state, &[], None)?;
+                    print_draw(&mut stdout, layout, &options.theme)?;
+                    break None;
+                }
+                GameResult::XWins(line) | GameResult::OWins(line) => {
+                    let mark = switch_player(state.current_player);
+                    draw_ultimate_board(&mut stdout, options, &state, &[], Some(&line))?;
+                    print_winner(&mut stdout, layout, seat(&players, mark), None, options)?;
+                    break Some(mark);
+                }
+            }
+        };
+
+        match winner {
+            Some(PLAYER_X) => score_player_x.increment(),
+            Some(_) => score_player_o.increment(),
+            None => score_draws.increment(),
+        }
+        print_scoreboard(
+            &mut stdout,
+            layout,
+            score_player_x,
+            score_player_o,
+            score_draws,
+            &players,
+            &options.theme,
+        )?;
+
+        let (x, y) = layout.prompt;
+        queue!(
+            stdout,
+            MoveTo(x, y),
+            Print(fill(text().play_again, &[&"y/n"]))
+        )?;
+        stdout.flush()?;
+        loop {
+            if let Input::Key(event) = next_input(input)?
+                && let KeyCode::Char(c) = event.code
+            {
+                match c {
+                    'y' | 'Y' => break,
+                    'n' | 'N' => return Ok(()),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+// "7x6" → (7, 6). Columns are picked with a single digit, so at most 9 of them.
+fn parse_grid_size(size: &str) -> Option<(usize, usize)> {
+    let (width, height) = size.split_once('x')?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    ((1..=9).contains(&width) && (1..=16).contains(&height)).then_some((width, height))
+}
+
+// Applies one "action=key" binding, e.g. "copy=k". None for a malformed spec,
+// an unknown action, '?' (which always opens the help screen), a digit (which
+// always picks a cell) or a key another action already uses.
+fn bind_key(mut keymap: Keymap, binding: &str) -> Option<Keymap> {
+    let (action, key) = binding.split_once('=')?;
+    let mut chars = key.chars();
+    let (Some(key), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+    if key == '?' || key.is_ascii_digit() {
+        return None;
+    }
+    match action {
+        "copy" => keymap.copy_board = key,
+        "replay" => keymap.replay = key,
+        "read" => keymap.read_board = key,
+        "undo" => keymap.undo = key,
+        "redo" => keymap.redo = key,
+        "hint" => keymap.hint = key,
+        "quit" => keymap.quit = key,
+        "resign" => keymap.resign = key,
+        "draw" => keymap.offer_draw = key,
+        "up" => keymap.up = KeyCode::Char(key),
+        "down" => keymap.down = KeyCode::Char(key),
+        "left" => keymap.left = KeyCode::Char(key),
+        "right" => keymap.right = KeyCode::Char(key),
+        "place" => keymap.place = KeyCode::Char(key),
+        _ => return None,
+    }
+    (!keymap.has_clash()).then_some(keymap)
+}
+
+// Human-readable list of the active bindings, shown on the welcome screen.
+fn control_lines(keymap: &Keymap, cells: usize) -> Vec<String> {
+    let pad = |key: char| format!("{:<3}", key);
+    let lines = &text().control_lines;
+    vec![
+        fill(lines[0], &[&cells]),
+        fill(lines[1], &[&movement_keys(keymap)]),
+        lines[2].to_string(),
+        fill(lines[3], &[&keymap.undo, &keymap.redo]),
+        fill(lines[4], &[&pad(keymap.hint)]),
+        fill(lines[5], &[&keymap.quit]),
+        fill(lines[6], &[&keymap.resign, &keymap.offer_draw]),
+        fill(lines[7], &[&pad(keymap.copy_board)]),
+        fill(lines[8], &[&pad(keymap.replay)]),
+        fill(lines[9], &[&pad(keymap.read_board)]),
+        lines[10].to_string(),
+    ]
+}
+
+// "arrows + Enter" for the arrow keys, otherwise the four keys and the place
+// key, e.g. "w/a/s/d + e".
+fn movement_keys(keymap: &Keymap) -> String {
+    let name = |code: KeyCode| match code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Up => "↑".to_string(),
+        KeyCode::Down => "↓".to_string(),
+        KeyCode::Left => "←".to_string(),
+        KeyCode::Right => "→".to_string(),
+        other => format!("{:?}", other),
+    };
+    let arrows = [KeyCode::Up, KeyCode::Left, KeyCode::Down, KeyCode::Right];
+    let keys = [keymap.up, keymap.left, keymap.down, keymap.right];
+    let moves = if keys == arrows {
+        "arrows".to_string()
+    } else {
+        keys.map(name).join("/")
+    };
+    format!("{} + {}", moves, name(keymap.place))
+}
+
+fn show_welcome_screen(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    options: &Options,
+) -> Result<()> {
+    draw_welcome_screen(stdout, options)?;
+    // wait any key, drawing again after a resize
+    loop {
+        match next_input(input)? {
+            Input::Key(_) => break,
+            Input::Resize => draw_welcome_screen(stdout, options)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn draw_welcome_screen(stdout: &mut impl Write, options: &Options) -> Result<()> {
+    clear_screen(stdout, options)?;
+    queue!(stdout, MoveTo(8, 0), Print(fit(text().welcome, 8)))?;
+    queue!(
+        stdout,
+        MoveTo(8, 2),
+        Print(fit(&fill(text().win_rule, &[&options.win_length()]), 8))
+    )?;
+    if options.variant == Variant::Torus {
+        queue!(stdout, MoveTo(8, 3), Print(fit(text().torus_rule, 8)))?;
+    }
+    queue!(stdout, MoveTo(8, 5), Print(text().controls))?;
+    let size = options.board_size();
+    let controls = control_lines(&options.keymap, size * size);
+    for (row, line) in controls.iter().enumerate() {
+        queue!(stdout, MoveTo(10, 6 + row as u16), Print(fit(line, 10)))?;
+    }
+    queue!(
+        stdout,
+        MoveTo(8, 7 + controls.len() as u16),
+        Print(fit(text().press_any_key, 8))
+    )?;
+    if let Some(seed) = options.seed {
+        queue!(
+            stdout,
+            MoveTo(8, 9 + controls.len() as u16),
+            SetForegroundColor(options.theme.hint),
+            Print(fit(&fill(text().seed, &[&seed, &seed]), 8)),
+            ResetColor
+        )?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// What the help screen lists: the controls and numbering for this session's
+// board, and the colours of its theme.
+struct HelpScreen {
+    lines: Vec<String>,
+    theme: Theme,
+    compact: bool,
+}
+
+// Set once the screen is taken over, like BIG_CELLS; `next_input` opens the
+// help screen from any prompt, where the options aren't to hand.
+static HELP: OnceLock<HelpScreen> = OnceLock::new();
+
+fn help_screen(options: &Options) -> HelpScreen {
+    let cells = match options.gravity {
+        Some((width, _)) => width,
+        None if options.ultimate => 9,
+        None => options.board_size() * options.board_size(),
+    };
+    let mut lines = control_lines(&options.keymap, cells);
+    lines.push(String::new());
+    match options.gravity {
+        Some((width, _)) => lines.push(fill(text().help_columns, &[&width])),
+        None if options.ultimate => lines.push(text().help_ultimate.to_string()),
+        None => {
+            lines.push(fill(text().help_numbering, &[&cells]));
+            if cells > 9 {
+                lines.push(text().help_digits.to_string());
+            }
+        }
+    }
+    HelpScreen {
+        lines,
+        theme: options.theme,
+        compact: options.compact,
+    }
+}
+
+// Controls, cell numbering and what each colour means, over the whole screen.
+fn render_help(stdout: &mut impl Write) -> Result<()> {
+    let Some(help) = HELP.get() else {
+        return Ok(());
+    };
+    let theme = &help.theme;
+    wipe_screen(stdout, help.compact)?;
+    queue!(
+        stdout,
+        MoveTo(2, 0),
+        SetForegroundColor(theme.hint),
+        Print(fit(text().help_title, 2)),
+        ResetColor,
+        MoveTo(2, 2),
+        Print(text().controls)
+    )?;
+    for (row, line) in help.lines.iter().enumerate() {
+        queue!(stdout, MoveTo(4, 3 + row as u16), Print(fit(line, 4)))?;
+    }
+    let top = 4 + help.lines.len() as u16;
+    queue!(stdout, MoveTo(2, top), Print(text().colours))?;
+    let swatch = if theme.ascii { "###" } else { "■■■" };
+    let colours = [
+        theme.x,
+        theme.o,
+        theme.highlight,
+        theme.cursor,
+        theme.hint,
+        theme.computer,
+        theme.alert,
+    ];
+    for (row, (colour, label)) in colours.into_iter().zip(text().legend).enumerate() {
+        queue!(stdout, MoveTo(4, top + 1 + row as u16))?;
+        // The cursor is a background, so its sample is too.
+        if row == 3 {
+            queue!(stdout, SetBackgroundColor(colour), Print("   "))?;
+        } else {
+            queue!(stdout, SetForegroundColor(colour), Print(swatch))?;
+        }
+        queue!(stdout, ResetColor, Print(format!("  {}", fit(label, 9))))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// Shows the help screen until a key is pressed (that key is used up), then
+// clears it for the caller to draw its screen again.
+fn show_help(stdout: &mut impl Write, input: &mut dyn InputSource) -> Result<()> {
+    render_help(stdout)?;
+    loop {
+        match classify_event(input.next_event()?)? {
+            Input::Key(_) => break,
+            Input::Resize => render_help(stdout)?,
+            _ => {}
+        }
+    }
+    wipe_screen(stdout, HELP.get().is_some_and(|help| help.compact))
+}
+
+// The game --mode asks for, prompting only for AI settings not given as flags.
+fn preset_game_mode(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    options: &Options,
+) -> Result<GameMode> {
+    if options.mode == Some(ModeFlag::Friend) {
+        return Ok(GameMode::Friend);
+    }
+    let asks = options.difficulty.is_none() || options.player_first.is_none();
+    if asks {
+        clear_screen(stdout, options)?;
+        print_menu_line(stdout, 0, text().ai_game_title)?;
+    }
+    let difficulty = match options.difficulty {
+        Some(difficulty) => difficulty,
+        None => ask_difficulty(
+            stdout,
+            input,
+            options.board_size(),
+            4,
+            text().select_difficulty,
+            true,
+        )?,
+    };
+    let player_first = match options.player_first {
+        Some(first) => first,
+        None => ask_first_player(stdout, input)?,
+    };
+    Ok(GameMode::AI(difficulty, player_first, 0))
+}
+
+// Pressing 'e' opens the position editor, which fills `board` before the mode is chosen.
+fn ask_game_mode(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    options: &Options,
+    lines: &[Vec<usize>],
+    board: &mut Vec<char>,
+) -> Result<GameMode> {
+    clear_screen(stdout, options)?;
+    print_menu_line(stdout, 0, text().title)?;
+    let mut prompt = text().mode_menu;
+
+    loop {
+        match read_menu_key(stdout, input, 2, prompt)? {
+            'f' | 'F' => return Ok(GameMode::Friend),
+            'a' | 'A' => {
+                let difficulty = ask_difficulty(
+                    stdout,
+                    input,
+                    board_size(board),
+                    4,
+                    text().select_difficulty,
+                    true,
+                )?;
+                let player_first = ask_first_player(stdout, input)?;
+                let free_moves = ask_free_moves(stdout, input)?;
+                return Ok(GameMode::AI(difficulty, player_first, free_moves));
+            }
+            'd' | 'D' => {
+                let [x, o] = options.theme.marks;
+                let size = board_size(board);
+                let x_difficulty = ask_difficulty(
+                    stdout,
+                    input,
+                    size,
+                    4,
+                    &fill(text().difficulty_of, &[&x]),
+                    false,
+                )?;
+                let o_difficulty = ask_difficulty(
+                    stdout,
+                    input,
+                    size,
+                    6,
+                    &fill(text().difficulty_of, &[&o]),
+                    false,
+                )?;
+                return Ok(GameMode::AIvsAI(x_difficulty, o_difficulty));
+            }
+            'e' | 'E' => {
+                *board = edit_position(stdout, input, options, lines)?;
+                clear_screen(stdout, options)?;
+                print_menu_line(stdout, 0, text().title)?;
+                prompt = text().mode_menu_ready;
+            }
+            _ => {
+                queue!(
+                    stdout,
+                    Print(format!("\n{}", invalid_input(&["f", "a", "d", "e"])))
+                )?;
+                stdout.flush()?;
+            }
+        }
+    }
+}
+
+// Free placement: digits cycle a cell through empty -> X -> O, turns don't alternate.
+fn edit_position(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    options: &Options,
+    lines: &[Vec<usize>],
+) -> Result<Vec<char>> {
+    let size = options.board_size();
+    let mut board = empty_board(size);
+    let mut error: Option<BoardError> = None;
+    loop {
+        let layout = current_layout(size);
+        render_board(&board, stdout, &[], options, Highlight::default())?;
+        let (x, y) = layout.hint;
+        queue!(
+            stdout,
+            MoveTo(x, y),
+            SetForegroundColor(options.theme.hint),
+            Print(text().editor_hint),
+            ResetColor
+        )?;
+        if let Some(err) = &error {
+            let (x, y) = layout.result;
+            queue!(
+                stdout,
+                MoveTo(x, y + 1),
+                SetForegroundColor(options.theme.alert),
+                Print(fill(text().cant_start, &[&board_error_text(err)])),
+                ResetColor
+            )?;
+        }
+        stdout.flush()?;
+
+        if let Input::Key(event) = next_input(input)? {
+            match event.code {
+                KeyCode::Char(c) => {
+                    if let Some(d) = c.to_digit(10)
+                        && let Some(idx) = read_cell_number(input, d, board.len())?
+                        && idx < board.len()
+                    {
+                        board[idx] = match board[idx] {
+                            PLAYER_X => PLAYER_O,
+                            PLAYER_O => EMPTY,
+                            _ => PLAYER_X,
+                        };
+                    }
+                    error = None;
+                }
+                KeyCode::Esc => {
+                    board = empty_board(size);
+                    error = None;
+                }
+                KeyCode::Enter => match validate_board(&board, lines) {
+                    Ok(()) => return Ok(board),
+                    Err(err) => error = Some(err),
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+// The full search behind Impossible only finishes in time on small boards.
+// `label` starts the question asked on `row`, e.g. "Select difficulty".
+// `adaptive` offers the level that follows the human's results, so only for
+// games with a human in them.
+fn ask_difficulty(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    board_size: usize,
+    row: u16,
+    label: &str,
+    adaptive: bool,
+) -> Result<Difficulty> {
+    let impossible = board_size <= MAX_IMPOSSIBLE_SIZE;
+    // Each level's key, in the order of `Strings::levels`.
+    let offered = [true, true, true, true, true, impossible, adaptive];
+    let (names, keys): (Vec<&str>, Vec<&str>) = text()
+        .levels
+        .into_iter()
+        .zip(["e", "s", "m", "h", "d", "i", "a"])
+        .zip(offered)
+        .filter_map(|(level, offered)| offered.then_some(level))
+        .unzip();
+    let prompt = format!("{}: {}: ", label, or_list(&names));
+    loop {
+        match read_menu_key(stdout, input, row, &prompt)? {
+            'e' | 'E' => return Ok(Difficulty::Easy),
+            's' | 'S' => return Ok(Difficulty::Safe),
+            'm' | 'M' => return Ok(Difficulty::Medium),
+            'h' | 'H' => return Ok(Difficulty::Hard),
+            'd' | 'D' => return ask_search_depth(stdout, input, board_size, row),
+            'i' | 'I' if impossible => return Ok(Difficulty::Impossible),
+            'a' | 'A' if adaptive => return Ok(Difficulty::Adaptive),
+            _ => {
+                queue!(stdout, Print(format!("\n{}", invalid_input(&keys))))?;
+                stdout.flush()?;
+            }
+        }
+    }
+}
+
+// How far ahead the Depth level looks, asked on the difficulty's own row.
+fn ask_search_depth(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    board_size: usize,
+    row: u16,
+) -> Result<Difficulty> {
+    let deepest = max_search_depth(board_size);
+    let prompt = fill(text().depth_prompt, &[&deepest]);
+    loop {
+        match read_menu_key(stdout, input, row, &prompt)?.to_digit(10) {
+            Some(depth) if (1..=deepest).contains(&(depth as usize)) => {
+                return Ok(Difficulty::Depth(depth as usize));
+            }
+            _ => {
+                let range = format!("1-{}", deepest);
+                queue!(stdout, Print(format!("\n{}", invalid_input(&[&range]))))?;
+                stdout.flush()?;
+            }
+        }
+    }
+}
+
+fn max_search_depth(board_size: usize) -> usize {
+    if board_size > MAX_IMPOSSIBLE_SIZE {
+        MAX_BIG_BOARD_DEPTH
+    } else {
+        MAX_SEARCH_DEPTH
+    }
+}
+
+// "Invalid input. Type a, b or c: ".
+fn invalid_input(keys: &[&str]) -> String {
+    fill(text().invalid, &[&or_list(keys)])
+}
+
+// "a, b or c".
+fn or_list(items: &[&str]) -> String {
+    match items.split_last() {
+        Some((last, rest)) if !rest.is_empty() => {
+            format!("{} {} {}", rest.join(", "), text().or, last)
+        }
+        _ => items.concat(),
+    }
+}
+
+// A staircase: every game the human wins more than they lose moves the computer
+// up a level, every loss moves it back down, so it settles where the human wins
+// about half the decided games. Draws leave it where it is.
+fn pick_adaptive_difficulty(wins: usize, losses: usize) -> Difficulty {
+    let step = wins.saturating_sub(losses).min(DIFFICULTIES.len() - 1);
+    DIFFICULTIES[step]
+}
+
+// The level an adaptive game is played at, from the human's results in the
+// adaptive games before it; other modes come back unchanged. Boards too big to
+// search stop at Hard.
+fn resolve_adaptive(mode: GameMode, (wins, losses): (usize, usize), board_size: usize) -> GameMode {
+    match mode {
+        GameMode::AI(Difficulty::Adaptive, player_first, free_moves) => {
+            let level = match pick_adaptive_difficulty(wins, losses) {
+                Difficulty::Impossible if board_size > MAX_IMPOSSIBLE_SIZE => Difficulty::Hard,
+                level => level,
+            };
+            GameMode::AI(level, player_first, free_moves)
+        }
+        other => other,
+    }
+}
+
+// Counts a finished adaptive game toward the human's (wins, losses). A win at
+// the top level or a loss at Easy isn't kept, so the next result moves the
+// level straight away instead of first paying off a run at either end.
+fn record_adaptive(
+    record: &mut (usize, usize),
+    mode: GameMode,
+    winner: Option<char>,
+    board_size: usize,
+) {
+    let GameMode::AI(Difficulty::Adaptive, player_first, _) = mode else {
+        return;
+    };
+    let top = if board_size > MAX_IMPOSSIBLE_SIZE {
+        DIFFICULTIES.len() - 2
+    } else {
+        DIFFICULTIES.len() - 1
+    };
+    let step = record.0.saturating_sub(record.1);
+    let human = if player_first { PLAYER_X } else { PLAYER_O };
+    match winner {
+        Some(mark) if mark == human && step < top => record.0 += 1,
+        Some(mark) if mark != human && step > 0 => record.1 += 1,
+        _ => {}
+    }
+}
+
+// 1 plays a single game as before; 3, 5 or 7 start a best-of series.
+fn ask_series_length(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    row: u16,
+) -> Result<u32> {
+    loop {
+        let c = read_menu_key(stdout, input, row, text().series_length)?;
+        match c {
+            '1' | '3' | '5' | '7' => return Ok(c.to_digit(10).unwrap_or(1)),
+            _ => {
+                queue!(
+                    stdout,
+                    Print(format!("\n{}", invalid_input(&["1", "3", "5", "7"])))
+                )?;
+                stdout.flush()?;
+            }
+        }
+    }
+}
+
+fn ask_time_limit(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    row: u16,
+) -> Result<Option<Duration>> {
+    let prompt = text().time_limit;
+    loop {
+        let seconds = match read_menu_key(stdout, input, row, prompt)? {
+            'u' | 'U' => return Ok(None),
+            '1' => 5,
+            '2' => 10,
+            '3' => 30,
+            _ => {
+                queue!(
+                    stdout,
+                    Print(format!("\n{}", invalid_input(&["u", "1", "2", "3"])))
+                )?;
+                stdout.flush()?;
+                continue;
+            }
+        };
+        return Ok(Some(Duration::from_secs(seconds)));
+    }
+}
+
+fn ask_first_player(stdout: &mut impl Write, input: &mut dyn InputSource) -> Result<bool> {
+    loop {
+        match read_menu_key(stdout, input, 6, text().first_player)? {
+            'p' | 'P' => return Ok(true),
+            'c' | 'C' => return Ok(false),
+            _ => {
+                queue!(stdout, Print(format!("\n{}", invalid_input(&["p", "c"]))))?;
+                stdout.flush()?;
+            }
+        }
+    }
+}
+
+// Handicap for younger players: the computer passes this many of its turns
+// at the start of each game.
+fn ask_free_moves(stdout: &mut impl Write, input: &mut dyn InputSource) -> Result<usize> {
+    loop {
+        let c = read_menu_key(stdout, input, 8, text().free_moves)?;
+        match c.to_digit(10) {
+            Some(n @ 0..=3) => return Ok(n as usize),
+            _ => {
+                queue!(
+                    stdout,
+                    Print(format!("\n{}", invalid_input(&["0", "1", "2", "3"])))
+                )?;
+                stdout.flush()?;
+            }
+        }
+    }
+}
+
+// The lines of the menu screen so far, by row, so that it can be drawn again
+// after a resize or the help screen. `clear_screen` starts it afresh.
+static MENU_LINES: Mutex<Vec<(u16, String)>> = Mutex::new(Vec::new());
+
+fn print_menu_line(stdout: &mut impl Write, row: u16, line: &str) -> Result<()> {
+    if let Ok(mut lines) = MENU_LINES.lock() {
+        lines.retain(|&(other, _)| other != row);
+        lines.push((row, line.to_string()));
+    }
+    queue!(
+        stdout,
+        MoveTo(0, row),
+        Clear(ClearType::UntilNewLine),
+        Print(fit(line, 0))
+    )
+}
+
+// Prints a menu question on `row` and waits for a letter or digit. Each call
+// writes the question afresh, and a resize, which some terminals answer by
+// blanking the screen, writes the whole menu again.
+fn read_menu_key(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    row: u16,
+    prompt: &str,
+) -> Result<char> {
+    print_menu_line(stdout, row, prompt)?;
+    loop {
+        stdout.flush()?;
+        loop {
+            match next_input(input)? {
+                Input::Key(KeyEvent {
+                    code: KeyCode::Char(c),
+                    ..
+                }) => return Ok(c),
+                Input::Resize => break,
+                _ => {}
+            }
+        }
+        let lines = MENU_LINES
+            .lock()
+            .map(|lines| lines.clone())
+            .unwrap_or_default();
+        for (row, line) in lines {
+            queue!(stdout, MoveTo(0, row), Print(fit(&line, 0)))?;
+        }
+        // Back to the end of the question waiting for its answer.
+        queue!(stdout, MoveTo(0, row), Print(fit(prompt, 0)))?;
+    }
+}
+
+// Short heads/tails animation deciding who starts a friend game; any key skips it.
+fn flip_coin(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    options: &Options,
+    rng: &mut impl Rng,
+) -> Result<char> {
+    let starter = if rng.random::<bool>() {
+        PLAYER_X
+    } else {
+        PLAYER_O
+    };
+    clear_screen(stdout, options)?;
+    queue!(stdout, MoveTo(0, 0), Print(text().coin_flip))?;
+
+    let mut skipped = false;
+    for frame in 0..12u64 {
+        // Finish on the real result; slow down towards the end like a settling coin.
+        let face = if (12 - frame) % 2 == 1 {
+            starter
+        } else {
+            switch_player(starter)
+        };
+        queue!(
+            stdout,
+            MoveTo(4, 2),
+            SetForegroundColor(options.theme.hint),
+            Print(format!("( {} )", options.theme.glyph(face))),
+            ResetColor
+        )?;
+        stdout.flush()?;
+        if !skipped && input.poll(Duration::from_millis(40 + frame * 15))? {
+            skipped = matches!(next_input(input)?, Input::Key(_));
+        }
+    }
+
+    queue!(
+        stdout,
+        MoveTo(4, 2),
+        SetForegroundColor(options.theme.hint),
+        Print(format!("( {} )", options.theme.glyph(starter))),
+        ResetColor,
+        MoveTo(0, 4),
+        Print(fill(
+            text().goes_first,
+            &[&seat(&seat_players(options.names(), &options.theme), starter).label()]
+        ))
+    )?;
+    stdout.flush()?;
+    loop {
+        if let Input::Key(_) = next_input(input)? {
+            return Ok(starter);
+        }
+    }
+}
+
+// Takes back moves until a human move has been removed, so against the computer
+// both its reply and the player's move go. The remover is then to move again.
+// Each logged move keeps its mark, and the mode says which mark is the human's,
+// so the log needs nothing more to tell the two apart.
+fn undo_step(
+    state: &mut GameState,
+    history: &mut Vec<(char, usize)>,
+    redo_stack: &mut Vec<(char, usize)>,
+    game_mode: GameMode,
+) -> bool {
+    // Nothing to take back if the computer's opening move is all there is.
+    if !history
+        .iter()
+        .any(|&(mark, _)| is_human_turn(game_mode, mark))
+    {
+        return false;
+    }
+    while let Some((mark, idx)) = history.pop() {
+        state.clear_cell(idx);
+        redo_stack.push((mark, idx));
+        if is_human_turn(game_mode, mark) {
+            return true;
+        }
+    }
+    false
+}
+
+// Replays one undone human move plus the computer reply that followed it.
+fn redo_step(
+    state: &mut GameState,
+    history: &mut Vec<(char, usize)>,
+    redo_stack: &mut Vec<(char, usize)>,
+    game_mode: GameMode,
+) -> bool {
+    let Some((mark, idx)) = redo_stack.pop() else {
+        return false;
+    };
+    // Undone moves were legal in this exact position, so replaying them can't
+    // fail. The mark is set explicitly since a pass breaks the alternation.
+    state.current_player = mark;
+    let _ = state.apply_move(idx);
+    history.push((mark, idx));
+    while let Some(&(next, idx)) = redo_stack.last()
+        && !is_human_turn(game_mode, next)
+    {
+        redo_stack.pop();
+        state.current_player = next;
+        let _ = state.apply_move(idx);
+        history.push((next, idx));
+    }
+    // Whoever moved next originally is to move now, even across a pass.
+    if let Some(&(next, _)) = redo_stack.last() {
+        state.current_player = next;
+    }
+    true
+}
+
+fn is_human_turn(game_mode: GameMode, current: char) -> bool {
+    match game_mode {
+        GameMode::Friend => true,
+        GameMode::AIvsAI(..) => false,
+        GameMode::AI(_difficulty, player_first, _) => {
+            if player_first {
+                current == PLAYER_X
+            } else {
+                current == PLAYER_O
+            }
+        }
+    }
+}
+
+// `result` is from the human's side: 1 win, 0 draw, -1 loss.
+fn ask_end_early(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    layout: Layout,
+    result: i32,
+    theme: &Theme,
+) -> Result<EarlyEnd> {
+    let [win, loss, draw] = text().outcomes;
+    let label = match result {
+        1 => win,
+        -1 => loss,
+        _ => draw,
+    };
+    let (x, y) = layout.prompt;
+    loop {
+        queue!(
+            stdout,
+            MoveTo(x, y),
+            SetForegroundColor(theme.hint),
+            Print(fill(text().decided, &[&label])),
+            ResetColor
+        )?;
+        stdout.flush()?;
+        // After a resize or the help screen only the question comes back; the
+        // board does once it's answered.
+        if let Input::Key(event) = next_input(input)?
+            && let KeyCode::Char(c) = event.code
+        {
+            match c {
+                'y' | 'Y' => return Ok(EarlyEnd::Stop),
+                'n' | 'N' => return Ok(EarlyEnd::Continue),
+                'a' | 'A' => return Ok(EarlyEnd::AutoPlay),
+                _ => {}
+            }
+        }
+    }
+}
+
+// `mode` is the game just played; only AI games offer to swap sides.
+fn ask_replay(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    layout: Layout,
+    options: &Options,
+    board: &[char],
+    clipboard: &mut Option<Clipboard>,
+    mode: GameMode,
+) -> Result<PostGame> {
+    let (x, y) = layout.prompt;
+    let copy_key = options.keymap.copy_board;
+    let replay_key = options.keymap.replay;
+    let swap = matches!(mode, GameMode::AI(..));
+    // Judging every move takes the full solver, which is only quick on 3×3.
+    let accuracy = matches!(mode, GameMode::Friend) && board_size(board) == 3;
+    // Each key the prompt takes besides y/n, with its label. The copy key
+    // drops out once the board has been copied.
+    let keys = |copied: bool| {
+        let mut keys = Vec::new();
+        if swap {
+            keys.push(('s', text().swap_sides.to_string()));
+        }
+        if accuracy {
+            keys.push(('a', text().accuracy.to_string()));
+        }
+        if !copied {
+            keys.push((copy_key, fill(text().copy, &[&copy_key])));
+        }
+        keys.push((replay_key, fill(text().replay, &[&replay_key])));
+        keys
+    };
+    let prompt = |copied: bool| {
+        let mut labels = vec!["y/n".to_string()];
+        labels.extend(keys(copied).into_iter().map(|(_, label)| label));
+        fit(&fill(text().play_again, &[&labels.join(", ")]), x)
+    };
+    // Every key the prompt takes, for the line shown after any other one.
+    let valid_keys = |copied: bool| {
+        let mut valid = vec!["y".to_string(), "n".to_string()];
+        valid.extend(keys(copied).into_iter().map(|(key, _)| key.to_string()));
+        invalid_input(&valid.iter().map(String::as_str).collect::<Vec<_>>())
+    };
+    let mut copied = false;
+    queue!(stdout, MoveTo(x, y), Print(prompt(copied)))?;
+    stdout.flush()?;
+    loop {
+        let c = match next_input(input)? {
+            Input::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) => c,
+            Input::Resize => return Ok(PostGame::Redraw),
+            _ => continue,
+        };
+        match c {
+            'y' | 'Y' => return Ok(PostGame::Again),
+            'n' | 'N'
+                if options.quit_at_once
+                    || ask_yes_no(stdout, input, (x, y), text().really_quit)? =>
+            {
+                return Ok(PostGame::Quit);
+            }
+            'n' | 'N' => {
+                queue!(
+                    stdout,
+                    MoveTo(x, y),
+                    Clear(ClearType::UntilNewLine),
+                    Print(prompt(copied))
+                )?;
+                stdout.flush()?;
+            }
+            c if c == replay_key => return Ok(PostGame::Replay),
+            c if c == copy_key => {
+                let board_text = board_to_text(board, &options.theme, options.grid);
+                let (nx, ny) = layout.notes;
+                if copy_to_clipboard(clipboard, &board_text) {
+                    queue!(stdout, MoveTo(nx, ny), Print(text().copied))?;
+                } else {
+                    // Headless or SSH session: show the text so it can be copied by hand.
+                    queue!(stdout, MoveTo(nx, ny), Print(text().no_clipboard))?;
+                    for (row, line) in board_text.lines().enumerate() {
+                        queue!(stdout, MoveTo(nx, ny + 1 + row as u16), Print(line))?;
+                    }
+                }
+                copied = true;
+                queue!(
+                    stdout,
+                    MoveTo(x, y),
+                    Clear(ClearType::UntilNewLine),
+                    Print(prompt(copied))
+                )?;
+                stdout.flush()?;
+            }
+            's' | 'S' if swap => return Ok(PostGame::SwapSides),
+            'a' | 'A' if accuracy => return Ok(PostGame::Accuracy),
+            _ => {
+                queue!(
+                    stdout,
+                    MoveTo(x, y),
+                    Clear(ClearType::UntilNewLine),
+                    Print(fit(&valid_keys(copied), x))
+                )?;
+                stdout.flush()?;
+            }
+        }
+    }
+}
+
+// A y/n question at `(x, y)`, e.g. one more before leaving so a stray 'n'
+// doesn't end the session. Only y counts as yes.
+fn ask_yes_no(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    (x, y): (u16, u16),
+    question: &str,
+) -> Result<bool> {
+    loop {
+        queue!(
+            stdout,
+            MoveTo(x, y),
+            Clear(ClearType::UntilNewLine),
+            Print(question)
+        )?;
+        stdout.flush()?;
+        // Anything but a key, such as closing the help screen, asks again.
+        if let Input::Key(event) = next_input(input)?
+            && let KeyCode::Char(c) = event.code
+        {
+            return Ok(matches!(c, 'y' | 'Y'));
+        }
+    }
+}
+
+// Steps through a finished game one move at a time, starting after `step`
+// moves; the latest move is shown in yellow.
+fn watch_replay(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    options: &Options,
+    board: &[char],
+    history: &[(char, usize)],
+    lines: &[Vec<usize>],
+    mut step: usize,
+) -> Result<()> {
+    let start = starting_position(board, history);
+    loop {
+        let mut position = start.clone();
+        for &(mark, idx) in &history[..step] {
+            position[idx] = mark;
+        }
+        let line = check_winner(&position, lines).map(|(_, line)| line);
+        render_board(
+            &position,
+            stdout,
+            &history[..step],
+            options,
+            Highlight {
+                line: line.as_deref(),
+                ..Highlight::default()
+            },
+        )?;
+
+        let layout = current_layout(board_size(board));
+        let (x, y) = layout.hint;
+        let caption = match step.checked_sub(1).map(|turn| history[turn]) {
+            Some((mark, idx)) => {
+                let size = board_size(board);
+                let (cx, cy) = cell_position(idx, size, board_origin(size));
+                queue!(
+                    stdout,
+                    MoveTo(cx, cy),
+                    SetForegroundColor(options.theme.hint),
+                    Print(options.theme.glyph(mark)),
+                    ResetColor
+                )?;
+                fill(
+                    text().replay_step,
+                    &[
+                        &step,
+                        &history.len(),
+                        &options.theme.glyph(mark),
+                        &(idx + 1),
+                    ],
+                )
+            }
+            None => fill(text().replay_start, &[&history.len()]),
+        };
+        queue!(
+            stdout,
+            MoveTo(x, y),
+            Print(caption),
+            MoveTo(layout.prompt.0, layout.prompt.1),
+            Print(fill(text().replay_controls, &[&options.keymap.quit]))
+        )?;
+        stdout.flush()?;
+
+        loop {
+            match next_input(input)? {
+                Input::Key(event) => match event.code {
+                    KeyCode::Left if step > 0 => step -= 1,
+                    KeyCode::Right | KeyCode::Char(' ') if step < history.len() => step += 1,
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Char(c) if c == options.keymap.quit => return Ok(()),
+                    _ => continue,
+                },
+                Input::Resize => {}
+                Input::Click(..) | Input::Ignored => continue,
+            }
+            break;
+        }
+    }
+}
+
+// ======================================
+// DRAWING
+// ======================================
+
+// Plain-text grid for pasting elsewhere; empty cells are left blank.
+fn board_to_text(board: &[char], theme: &Theme, grid: GridStyle) -> String {
+    grid_text(board, grid, |idx| match board[idx] {
+        EMPTY => " ".to_string(),
+        mark => theme.glyph(mark).to_string(),
+    })
+}
+
+// The same grid with each free cell showing its number, for the plain renderer.
+fn numbered_board_text(board: &[char], theme: &Theme, grid: GridStyle) -> String {
+    grid_text(board, grid, |idx| match board[idx] {
+        EMPTY => (idx + 1).to_string(),
+        mark => theme.glyph(mark).to_string(),
+    })
+}
+
+fn grid_text(board: &[char], grid: GridStyle, cell: impl Fn(usize) -> String) -> String {
+    let size = board_size(board);
+    let rule = grid.rule(size, 3);
+    let mut text = String::new();
+    for row in 0..size {
+        if row > 0 {
+            text.push_str(&rule);
+            text.push('\n');
+        }
+        let cells: Vec<String> = (row * size..row * size + size)
+            .map(|idx| format!("{:^3}", cell(idx)))
+            .collect();
+        text.push_str(cells.join(&grid.vertical.to_string()).trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+fn copy_to_clipboard(clipboard: &mut Option<Clipboard>, text: &str) -> bool {
+    if clipboard.is_none() {
+        *clipboard = Clipboard::new().ok();
+    }
+    match clipboard {
+        Some(clipboard) => clipboard.set_text(text).is_ok(),
+        None => false,
+    }
+}
+
+// Text rows below a gravity grid or the ultimate boards, `height` rows tall.
+fn grid_layout(height: usize) -> Layout {
+    let bottom = GRAVITY_TOP + height as u16;
+    Layout {
+        hint: (0, bottom + 2),
+        announce: (0, bottom + 1),
+        timer: (0, bottom + 5),
+        result: (0, bottom + 4),
+        prompt: (0, bottom + 8),
+        scores: (0, bottom + 6),
+        notes: (0, bottom + 10),
+        panel: None,
+        controls: (0, 0),
+    }
+}
+
+// Column numbers above the gravity grid, the winning line in green.
+fn draw_gravity_board(
+    stdout: &mut impl Write,
+    options: &Options,
+    cells: &[char],
+    width: usize,
+    line: Option<&[usize]>,
+) -> Result<()> {
+    clear_screen(stdout, options)?;
+    queue!(stdout, MoveTo(0, 0), Print(text().gravity_title))?;
+    queue!(stdout, MoveTo(0, 1), Print("========================"))?;
+
+    for col in 0..width {
+        queue!(
+            stdout,
+            MoveTo(col as u16 * 4 + 2, GRAVITY_TOP - 1),
+            Print(col + 1)
+        )?;
+    }
+    for (idx, &cell) in cells.iter().enumerate() {
+        let (row, col) = ((idx / width) as u16, (idx % width) as u16);
+        let color = if line.is_some_and(|l| l.contains(&idx)) {
+            options.theme.highlight
+        } else if cell == EMPTY {
+            options.theme.muted
+        } else {
+            options.theme.mark(cell)
+        };
+        queue!(
+            stdout,
+            MoveTo(col * 4, GRAVITY_TOP + row),
+            Print(format!("{} ", options.grid.vertical)),
+            SetForegroundColor(color),
+            Print(if cell == EMPTY {
+                '.'
+            } else {
+                options.theme.glyph(cell)
+            }),
+            ResetColor
+        )?;
+        if col as usize == width - 1 {
+            queue!(stdout, Print(format!(" {}", options.grid.vertical)))?;
+        }
+    }
+    let height = (cells.len() / width) as u16;
+    let [left, tee, right] = options.grid.bottom;
+    let segment = options.grid.horizontal.to_string().repeat(3);
+    queue!(
+        stdout,
+        MoveTo(0, GRAVITY_TOP + height),
+        Print(format!(
+            "{}{}{}",
+            left,
+            vec![segment; width].join(&tee.to_string()),
+            right
+        ))
+    )?;
+
+    stdout.flush()?;
+    Ok(())
+}
+
+// The nine small boards with the big board beside them. Free cells of the
+// boards in `active` show their numbers; a decided board keeps only the
+// winner's marks in colour, and `line` is the winning row of boards.
+fn draw_ultimate_board(
+    stdout: &mut impl Write,
+    options: &Options,
+    state: &UltimateState,
+    active: &[usize],
+    line: Option<&[usize]>,
+) -> Result<()> {
+    let theme = &options.theme;
+    let grid = &options.grid;
+    clear_screen(stdout, options)?;
+    queue!(stdout, MoveTo(0, 0), Print(text().ultimate_title))?;
+    queue!(stdout, MoveTo(0, 1), Print("========================"))?;
+
+    let meta = state.meta();
+    for (board, cells) in state.boards.iter().enumerate() {
+        let decided = state.board_result(board).is_over();
+        let (left, top) = (
+            ULTIMATE_LEFT + (board % 3) as u16 * 8,
+            GRAVITY_TOP + (board / 3) as u16 * 4,
+        );
+        for (cell, &mark) in cells.iter().enumerate() {
+            let (glyph, color) = if mark != EMPTY {
+                let color = if line.is_some_and(|line| line.contains(&board)) {
+                    theme.highlight
+                } else if decided && mark != meta[board] {
+                    theme.muted
+                } else {
+                    theme.mark(mark)
+                };
+                (theme.glyph(mark), color)
+            } else if active.contains(&board) {
+                (
+                    char::from_digit(cell as u32 + 1, 10).unwrap_or('?'),
+                    theme.free,
+                )
+            } else {
+                ('.', theme.muted)
+            };
+            queue!(
+                stdout,
+                MoveTo(left + (cell % 3) as u16 * 2, top + (cell / 3) as u16),
+                SetForegroundColor(color),
+                Print(glyph),
+                ResetColor
+            )?;
+        }
+    }
+    // Lines between the small boards.
+    for row in 0..ULTIMATE_ROWS as u16 {
+        let y = GRAVITY_TOP + row;
+        if row % 4 == 3 {
+            let rule = grid.rule(3, 7);
+            queue!(stdout, MoveTo(ULTIMATE_LEFT - 1, y), Print(rule))?;
+            continue;
+        }
+        for x in [ULTIMATE_LEFT + 6, ULTIMATE_LEFT + 14] {
+            queue!(stdout, MoveTo(x, y), Print(grid.vertical))?;
+        }
+    }
+    // The big board: each small board's number while it's open (bright when
+    // it can be played), its winner once won, '-' once drawn.
+    let right = ULTIMATE_LEFT + 26;
+    queue!(
+        stdout,
+        MoveTo(right, GRAVITY_TOP),
+        Print(text().boards_heading)
+    )?;
+    for (board, &mark) in meta.iter().enumerate() {
+        let (glyph, color) = if mark != EMPTY {
+            let color = if line.is_some_and(|line| line.contains(&board)) {
+                theme.highlight
+            } else {
+                theme.mark(mark)
+            };
+            (theme.glyph(mark), color)
+        } else if state.board_result(board).is_over() {
+            ('-', theme.muted)
+        } else if active.contains(&board) {
+            (
+                char::from_digit(board as u32 + 1, 10).unwrap_or('?'),
+                theme.hint,
+            )
+        } else {
+            (
+                char::from_digit(board as u32 + 1, 10).unwrap_or('?'),
+                theme.muted,
+            )
+        };
+        queue!(
+            stdout,
+            MoveTo(
+                right + (board % 3) as u16 * 2,
+                GRAVITY_TOP + 2 + (board / 3) as u16
+            ),
+            SetForegroundColor(color),
+            Print(glyph),
+            ResetColor
+        )?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// Wipes the screen before a redraw. With --compact only the rows the board,
+// its panel and the notes under it take up are blanked, or the help screen's if
+// that is longer, and the frame goes out in a single flush: the terminal below
+// them and its scrollback are left alone, and the board doesn't flicker.
+fn clear_screen(stdout: &mut impl Write, options: &Options) -> Result<()> {
+    if let Ok(mut lines) = MENU_LINES.lock() {
+        lines.clear();
+    }
+    FRAME_ROWS.store(frame_rows(options), Ordering::Relaxed);
+    wipe_screen(stdout, options.compact)
+}
+
+// Rows down to the end of the notes under whichever board is being played.
+// Menus and prompts are drawn within them.
+fn frame_rows(options: &Options) -> u16 {
+    let notes = match options.gravity {
+        Some((_, height)) => grid_layout(height).notes.1,
+        None if options.ultimate => grid_layout(ULTIMATE_ROWS).notes.1,
+        None => current_layout(options.board_size()).notes.1,
+    };
+    notes + NOTES_ROOM
+}
+
+// Set by `clear_screen`, so the help screen, which isn't passed the options,
+// wipes the same rows.
+static FRAME_ROWS: AtomicU16 = AtomicU16::new(0);
+
+fn wipe_screen(stdout: &mut impl Write, compact: bool) -> Result<()> {
+    if !compact {
+        return execute!(stdout, Clear(ClearType::All));
+    }
+    // Title, controls, each line, the colours heading and its seven samples.
+    let help_rows = HELP.get().map_or(0, |help| help.lines.len() as u16 + 12);
+    let (_, height) = size().unwrap_or((0, 0));
+    let rows = FRAME_ROWS
+        .load(Ordering::Relaxed)
+        .max(help_rows)
+        .min(height);
+    for row in 0..rows {
+        queue!(stdout, MoveTo(0, row), Clear(ClearType::UntilNewLine))?;
+    }
+    Ok(())
+}
+
+// --big-board takes a lot of room. While the window is too small for it this
+// says so, until the window is enlarged or a key is pressed to play anyway.
+fn warn_if_board_too_big(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    options: &Options,
+) -> Result<()> {
+    let board_size = options.board_size();
+    loop {
+        let (width, height) = size()?;
+        let needed = (grid_width(board_size), notes_row(board_size) + NOTES_ROOM);
+        if width >= needed.0 && height >= needed.1 {
+            return Ok(());
+        }
+        clear_screen(stdout, options)?;
+        queue!(
+            stdout,
+            MoveTo(0, 0),
+            SetForegroundColor(options.theme.alert),
+            Print(fill(
+                text().big_board_window,
+                &[&needed.0, &needed.1, &width, &height]
+            )),
+            ResetColor,
+            MoveTo(0, 2),
+            Print(text().big_board_anyway)
+        )?;
+        stdout.flush()?;
+        if let Input::Key(_) = next_input(input)? {
+            return Ok(());
+        }
+    }
+}
+
+// Side-by-side layout when the terminal is wide enough, stacked otherwise.
+// Every row is offset by the same origin as the grid.
+fn current_layout(board_size: usize) -> Layout {
+    let (left, top) = board_origin(board_size);
+    let grid_bottom = top + grid_bottom(board_size);
+    let panel_x = left + grid_width(board_size) + PANEL_GAP;
+    let notes = top + notes_row(board_size);
+    if has_panel(board_size) {
+        Layout {
+            hint: (panel_x, top + 2),
+            announce: (panel_x, top + 1),
+            timer: (panel_x, top + 4),
+            result: (panel_x, top + 2),
+            prompt: (panel_x, top + 3),
+            scores: (panel_x, top + 5),
+            notes: (left, notes),
+            panel: Some((panel_x, top + 7)),
+            controls: (panel_x, top),
+        }
+    } else {
+        Layout {
+            hint: (left, grid_bottom + 3),
+            announce: (left, grid_bottom + 1),
+            timer: (left, grid_bottom + 6),
+            result: (left, grid_bottom + 5),
+            prompt: (left, grid_bottom + 9),
+            scores: (left, grid_bottom + 7),
+            notes: (left, notes),
+            panel: None,
+            controls: (left, top),
+        }
+    }
+}
+
+// Row of the notes line, counted from the top of the game screen.
+fn notes_row(board_size: usize) -> u16 {
+    if has_panel(board_size) {
+        // Below the longest move list, or the grid if that is taller.
+        17.max(grid_bottom(board_size) + 2)
+    } else {
+        grid_bottom(board_size) + 11
+    }
+}
+
+// Whether the move log fits beside the grid.
+fn has_panel(board_size: usize) -> bool {
+    let (width, _) = size().unwrap_or((0, 0));
+    width >= grid_width(board_size) + PANEL_GAP + PANEL_WIDTH
+}
+
+// Screen column where the grid starts and row of the title above it. With the
+// move log beside it the pair is centred across the terminal; stacked, the grid
+// and the text under it share a column as wide as the panel would be, so that
+// is centred instead. Either way the screen is centred vertically when there
+// are rows to spare.
+fn board_origin(board_size: usize) -> (u16, u16) {
+    let (width, height) = size().unwrap_or((0, 0));
+    let left = if has_panel(board_size) {
+        (width - (grid_width(board_size) + PANEL_GAP + PANEL_WIDTH)) / 2
+    } else {
+        width.saturating_sub(grid_width(board_size).max(PANEL_WIDTH)) / 2
+    };
+    let top = height.saturating_sub(notes_row(board_size) + NOTES_ROOM) / 2;
+    (left, top)
+}
+
+// Columns and rows of one cell, without the grid lines.
+fn cell_size() -> (u16, u16) {
+    if BIG_CELLS.load(Ordering::Relaxed) {
+        (BIG_CELL_WIDTH, BIG_CELL_HEIGHT)
+    } else {
+        (CELL_WIDTH, CELL_HEIGHT)
+    }
+}
+
+fn grid_width(board_size: usize) -> u16 {
+    board_size as u16 * (cell_size().0 + 1) - 1
+}
+
+// First screen row below the grid.
+fn grid_bottom(board_size: usize) -> u16 {
+    GRID_TOP + board_size as u16 * (cell_size().1 + 1) - 1
+}
+
+// Redraws the whole game screen: title, grid and every cell, each picked out
+// as `highlight` asks.
+fn render_board(
+    board: &[char],
+    stdout: &mut impl Write,
+    history: &[(char, usize)],
+    options: &Options,
+    highlight: Highlight,
+) -> Result<()> {
+    let size = board_size(board);
+    let origin @ (left, top) = board_origin(size);
+    clear_screen(stdout, options)?;
+    queue!(
+        stdout,
+        MoveTo(left, top),
+        Print(format!("{}\n", text().title))
+    )?;
+    queue!(
+        stdout,
+        MoveTo(left, top + 1),
+        Print("========================\n")
+    )?;
+
+    draw_grid_lines(stdout, size, origin, options, false)?;
+    for idx in 0..board.len() {
+        draw_cell(stdout, board, idx, highlight.look(idx), &options.theme)?;
+        if options.show_move_numbers {
+            let (x, y) = cell_position(idx, size, origin);
+            print_move_number(stdout, history, idx, (x, y), &options.theme)?;
+        }
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+// `render_board` with the winning line lighting up a cell at a time, `delay`
+// apart, in the order the line runs. Once the last cell is lit the screen is
+// the same as drawing the whole line at once, which is what a zero delay does.
+fn sweep_winning_line(
+    board: &[char],
+    stdout: &mut impl Write,
+    history: &[(char, usize)],
+    options: &Options,
+    highlight: Highlight,
+    delay: Duration,
+) -> Result<()> {
+    let Some(line) = highlight.line.filter(|_| !delay.is_zero()) else {
+        return render_board(board, stdout, history, options, highlight);
+    };
+    let unlit = Highlight {
+        line: None,
+        ..highlight
+    };
+    render_board(board, stdout, history, options, unlit)?;
+    let size = board_size(board);
+    for &idx in line {
+        thread::sleep(delay);
+        draw_cell(stdout, board, idx, highlight.look(idx), &options.theme)?;
+        // The brackets of an ASCII highlight reach the move number's column.
+        if options.show_move_numbers {
+            let (x, y) = cell_position(idx, size, board_origin(size));
+            print_move_number(stdout, history, idx, (x, y), &options.theme)?;
+        }
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+// One cell's mark or number. The cursor gets a grey background; the winning
+// line is drawn in green and an unfinished two-in-a-row is tinted in the colour
+// of the side that made it. A refused cell is drawn in reverse in the alert
+// colour, which shows whatever its mark's own colour is. The latest move is
+// underlined, which needs no colour of its own and so reads the same in every
+// theme.
+fn draw_cell(
+    stdout: &mut impl Write,
+    board: &[char],
+    idx: usize,
+    look: CellLook,
+    theme: &Theme,
+) -> Result<()> {
+    let size = board_size(board);
+    let (x, y) = cell_position(idx, size, board_origin(size));
+    let color = if look.refused {
+        theme.alert
+    } else if look.winning {
+        theme.highlight
+    } else if look.threat.is_some() {
+        theme.free
+    } else if look.cursor && theme.mark(board[idx]) == theme.cursor {
+        // Dimmed or monochrome numbers would vanish into the cursor.
+        theme.hint
+    } else {
+        theme.mark(board[idx])
+    };
+    queue!(stdout, MoveTo(x, y), SetForegroundColor(color))?;
+    if look.refused {
+        queue!(stdout, SetAttribute(Attribute::Reverse))?;
+    }
+    if let Some(mark) = look.threat {
+        let background = if mark == PLAYER_X {
+            theme.x_tint
+        } else {
+            theme.o_tint
+        };
+        queue!(stdout, SetBackgroundColor(background))?;
+    }
+    let label = cell_label(board[idx], idx, theme);
+    if cell_size().1 > 1 {
+        return draw_big_cell(stdout, board[idx], label, (x, y), look, theme);
+    }
+    if look.last {
+        queue!(stdout, SetAttribute(Attribute::Underlined))?;
+    }
+    if theme.ascii {
+        let marked = look.cursor || look.winning || look.refused;
+        queue!(
+            stdout,
+            MoveTo(x - 1, y),
+            Print(bracketed(label, marked)),
+            ResetColor
+        )?;
+        return Ok(());
+    }
+    if look.cursor && !look.refused {
+        queue!(stdout, SetBackgroundColor(theme.cursor))?;
+    }
+    // ResetColor clears the underline as well.
+    queue!(stdout, Print(label), ResetColor)?;
+    Ok(())
+}
+
+// The rest of `draw_cell` for --big-board, once the colours are set: the whole
+// cell is painted, so the cursor and threat backgrounds fill it, then a block
+// glyph for a mark or the number of a free cell in the middle. Without colour
+// the winning glyphs are shaded and the brackets go at the cell's edges.
+fn draw_big_cell(
+    stdout: &mut impl Write,
+    cell: char,
+    label: String,
+    (x, y): (u16, u16),
+    look: CellLook,
+    theme: &Theme,
+) -> Result<()> {
+    let (width, height) = cell_size();
+    let (left, top) = (x - width / 2, y - height / 2);
+    if look.cursor && !look.refused && !theme.ascii {
+        queue!(stdout, SetBackgroundColor(theme.cursor))?;
+    }
+    let blank = " ".repeat(width as usize);
+    for row in 0..height {
+        queue!(stdout, MoveTo(left, top + row), Print(&blank))?;
+    }
+    let glyph = match cell {
+        PLAYER_X => Some(BIG_X),
+        PLAYER_O => Some(BIG_O),
+        _ => None,
+    };
+    match glyph {
+        Some(rows) => {
+            for (row, line) in rows.iter().enumerate() {
+                let line = if theme.ascii && look.winning {
+                    line.replace(['█', '▄', '▀'], "▒")
+                } else {
+                    line.to_string()
+                };
+                queue!(stdout, MoveTo(left + 1, top + row as u16), Print(line))?;
+            }
+        }
+        None => queue!(stdout, MoveTo(x, y), Print(label))?,
+    }
+    if theme.ascii && (look.cursor || look.winning || look.refused) {
+        queue!(
+            stdout,
+            MoveTo(left, y),
+            Print('['),
+            MoveTo(left + width - 1, y),
+            Print(']')
+        )?;
+    }
+    // An underlined block glyph just looks striped, so a dot in the corner
+    // marks the latest move instead.
+    if look.last {
+        queue!(stdout, MoveTo(left, top), Print('•'))?;
+    }
+    queue!(stdout, ResetColor)?;
+    Ok(())
+}
+
+// "[X]" for a marked cell, " X " otherwise, drawn one column left of the mark
+// so the brackets from an earlier frame are always overwritten.
+fn bracketed(label: String, marked: bool) -> String {
+    if marked {
+        format!("[{}]", label)
+    } else {
+        format!(" {} ", label)
+    }
+}
+
+// A free cell shows the number that picks it.
+fn cell_label(cell: char, idx: usize, theme: &Theme) -> String {
+    if cell == EMPTY {
+        (idx + 1).to_string()
+    } else {
+        theme.glyph(cell).to_string()
+    }
+}
+
+// Screen column and row of a cell's mark, for a screen whose grid starts at
+// column `left` and whose title is on row `top`.
+fn cell_position(idx: usize, board_size: usize, (left, top): (u16, u16)) -> (u16, u16) {
+    let (row, col) = ((idx / board_size) as u16, (idx % board_size) as u16);
+    let (width, height) = cell_size();
+    (
+        left + col * (width + 1) + width / 2,
+        top + GRID_TOP + row * (height + 1) + height / 2,
+    )
+}
+
+// The cell drawn at a position, if any; the whole cell counts, not just its mark.
+// `x` and `y` count from the board's origin.
+fn cell_at(x: u16, y: u16, board_size: usize) -> Option<usize> {
+    let y = y.checked_sub(GRID_TOP)?;
+    let (width, height) = cell_size();
+    let (col, dx) = (x / (width + 1), x % (width + 1));
+    let (row, dy) = (y / (height + 1), y % (height + 1));
+    let (row, col) = (row as usize, col as usize);
+    (dx < width && dy < height && row < board_size && col < board_size)
+        .then_some(row * board_size + col)
+}
+
+// Bars between columns and "---+---+---" rules between rows, or the same in
+// box drawing with --grid box.
+fn draw_grid_lines(
+    stdout: &mut impl Write,
+    board_size: usize,
+    (left, top): (u16, u16),
+    options: &Options,
+    flash: bool,
+) -> Result<()> {
+    let (width, height) = cell_size();
+    let rule = options.grid.rule(board_size, width as usize);
+    let bar = options.grid.vertical.to_string();
+    let board_size = board_size as u16;
+    if flash {
+        // Without colours the lines are flashed in reverse video instead.
+        queue!(stdout, SetForegroundColor(options.theme.alert))?;
+        if options.theme.ascii {
+            queue!(stdout, SetAttribute(Attribute::Reverse))?;
+        }
+    } else {
+        queue!(stdout, SetForegroundColor(options.theme.grid))?;
+    }
+    for row in 0..board_size {
+        let row_top = top + GRID_TOP + row * (height + 1);
+        if row > 0 {
+            queue!(stdout, MoveTo(left, row_top - 1), Print(&rule))?;
+        }
+        for y in row_top..row_top + height {
+            for col in 1..board_size {
+                queue!(stdout, MoveTo(left + col * (width + 1) - 1, y), Print(&bar))?;
+            }
+        }
+    }
+    queue!(stdout, ResetColor)?;
+    Ok(())
+}
+
+// Small superscript showing on which turn a cell was filled, e.g. "X³" or
+// "X¹²": right of the mark whose centre is at `x`, `y`, or in the top right
+// corner of a big cell.
+fn print_move_number(
+    stdout: &mut impl Write,
+    history: &[(char, usize)],
+    idx: usize,
+    (x, y): (u16, u16),
+    theme: &Theme,
+) -> Result<()> {
+    let Some(turn) = history.iter().position(|&(_, cell)| cell == idx) else {
+        return Ok(());
+    };
+    let number: String = (turn + 1)
+        .to_string()
+        .bytes()
+        .map(|digit| SUPERSCRIPT_DIGITS[(digit - b'0') as usize])
+        .collect();
+    let (width, height) = cell_size();
+    let (x, y) = if height > 1 {
+        (
+            x + width / 2 + 1 - number.chars().count() as u16,
+            y - height / 2,
+        )
+    } else {
+        (x + 1, y)
+    };
+    queue!(
+        stdout,
+        MoveTo(x, y),
+        SetForegroundColor(theme.muted),
+        Print(number),
+        ResetColor
+    )?;
+    Ok(())
+}
+
+// Controls, live scores and the move log, shown only in the wide layout.
+fn draw_info_panel(
+    stdout: &mut impl Write,
+    layout: Layout,
+    history: &[(char, usize)],
+    (px, po, draws): (Score, Score, Score),
+    table: &[Player; 2],
+    theme: &Theme,
+) -> Result<()> {
+    let Some((x, y)) = layout.panel else {
+        return Ok(());
+    };
+    let (cx, cy) = layout.controls;
+    queue!(stdout, MoveTo(cx, cy), Print(text().panel_controls))?;
+    print_scoreboard(stdout, layout, px, po, draws, table, theme)?;
+    let (_, height) = size().unwrap_or((0, 0));
+    render_move_list(stdout, (x, y), height.saturating_sub(y), history, theme)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// The moves of this game under a "Moves:" heading, one per row, in at most
+// `rows` rows so the list never scrolls the screen. When a long game (49 moves
+// on 7×7) runs out of room the oldest moves give way to a count of them, and
+// with no room for a single move the list is left out.
+fn render_move_list(
+    stdout: &mut impl Write,
+    (x, y): (u16, u16),
+    rows: u16,
+    history: &[(char, usize)],
+    theme: &Theme,
+) -> Result<()> {
+    // The bottom row stays free: printing there can scroll some terminals.
+    let room = rows.saturating_sub(2) as usize;
+    if room == 0 {
+        return Ok(());
+    }
+    let skipped = history.len().saturating_sub(room);
+    let heading = match skipped {
+        0 => text().moves_heading.to_string(),
+        n => fill(text().moves_earlier, &[&n]),
+    };
+    queue!(stdout, MoveTo(x, y), Print(heading))?;
+    for (row, (turn, &(mark, idx))) in history.iter().enumerate().skip(skipped).enumerate() {
+        queue!(
+            stdout,
+            MoveTo(x, y + 1 + row as u16),
+            Print(format!(
+                "{:>2}. {} {} {}",
+                turn + 1,
+                theme.glyph(mark),
+                if theme.ascii { "->" } else { "→" },
+                idx + 1
+            ))
+        )?;
+    }
+    Ok(())
+}
+
+// Status lines about the computer's last move: search cost and/or its reason.
+fn print_ai_notes(
+    stdout: &mut impl Write,
+    layout: Layout,
+    options: &Options,
+    ai_move: AiMove,
+) -> Result<()> {
+    let (x, mut y) = layout.notes;
+    if options.show_nodes {
+        queue!(
+            stdout,
+            MoveTo(x, y),
+            SetForegroundColor(options.theme.muted),
+            Print(fill(text().nodes, &[&ai_move.nodes])),
+            ResetColor
+        )?;
+        y += 1;
+    }
+    if options.explain {
+        queue!(
+            stdout,
+            MoveTo(x, y),
+            SetForegroundColor(options.theme.muted),
+            Print(fill(
+                text().explain,
+                &[&(ai_move.pos + 1), &text().reasons[ai_move.reason as usize]]
+            )),
+            ResetColor
+        )?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// Row and column wording shared by the move and board announcements.
+fn describe_cell(idx: usize, board_size: usize) -> String {
+    fill(
+        text().cell_at,
+        &[&(idx / board_size + 1), &(idx % board_size + 1)],
+    )
+}
+
+// Only the change since the last turn, so a screen reader stays brief.
+fn print_announcement(
+    stdout: &mut impl Write,
+    layout: Layout,
+    history: &[(char, usize)],
+    board_size: usize,
+    theme: &Theme,
+) -> Result<()> {
+    let Some(&(mark, idx)) = history.last() else {
+        return Ok(());
+    };
+    let (x, y) = layout.announce;
+    queue!(
+        stdout,
+        MoveTo(x, y),
+        Print(fill(
+            text().placed_at,
+            &[&theme.glyph(mark), &describe_cell(idx, board_size)]
+        ))
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// The full board in words, one line per row, on request.
+fn print_board_reading(
+    stdout: &mut impl Write,
+    layout: Layout,
+    board: &[char],
+    theme: &Theme,
+) -> Result<()> {
+    let (x, y) = layout.notes;
+    for (row, cells) in board.chunks(board_size(board)).enumerate() {
+        let cells: Vec<String> = cells
+            .iter()
+            .map(|&cell| match cell {
+                EMPTY => text().empty_cell.to_string(),
+                mark => theme.glyph(mark).to_string(),
+            })
+            .collect();
+        queue!(
+            stdout,
+            MoveTo(x, y + row as u16),
+            Clear(ClearType::UntilNewLine),
+            Print(fill(text().board_row, &[&(row + 1), &cells.join(", ")]))
+        )?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn print_turn_hint(
+    stdout: &mut impl Write,
+    layout: Layout,
+    mode: GameMode,
+    current: char,
+    cells: usize,
+    players: &[Player; 2],
+    theme: &Theme,
+) -> Result<()> {
+    let hint = match mode {
+        GameMode::Friend => fill(
+            text().friend_turn,
+            &[&seat(players, current).label(), &cells],
+        ),
+        GameMode::AI(_d, player_first, _) => {
+            if is_human_turn(mode, current) {
+                let you_mark = if player_first { PLAYER_X } else { PLAYER_O };
+                fill(text().your_turn, &[&theme.glyph(you_mark), &cells])
+            } else {
+                let comp_mark = if player_first { PLAYER_O } else { PLAYER_X };
+                fill(text().computer_turn, &[&theme.glyph(comp_mark)])
+            }
+        }
+        GameMode::AIvsAI(x_difficulty, o_difficulty) => {
+            let difficulty = if current == PLAYER_X {
+                x_difficulty
+            } else {
+                o_difficulty
+            };
+            fill(
+                text().ai_turn,
+                &[&level_name(difficulty), &theme.glyph(current)],
+            )
+        }
+    };
+
+    let color = if is_human_turn(mode, current) {
+        theme.hint
+    } else {
+        theme.computer
+    };
+
+    let (x, y) = layout.hint;
+    queue!(
+        stdout,
+        MoveTo(x, y),
+        SetForegroundColor(color),
+        Print(fit(&hint, x)),
+        ResetColor
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Whole seconds left, rounded up so "0s" never shows while time remains.
+fn print_time_left(
+    stdout: &mut impl Write,
+    layout: Layout,
+    left: Duration,
+    theme: &Theme,
+) -> Result<()> {
+    let seconds = left.as_millis().div_ceil(1000);
+    let (x, y) = layout.timer;
+    queue!(
+        stdout,
+        SavePosition,
+        MoveTo(x, y),
+        Clear(ClearType::UntilNewLine),
+        SetForegroundColor(if seconds <= 3 {
+            theme.alert
+        } else {
+            theme.hint
+        }),
+        Print(fill(text().seconds_left, &[&seconds])),
+        ResetColor,
+        RestorePosition
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Both sides' time left in a --time-attack game, e.g. "X 0:42  O ∞", on the
+// timer row. The side to move is picked out, in the alert colour for its last
+// ten seconds; the computer's clock shows no limit.
+fn print_clocks(
+    stdout: &mut impl Write,
+    layout: Layout,
+    clocks: [Option<Duration>; 2],
+    mover: char,
+    theme: &Theme,
+) -> Result<()> {
+    let (x, y) = layout.timer;
+    queue!(
+        stdout,
+        SavePosition,
+        MoveTo(x, y),
+        Clear(ClearType::UntilNewLine)
+    )?;
+    for (mark, clock) in [PLAYER_X, PLAYER_O].into_iter().zip(clocks) {
+        let shown = match clock {
+            Some(left) => {
+                let seconds = left.as_millis().div_ceil(1000);
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            }
+            None if theme.ascii => "--".to_string(),
+            None => "∞".to_string(),
+        };
+        let color = match clock {
+            _ if mark != mover => theme.muted,
+            Some(left) if left <= Duration::from_secs(10) => theme.alert,
+            _ => theme.hint,
+        };
+        queue!(
+            stdout,
+            SetForegroundColor(color),
+            Print(format!("{} {}  ", theme.glyph(mark), shown))
+        )?;
+    }
+    queue!(stdout, ResetColor, RestorePosition)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Rings twice with --feedback beep; the gap keeps terminals from merging the bells.
+// `note` follows on the same line, e.g. "Player O resigned." or a new record.
+fn print_winner(
+    stdout: &mut impl Write,
+    layout: Layout,
+    winner: &Player,
+    note: Option<&str>,
+    options: &Options,
+) -> Result<()> {
+    let theme = &options.theme;
+    let (x, y) = layout.result;
+    queue!(
+        stdout,
+        MoveTo(x, y),
+        SetForegroundColor(winner.color),
+        Print(fit(
+            &format!(
+                "{} {}",
+                fill(text().wins, &[&winner.label()]),
+                if theme.ascii {
+                    text().ascii_badges[0]
+                } else {
+                    "🎉"
+                }
+            ),
+            x
+        )),
+        ResetColor
+    )?;
+    if let Some(note) = note {
+        queue!(
+            stdout,
+            SetForegroundColor(theme.hint),
+            Print(format!(" {}", note)),
+            ResetColor
+        )?;
+    }
+    if options.feedback == Feedback::Beep {
+        queue!(stdout, Print(BELL))?;
+        stdout.flush()?;
+        thread::sleep(Duration::from_millis(150));
+        queue!(stdout, Print(BELL))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn print_draw(stdout: &mut impl Write, layout: Layout, theme: &Theme) -> Result<()> {
+    let (x, y) = layout.result;
+    queue!(
+        stdout,
+        MoveTo(x, y),
+        SetForegroundColor(theme.hint),
+        Print(format!(
+            "{} {}",
+            text().draw,
+            if theme.ascii {
+                text().ascii_badges[1]
+            } else {
+                "🤝"
+            }
+        )),
+        ResetColor
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// How many of each side's moves kept the best result open to them, with the
+// solver as judge: (best moves, moves) for X, then for O. In a lost position
+// every move counts as best, since none of them could have saved it.
+fn move_accuracy(
+    board: &[char],
+    history: &[(char, usize)],
+    lines: &[Vec<usize>],
+) -> [(usize, usize); 2] {
+    let mut counts = [(0, 0); 2];
+    let mut position = starting_position(board, history);
+    for &(mark, idx) in history {
+        let scores = score_moves(&position, mark, lines);
+        let best = scores.iter().map(|&(_, result)| result).max();
+        let played = scores
+            .iter()
+            .find(|&&(cell, _)| cell == idx)
+            .map(|&(_, r)| r);
+        let count = &mut counts[usize::from(mark == PLAYER_O)];
+        count.1 += 1;
+        if played == best {
+            count.0 += 1;
+        }
+        position[idx] = mark;
+    }
+    counts
+}
+
+// "Alice (X) played 4/5 optimal, Bob (O) 3/4", on the notes line.
+fn print_accuracy(
+    stdout: &mut impl Write,
+    layout: Layout,
+    board: &[char],
+    history: &[(char, usize)],
+    lines: &[Vec<usize>],
+    [x, o]: &[Player; 2],
+) -> Result<()> {
+    let [(x_best, x_moves), (o_best, o_moves)] = move_accuracy(board, history, lines);
+    let (nx, ny) = layout.notes;
+    queue!(
+        stdout,
+        MoveTo(nx, ny),
+        Clear(ClearType::UntilNewLine),
+        Print(fill(
+            text().accuracy_line,
+            &[&x.label(), &x_best, &x_moves, &o.label(), &o_best, &o_moves]
+        ))
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Post-game review: finds the first move that threw away part of the mover's
+// best result and shows what every alternative at that point would have led to.
+fn print_analysis(
+    stdout: &mut impl Write,
+    layout: Layout,
+    board: &[char],
+    history: &[(char, usize)],
+    lines: &[Vec<usize>],
+) -> Result<()> {
+    let (x, y) = layout.notes;
+    let y = y + 1;
+
+    let mut position = starting_position(board, history);
+
+    for (turn, &(mark, idx)) in history.iter().enumerate() {
+        let scores = score_moves(&position, mark, lines);
+        let best = scores.iter().map(|&(_, r)| r).max().unwrap_or(0);
+        let played = scores
+            .iter()
+            .find(|&&(cell, _)| cell == idx)
+            .map_or(best, |&(_, r)| r);
+        if played < best {
+            let [win, loss, draw] = text().outcome_letters;
+            let label = |result: i32| match result {
+                1 => win,
+                -1 => loss,
+                _ => draw,
+            };
+            // Both headings padded to the longer, so the columns line up.
+            let [cell_heading, result_heading] = text().analysis_rows;
+            let width = cell_heading
+                .chars()
+                .count()
+                .max(result_heading.chars().count());
+            let mut cells = format!("{:<width$}", cell_heading);
+            let mut results = format!("{:<width$}", result_heading);
+            for &(cell, result) in &scores {
+                cells.push_str(&format!(" {}", cell + 1));
+                results.push_str(&format!(" {}", label(result)));
+            }
+            queue!(
+                stdout,
+                MoveTo(x, y),
+                Print(fill(
+                    text().key_moment,
+                    &[&(turn + 1), &mark, &(idx + 1), &label(best), &label(played)]
+                )),
+                MoveTo(x, y + 1),
+                Print(cells),
+                MoveTo(x, y + 2),
+                Print(results)
+            )?;
+            stdout.flush()?;
+            return Ok(());
+        }
+        position[idx] = mark;
+    }
+
+    queue!(stdout, MoveTo(x, y), Print(text().no_mistakes))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn print_scoreboard(
+    stdout: &mut impl Write,
+    layout: Layout,
+    px: Score,
+    po: Score,
+    draws: Score,
+    [x_player, o_player]: &[Player; 2],
+    theme: &Theme,
+) -> Result<()> {
+    let (x, y) = layout.scores;
+    queue!(
+        stdout,
+        MoveTo(x, y),
+        SetForegroundColor(x_player.color),
+        Print(format!("{}: {} ", x_player.tag(), px)),
+        SetForegroundColor(o_player.color),
+        Print(format!("{}: {} ", o_player.tag(), po)),
+        SetForegroundColor(theme.hint),
+        Print(format!("{}: {}", text().draws, draws)),
+        ResetColor
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Series standing on the line under the scoreboard, or its champion once decided.
+fn print_series(
+    stdout: &mut impl Write,
+    layout: Layout,
+    series: &Series,
+    theme: &Theme,
+) -> Result<()> {
+    let (x, y) = layout.scores;
+    queue!(stdout, MoveTo(x, y + 1), Clear(ClearType::UntilNewLine))?;
+    if series.is_over() {
+        let banner = match series.winner() {
+            Some(mark) => fill(text().series_winner, &[&theme.glyph(mark)]),
+            None => text().series_drawn.to_string(),
+        };
+        queue!(
+            stdout,
+            SetForegroundColor(theme.hint),
+            Print(fill(
+                text().series_score,
+                &[&banner, &series.wins_x, &series.wins_o, &series.length]
+            )),
+            ResetColor
+        )?;
+    } else {
+        queue!(
+            stdout,
+            Print(fill(
+                text().series_running,
+                &[
+                    &theme.glyph(PLAYER_X),
+                    &series.wins_x,
+                    &theme.glyph(PLAYER_O),
+                    &series.wins_o,
+                    &series.length
+                ]
+            ))
+        )?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// After a tournament match: once a key is pressed under the final board, the
+// standings, the next pairing and who waits after them. Returns false when the
+// players would rather stop (always an option; after a champion the only other
+// one is a fresh tournament).
+fn show_standings(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    layout: Layout,
+    options: &Options,
+    tournament: &Tournament,
+    result: &str,
+) -> Result<bool> {
+    let (x, y) = layout.prompt;
+    queue!(
+        stdout,
+        MoveTo(x, y),
+        Clear(ClearType::UntilNewLine),
+        Print(text().standings_key)
+    )?;
+    stdout.flush()?;
+    while !matches!(next_input(input)?, Input::Key(_)) {}
+    draw_standings(stdout, options, tournament, result)?;
+    loop {
+        let code = match next_input(input)? {
+            Input::Key(event) => event.code,
+            Input::Resize => {
+                draw_standings(stdout, options, tournament, result)?;
+                continue;
+            }
+            _ => continue,
+        };
+        match code {
+            KeyCode::Esc => return Ok(false),
+            KeyCode::Char(c) if c == options.keymap.quit => return Ok(false),
+            KeyCode::Char('n' | 'N') => return Ok(true),
+            _ if tournament.champion().is_none() => return Ok(true),
+            _ => {}
+        }
+    }
+}
+
+fn draw_standings(
+    stdout: &mut impl Write,
+    options: &Options,
+    tournament: &Tournament,
+    result: &str,
+) -> Result<()> {
+    let theme = &options.theme;
+    clear_screen(stdout, options)?;
+    queue!(
+        stdout,
+        MoveTo(0, 0),
+        Print(if tournament.target == 1 {
+            text().tournament_one.to_string()
+        } else {
+            fill(text().tournament_title, &[&tournament.target])
+        }),
+        MoveTo(0, 2),
+        Print(result)
+    )?;
+    // Most wins first; level players stay in the order they were named.
+    let mut order: Vec<usize> = (0..tournament.players.len()).collect();
+    order.sort_by_key(|&player| std::cmp::Reverse(tournament.wins[player]));
+    let width = tournament
+        .players
+        .iter()
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut y = 4;
+    for player in order {
+        queue!(
+            stdout,
+            MoveTo(2, y),
+            Print(format!(
+                "{:<width$}  {}",
+                tournament.players[player], tournament.wins[player]
+            ))
+        )?;
+        y += 1;
+    }
+    y += 1;
+    match tournament.champion() {
+        Some(champion) => queue!(
+            stdout,
+            MoveTo(0, y),
+            SetForegroundColor(theme.hint),
+            Print(fill(
+                text().champion,
+                &[
+                    &champion,
+                    &if theme.ascii {
+                        text().ascii_badges[2]
+                    } else {
+                        "🏆"
+                    },
+                ]
+            )),
+            ResetColor,
+            MoveTo(0, y + 2),
+            Print(fill(text().new_tournament, &[&options.keymap.quit]))
+        )?,
+        None => {
+            let (x, o) = tournament.names();
+            let waiting: Vec<&str> = tournament
+                .queue
+                .iter()
+                .skip(1)
+                .map(|&player| tournament.players[player].as_str())
+                .collect();
+            queue!(
+                stdout,
+                MoveTo(0, y),
+                Print(fill(
+                    text().next_match,
+                    &[
+                        &x,
+                        &theme.glyph(PLAYER_X),
+                        &o,
+                        &theme.glyph(PLAYER_O),
+                        &waiting.join(", ")
+                    ]
+                )),
+                MoveTo(0, y + 2),
+                Print(fill(text().next_match_key, &[&options.keymap.quit]))
+            )?;
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// Quit summary; AI wins are broken down by the difficulty they were earned against.
+// Returns the first row below it.
+fn print_stats(stdout: &mut impl Write, layout: Layout, records: &[GameRecord]) -> Result<u16> {
+    let (x, mut y) = layout.notes;
+    queue!(
+        stdout,
+        MoveTo(x, y),
+        Print(fill(text().games_played, &[&records.len()]))
+    )?;
+
+    // Safe and Depth games are listed under each level played, after the
+    // adaptive ladder's.
+    let mut levels = DIFFICULTIES.to_vec();
+    for record in records {
+        if let GameMode::AI(level @ (Difficulty::Safe | Difficulty::Depth(_)), ..) = record.mode
+            && !levels.contains(&level)
+        {
+            levels.push(level);
+        }
+    }
+    for difficulty in levels {
+        let mut played = 0;
+        let mut won = 0;
+        for record in records {
+            if let GameMode::AI(d, player_first, _) = record.mode
+                && d == difficulty
+            {
+                let human = if player_first { PLAYER_X } else { PLAYER_O };
+                played += 1;
+                if record.winner == Some(human) {
+                    won += 1;
+                }
+            }
+        }
+        if played > 0 {
+            y += 1;
+            queue!(
+                stdout,
+                MoveTo(x, y),
+                Print(fill(
+                    text().beat_level,
+                    &[&level_name(difficulty), &won, &played]
+                ))
+            )?;
+        }
+    }
+
+    queue!(stdout, MoveTo(x, y + 1))?;
+    stdout.flush()?;
+    Ok(y + 1)
+}
+
+// Totals over every game of the session, whoever played them.
+struct SessionStats {
+    games: usize,
+    x_wins: usize,
+    o_wins: usize,
+    moves: usize,
+    streak: Option<(char, usize)>, // longest run of straight wins by one side
+}
+
+fn session_stats(records: &[GameRecord]) -> SessionStats {
+    let mut stats = SessionStats {
+        games: records.len(),
+        x_wins: 0,
+        o_wins: 0,
+        moves: 0,
+        streak: None,
+    };
+    let mut current: Option<(char, usize)> = None;
+    for record in records {
+        stats.moves += record.moves;
+        match record.winner {
+            Some(PLAYER_X) => stats.x_wins += 1,
+            Some(_) => stats.o_wins += 1,
+            None => {}
+        }
+        // A draw or a win by the other side starts the count again.
+        current = match (record.winner, current) {
+            (Some(winner), Some((side, run))) if side == winner => Some((side, run + 1)),
+            (Some(winner), _) => Some((winner, 1)),
+            (None, _) => None,
+        };
+        if let Some((_, run)) = current
+            && stats.streak.is_none_or(|(_, best)| run > best)
+        {
+            stats.streak = current;
+        }
+    }
+    stats
+}
+
+// Whole-number percentage; no games means nobody has won any.
+fn win_rate(wins: usize, games: usize) -> usize {
+    (wins * 100 + games / 2).checked_div(games).unwrap_or(0)
+}
+
+fn print_session_stats(
+    stdout: &mut impl Write,
+    (x, y): (u16, u16),
+    records: &[GameRecord],
+    theme: &Theme,
+) -> Result<()> {
+    let stats = session_stats(records);
+    let average = if stats.games == 0 {
+        0.0
+    } else {
+        stats.moves as f64 / stats.games as f64
+    };
+    let streak = match stats.streak {
+        Some((side, run)) => fill(text().streak, &[&theme.glyph(side), &run]),
+        None => text().no_streak.to_string(),
+    };
+    queue!(
+        stdout,
+        MoveTo(x, y),
+        Print(fill(
+            text().win_rates,
+            &[
+                &theme.glyph(PLAYER_X),
+                &win_rate(stats.x_wins, stats.games),
+                &theme.glyph(PLAYER_O),
+                &win_rate(stats.o_wins, stats.games),
+                &format!("{:.1}", average)
+            ]
+        )),
+        MoveTo(x, y + 1),
+        Print(fill(text().longest_streak, &[&streak])),
+        MoveTo(x, y + 2)
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// ======================================
+// INPUT
+// ======================================
+
+// What an input loop should react to, see `next_input`.
+enum Input {
+    Key(KeyEvent),
+    Click(u16, u16), // left button, screen column and row
+    Resize,
+    Ignored,
+}
+
+// Where the events every input loop reacts to come from: the terminal, or a
+// script of keys that plays a game through without one.
+trait InputSource {
+    fn next_event(&mut self) -> Result<Event>;
+    // Whether an event is waiting, after up to `timeout`.
+    fn poll(&mut self, timeout: Duration) -> Result<bool>;
+}
+
+// Key presses, clicks and resizes as crossterm reads them.
+struct Terminal;
+
+impl InputSource for Terminal {
+    fn next_event(&mut self) -> Result<Event> {
+        read()
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<bool> {
+        poll(timeout)
+    }
+}
+
+// Key presses played back in order, without waiting. Once they run out every
+// read fails with UnexpectedEof, which ends whatever was being played.
+struct ScriptedInput {
+    events: VecDeque<Event>,
+}
+
+impl ScriptedInput {
+    fn new(keys: impl IntoIterator<Item = KeyCode>) -> Self {
+        let events = keys
+            .into_iter()
+            .map(|code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE)))
+            .collect();
+        ScriptedInput { events }
+    }
+
+    // Each character typed in turn, '\n' as Enter: "519" plays cells 5, 1 and 9.
+    fn from_keys(keys: &str) -> Self {
+        ScriptedInput::new(keys.chars().map(|c| match c {
+            '\n' => KeyCode::Enter,
+            c => KeyCode::Char(c),
+        }))
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn next_event(&mut self) -> Result<Event> {
+        self.events
+            .pop_front()
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "the script ran out"))
+    }
+
+    fn poll(&mut self, _timeout: Duration) -> Result<bool> {
+        Ok(!self.events.is_empty())
+    }
+}
+
+// Waits up to `delay` while still honouring the quit keys; anything else is dropped.
+fn pause_for_input(input: &mut dyn InputSource, delay: Duration) -> Result<()> {
+    if input.poll(delay)? {
+        next_input(input)?;
+    }
+    Ok(())
+}
+
+// Shared event dispatch for every input loop, see `classify_event`. '?' opens
+// the help screen wherever it's pressed; closing it comes back as a resize, so
+// each loop draws its screen again the way it already does for one.
+fn next_input(input: &mut dyn InputSource) -> Result<Input> {
+    match classify_event(input.next_event()?)? {
+        Input::Key(KeyEvent {
+            code: KeyCode::Char('?'),
+            ..
+        }) if HELP.get().is_some() => {
+            show_help(&mut stdout(), input)?;
+            Ok(Input::Resize)
+        }
+        other => Ok(other),
+    }
+}
+
+// Keys go through `accept_key` (so quitting works everywhere), resizes are
+// reported so the caller can redraw, and focus changes, pastes, other mouse
+// events and modified keys come back as `Ignored`. Only presses count: a held
+// key's auto-repeats and the release that terminals with the kitty protocol
+// send would otherwise place marks or answer the next prompt on their own.
+fn classify_event(event: Event) -> Result<Input> {
+    match event {
+        Event::Key(event) if event.kind == KeyEventKind::Press && accept_key(&event)? => {
+            Ok(Input::Key(event))
+        }
+        Event::Mouse(event) if event.kind == MouseEventKind::Down(MouseButton::Left) => {
+            Ok(Input::Click(event.column, event.row))
+        }
+        Event::Resize(..) => Ok(Input::Resize),
+        _ => Ok(Input::Ignored),
+    }
+}
+
+// Ctrl+C / Ctrl+Q quit from any prompt by returning an `Interrupted` error, which
+// `main` treats as a clean exit: the scores are saved and `TerminalGuard` leaves
+// raw mode. Other Ctrl/Alt combos return false so they are never read as plain
+// input; Shift is fine since it only changes the letter case.
+fn accept_key(event: &KeyEvent) -> Result<bool> {
+    if is_ctrl_c(event) {
+        return Err(Error::new(ErrorKind::Interrupted, "quit"));
+    }
+    Ok(!event.modifiers.intersects(
+        KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SUPER | KeyModifiers::META,
+    ))
+}
+
+// In raw mode Ctrl+C arrives as a key rather than a signal. Caps Lock or Shift
+// can turn the letter upper case under the kitty protocol.
+fn is_ctrl_c(event: &KeyEvent) -> bool {
+    event.modifiers.contains(KeyModifiers::CONTROL)
+        && matches!(event.code, KeyCode::Char('c' | 'C' | 'q' | 'Q'))
+}
+
+// The cell one (row, column) step from `cursor`. At an edge it stays put, or
+// with `wrap` comes round to the far side of the same row or column.
+fn move_cursor(cursor: usize, size: usize, (dr, dc): (isize, isize), wrap: bool) -> usize {
+    let step = |at: usize, by: isize| {
+        let next = at as isize + by;
+        if wrap {
+            next.rem_euclid(size as isize) as usize
+        } else {
+            next.clamp(0, size as isize - 1) as usize
+        }
+    };
+    step(cursor / size, dr) * size + step(cursor % size, dc)
+}
+
+fn get_human_move(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    options: &Options,
+    state: &GameState,
+    cursor: &mut usize,
+    timer: &mut Timer,
+    rng: &mut impl Rng,
+) -> Result<Turn> {
+    let board = &state.board[..];
+    let size = board_size(board);
+    let layout = current_layout(size);
+    let mover = state.current_player;
+    // The game clock is charged from here to each key, not just per move, so
+    // it is still right when the turn ends in a resize.
+    let mut since = Instant::now();
+    stdout.flush()?;
+    loop {
+        if timer.run_down(mover, &mut since) {
+            return Ok(Turn::FlagFell);
+        }
+        // With a limit, poll in short ticks so the countdown keeps moving.
+        if let Some(deadline) = timer.deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(Turn::TimedOut);
+            }
+            print_time_left(stdout, layout, left, &options.theme)?;
+        }
+        if let Some(clocks) = timer.clocks {
+            print_clocks(stdout, layout, clocks, mover, &options.theme)?;
+        }
+        if let Some(left) = timer.time_left(mover)
+            && !input.poll(left.min(Duration::from_millis(250)))?
+        {
+            continue;
+        }
+        let next = next_input(input)?;
+        if timer.run_down(mover, &mut since) {
+            return Ok(Turn::FlagFell);
+        }
+        let event = match next {
+            Input::Key(event) => event,
+            // Clicks on the grid lines or off the board do nothing.
+            Input::Click(x, y) => {
+                let (left, top) = board_origin(size);
+                match x
+                    .checked_sub(left)
+                    .zip(y.checked_sub(top))
+                    .and_then(|(x, y)| cell_at(x, y, size))
+                {
+                    Some(idx) => match state.validate_move(idx) {
+                        Ok(()) => return Ok(Turn::Place(idx)),
+                        Err(err) => {
+                            refuse_move(stdout, state, &err, *cursor, options)?;
+                            continue;
+                        }
+                    },
+                    None => continue,
+                }
+            }
+            Input::Resize => return Ok(Turn::Resized),
+            Input::Ignored => continue,
+        };
+        let step = |direction| move_cursor(*cursor, size, direction, options.wrap_cursor);
+        let moved = match event.code {
+            KeyCode::Char(c) if c == options.keymap.undo => return Ok(Turn::Undo),
+            KeyCode::Char(c) if c == options.keymap.redo => return Ok(Turn::Redo),
+            KeyCode::Char(c) if c == options.keymap.quit => return Ok(Turn::Quit),
+            KeyCode::Char(c) if c == options.keymap.resign => return Ok(Turn::Resign),
+            KeyCode::Char(c) if c == options.keymap.offer_draw => return Ok(Turn::OfferDraw),
+            KeyCode::Esc => return Ok(Turn::Quit),
+            code if code == options.keymap.up => Some(step((-1, 0))),
+            code if code == options.keymap.down => Some(step((1, 0))),
+            code if code == options.keymap.left => Some(step((0, -1))),
+            code if code == options.keymap.right => Some(step((0, 1))),
+            code if code == options.keymap.place || code == KeyCode::Char(' ') => {
+                match state.validate_move(*cursor) {
+                    Ok(()) => return Ok(Turn::Place(*cursor)),
+                    Err(err) => refuse_move(stdout, state, &err, *cursor, options)?,
+                }
+                continue;
+            }
+            _ => None,
+        };
+        if let Some(next) = moved {
+            // Repaint just the two cells so the prompt and any message stay put.
+            queue!(stdout, SavePosition)?;
+            let last = state.last_move();
+            let look = CellLook {
+                last: last == Some(*cursor),
+                ..CellLook::default()
+            };
+            draw_cell(stdout, board, *cursor, look, &options.theme)?;
+            let look = CellLook {
+                cursor: true,
+                last: last == Some(next),
+                ..CellLook::default()
+            };
+            draw_cell(stdout, board, next, look, &options.theme)?;
+            queue!(stdout, RestorePosition)?;
+            stdout.flush()?;
+            *cursor = next;
+            continue;
+        }
+        if event.code == KeyCode::Char(options.keymap.hint) {
+            flash_hint(
+                stdout,
+                board,
+                suggest_move(state, rng),
+                *cursor,
+                &options.theme,
+            )?;
+            continue;
+        }
+        if options.announce && event.code == KeyCode::Char(options.keymap.read_board) {
+            print_board_reading(stdout, layout, board, &options.theme)?;
+            continue;
+        }
+        if let KeyCode::Char(c) = event.code
+            && let Some(d) = c.to_digit(10)
+            && let Some(idx) = read_cell_number(input, d, board.len())?
+        {
+            match state.validate_move(idx) {
+                Ok(()) => return Ok(Turn::Place(idx)),
+                Err(err) => refuse_move(stdout, state, &err, *cursor, options)?,
+            }
+            continue;
+        }
+        queue!(stdout, Print(format!("\n{}", text().try_again)))?;
+        signal_refusal(stdout, state, None, *cursor, options)?;
+    }
+}
+
+// Says why a typed or clicked cell can't be played, e.g. "Cell 5 is already
+// taken. Try again: ".
+fn refuse_move(
+    stdout: &mut impl Write,
+    state: &GameState,
+    err: &MoveError,
+    cursor: usize,
+    options: &Options,
+) -> Result<()> {
+    let reason = fill(text().retry, &[&move_error_text(err)]);
+    queue!(stdout, Print(format!("\n{}", reason)))?;
+    let tried = match *err {
+        MoveError::Occupied(idx) | MoveError::OutOfRange(idx) => Some(idx),
+        _ => None,
+    }
+    .filter(|&idx| idx < state.board.len());
+    signal_refusal(stdout, state, tried, cursor, options)
+}
+
+// Rings the bell for a refused move, or with --feedback flash turns `tried` red
+// (the grid lines when no cell on the board was named) and draws it as before
+// straight after. Keys pressed meanwhile stay queued for the input loop.
+fn signal_refusal(
+    stdout: &mut impl Write,
+    state: &GameState,
+    tried: Option<usize>,
+    cursor: usize,
+    options: &Options,
+) -> Result<()> {
+    match options.feedback {
+        Feedback::Beep => queue!(stdout, Print(BELL))?,
+        Feedback::Silent => {}
+        Feedback::Flash => {
+            let board = &state.board[..];
+            let origin = board_origin(board_size(board));
+            let look = |idx, refused| CellLook {
+                cursor: idx == cursor,
+                last: state.last_move() == Some(idx),
+                refused,
+                ..CellLook::default()
+            };
+            for flash in [true, false] {
+                queue!(stdout, SavePosition)?;
+                match tried {
+                    Some(idx) => draw_cell(stdout, board, idx, look(idx, flash), &options.theme)?,
+                    None => draw_grid_lines(stdout, board_size(board), origin, options, flash)?,
+                }
+                queue!(stdout, RestorePosition)?;
+                stdout.flush()?;
+                if flash {
+                    thread::sleep(FLASH_TIME);
+                }
+            }
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// Best move for the side to move: perfect play on 3×3, the Medium ladder on
+// bigger boards where a full search would take too long.
+fn suggest_move(state: &GameState, rng: &mut impl Rng) -> usize {
+    let player = state.current_player;
+    if board_size(&state.board) == 3 {
+        best_move(&state.board, player, state.lines())
+    } else {
+        let (board, lines) = (&state.board, state.lines());
+        get_ai_move_heuristic(board, lines, player, switch_player(player), rng).0
+    }
+}
+
+// Shows the suggested cell in the winning-line colour for a moment, then draws
+// it as before. Keys pressed meanwhile stay queued for the input loop.
+fn flash_hint(
+    stdout: &mut impl Write,
+    board: &[char],
+    idx: usize,
+    cursor: usize,
+    theme: &Theme,
+) -> Result<()> {
+    let (x, y) = cell_position(idx, board_size(board), board_origin(board_size(board)));
+    let label = bracketed(cell_label(board[idx], idx, theme), true);
+    let blank = " ".repeat(label.chars().count());
+    queue!(
+        stdout,
+        SavePosition,
+        MoveTo(x - 1, y),
+        SetForegroundColor(theme.highlight),
+        Print(label),
+        ResetColor,
+        RestorePosition
+    )?;
+    stdout.flush()?;
+    thread::sleep(Duration::from_millis(800));
+    queue!(stdout, SavePosition, MoveTo(x - 1, y), Print(blank))?;
+    let look = CellLook {
+        cursor: idx == cursor,
+        ..CellLook::default()
+    };
+    draw_cell(stdout, board, idx, look, theme)?;
+    queue!(stdout, RestorePosition)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Turns a typed digit into a cell index. On boards with more than nine cells a
+// digit that could still start a longer number waits for a second digit or Enter.
+fn read_cell_number(
+    input: &mut dyn InputSource,
+    first: u32,
+    cells: usize,
+) -> Result<Option<usize>> {
+    let mut number = first as usize;
+    if cells > 9 && (number == 0 || number * 10 <= cells) {
+        loop {
+            let Input::Key(event) = next_input(input)? else {
+                continue;
+            };
+            match event.code {
+                KeyCode::Char(c) if c.is_ascii_digit() => {
+                    number = number * 10 + c.to_digit(10).unwrap_or(0) as usize;
+                    break;
+                }
+                KeyCode::Enter => break,
+                _ => return Ok(None),
+            }
+        }
+    }
+    Ok(number.checked_sub(1))
+}
+
+// Column picked for the gravity variant; full columns are refused. None after a
+// resize, so the caller can draw the grid again.
+fn get_column(
+    stdout: &mut impl Write,
+    input: &mut dyn InputSource,
+    cells: &[char],
+    width: usize,
+) -> Result<Option<usize>> {
+    stdout.flush()?;
+    loop {
+        let event = match next_input(input)? {
+            Input::Key(event) => event,
+            Input::Resize => return Ok(None),
+            _ => continue,
+        };
+        if let KeyCode::Char(c) = event.code
+            && let Some(d) = c.to_digit(10)
+        {
+            let col = (d as usize).wrapping_sub(1);
+            if col < width && cells[col] == EMPTY {
+                return Ok(Some(col));
+            }
+        }
+        queue!(stdout, Print(format!("\n{}", text().column_full)))?;
+        stdout.flush()?;
+    }
+}
+
+// ======================================
+// AI
+// ======================================
+
+fn pick_ai_move(
+    board: &[char],
+    lines: &[Vec<usize>],
+    difficulty: Difficulty,
+    computer_mark: char,
+    rng: &mut impl Rng,
+) -> AiMove {
+    let human_mark = switch_player(computer_mark);
+    // Only Depth and Impossible search ahead; the others cost one node.
+    let (pos, nodes, reason) = match difficulty {
+        Difficulty::Easy => (get_ai_move_random(board, rng), 1, MoveReason::Random),
+        Difficulty::Safe => {
+            let (pos, reason) = get_ai_move_safe_random(board, lines, computer_mark, rng);
+            (pos, 1, reason)
+        }
+        Difficulty::Medium => {
+            let (pos, reason) = get_ai_move_heuristic(board, lines, computer_mark, human_mark, rng);
+            (pos, 1, reason)
+        }
+        Difficulty::Hard => {
+            let (pos, reason) = get_ai_move_blocking(board, lines, computer_mark, human_mark, rng);
+            (pos, 1, reason)
+        }
+        Difficulty::Depth(depth) => {
+            let (pos, nodes) = get_ai_move_minimax_depth(board, lines, computer_mark, depth, rng);
+            (pos, nodes, MoveReason::Lookahead)
+        }
+        Difficulty::Impossible => {
+            let (pos, nodes) = get_ai_move_minimax(board, lines, computer_mark, rng);
+            (pos, nodes, MoveReason::Search)
+        }
+        Difficulty::Adaptive => unreachable!("adaptive games pick a level before they start"),
+    };
+    AiMove { pos, nodes, reason }
+}
+
+// Runs `search` on a worker thread so a slow search doesn't freeze the screen:
+// "Computer is thinking..." animates over the turn hint and resizes trigger
+// `redraw` until the move arrives. A quick move still waits out `think`, unless
+// a key is pressed to skip it.
+fn wait_for_ai_move<W: Write>(
+    stdout: &mut W,
+    input: &mut dyn InputSource,
+    mut layout: Layout,
+    search: impl FnOnce() -> AiMove + Send + 'static,
+    think: Duration,
+    theme: &Theme,
+    redraw: &mut dyn FnMut(&mut W) -> Result<Layout>,
+) -> Result<AiMove> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(search());
+    });
+
+    let started = Instant::now();
+    let tick = Duration::from_millis(100);
+    let mut found = None;
+    let mut skipped = false;
+    loop {
+        match found {
+            Some(result) if skipped || started.elapsed() >= think => return Ok(result),
+            Some(_) => {
+                input.poll(tick)?;
+            }
+            None => match rx.recv_timeout(tick) {
+                Ok(result) => {
+                    found = Some(result);
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Error::other("AI search stopped without a move"));
+                }
+            },
+        }
+        while input.poll(Duration::ZERO)? {
+            // Moves typed during the computer's turn are dropped.
+            match next_input(input)? {
+                Input::Resize => layout = redraw(stdout)?,
+                Input::Key(_) => skipped = true,
+                _ => {}
+            }
+        }
+        // One to three dots, a new one every 300ms.
+        let dots = 1 + (started.elapsed().as_millis() / 300 % 3) as usize;
+        let (x, y) = layout.hint;
+        queue!(
+            stdout,
+            MoveTo(x, y),
+            Clear(ClearType::UntilNewLine),
+            SetForegroundColor(theme.computer),
+            Print(format!("{}{}", text().thinking, ".".repeat(dots))),
+            ResetColor
+        )?;
+        stdout.flush()?;
+    }
+}
+
+// Any empty cell, drawn from `rng`; a seeded one gives the same picks every
+// run, which is what --seed and the self-test rely on.
+fn get_ai_move_random(board: &[char], rng: &mut impl Rng) -> usize {
+    board
+        .iter()
+        .enumerate()
+        .filter(|&(_, &c)| c == EMPTY)
+        .map(|(i, _)| i)
+        .choose(rng)
+        .unwrap()
+}
+
+// A random cell among those after which the opponent can't complete a line
+// on their next move; a move that wins outright is always safe. When every
+// cell hands over a win, any of them will do.
+fn get_ai_move_safe_random(
+    board: &[char],
+    lines: &[Vec<usize>],
+    computer_mark: char,
+    rng: &mut impl Rng,
+) -> (usize, MoveReason) {
+    let opponent = switch_player(computer_mark);
+    let safe = (0..board.len())
+        .filter(|&idx| board[idx] == EMPTY)
+        .filter(|&idx| {
+            let mut next = board.to_vec();
+            next[idx] = computer_mark;
+            check_winner_at(&next, lines, idx).is_some()
+                || live_threats(&next, lines, opponent) == 0
+        })
+        .choose(rng);
+    match safe {
+        Some(idx) => (idx, MoveReason::Safe),
+        None => (get_ai_move_random(board, rng), MoveReason::Random),
+    }
+}
+
+// Full game-tree search, scored by `evaluate`, so the computer wins as fast as
+// it can and loses as late as it can.
+// Returns the move and the number of positions visited.
+fn get_ai_move_minimax(
+    board: &[char],
+    lines: &[Vec<usize>],
+    computer_mark: char,
+    rng: &mut impl Rng,
+) -> (usize, u64) {
+    let mut search = Search::new(board_size(board), lines, computer_mark, true, true);
+    let pos = search.best_move(board, rng);
+    (pos, search.nodes)
+}
+
+// Looks `depth` moves ahead, counting the computer's own, and scores the
+// positions it stops at with `evaluate`. Depth 1 takes a win when there
+// is one and otherwise the cell that does its lines the most good; each move
+// deeper sees one more reply coming.
+fn get_ai_move_minimax_depth(
+    board: &[char],
+    lines: &[Vec<usize>],
+    computer_mark: char,
+    depth: usize,
+    rng: &mut impl Rng,
+) -> (usize, u64) {
+    // The memo keeps exact scores, which a cut-off search doesn't have.
+    let mut search = Search::new(board_size(board), lines, computer_mark, true, false);
+    search.horizon = Some(depth as i32);
+    let pos = search.best_move(board, rng);
+    (pos, search.nodes)
+}
+
+// One search from a root position. `prune` turns on alpha-beta cut-offs and
+// `memo` the table of positions already searched; with both off this is the
+// plain minimax the faster versions are checked against.
+struct Search<'a> {
+    lines: &'a [Vec<usize>],
+    computer_mark: char,
+    prune: bool,
+    memo: Option<SearchMemo>,
+    order: Vec<usize>, // centre first, then corners, then the rest
+    nodes: u64,
+    horizon: Option<i32>, // moves searched before judging the position instead
+}
+
+impl<'a> Search<'a> {
+    fn new(
+        size: usize,
+        lines: &'a [Vec<usize>],
+        computer_mark: char,
+        prune: bool,
+        memo: bool,
+    ) -> Self {
+        let mut order: Vec<usize> = (0..size * size).collect();
+        order.sort_by_key(|&idx| square_rank(size)(idx));
+        Search {
+            lines,
+            computer_mark,
+            prune,
+            memo: memo.then(|| SearchMemo::new(size, lines)),
+            order,
+            nodes: 0,
+            horizon: None,
+        }
+    }
+
+    // Exact score of every free cell, each searched with a full window.
+    fn scores(&mut self, board: &[char]) -> Vec<(usize, i32)> {
+        let mut board = board.to_vec();
+        let mut scores = Vec::new();
+        for idx in 0..board.len() {
+            if board[idx] != EMPTY {
+                continue;
+            }
+            board[idx] = self.computer_mark;
+            scores.push((idx, self.minimax(&mut board, false, 1, i32::MIN, i32::MAX)));
+            board[idx] = EMPTY;
+        }
+        scores
+    }
+
+    // A best cell, picked at random among equally good ones so perfect play
+    // doesn't open the same way every game. Each move only has to show it is at
+    // least as good as the best so far, which lets the pruned search cut the
+    // replies to worse moves short.
+    fn best_move(&mut self, board: &[char], rng: &mut impl Rng) -> usize {
+        let mut board = board.to_vec();
+        let mut best: Option<i32> = None;
+        let mut ties = Vec::new();
+        for i in 0..self.order.len() {
+            let idx = self.order[i];
+            if board[idx] != EMPTY {
+                continue;
+            }
+            // One below the best so far, so that an equal score comes back exact.
+            let alpha = best.map_or(i32::MIN, |score| score - 1);
+            board[idx] = self.computer_mark;
+            let score = self.minimax(&mut board, false, 1, alpha, i32::MAX);
+            board[idx] = EMPTY;
+            match best {
+                Some(best_score) if score < best_score => {}
+                Some(best_score) if score == best_score => ties.push(idx),
+                _ => {
+                    best = Some(score);
+                    ties = vec![idx];
+                }
+            }
+        }
+        ties.into_iter()
+            .choose(rng)
+            .expect("minimax called on a full board")
+    }
+
+    // Score of `board` if it lies between `alpha` and `beta`; outside that window a
+    // pruned search only promises a bound on the correct side.
+    fn minimax(
+        &mut self,
+        board: &mut [char],
+        computer_to_move: bool,
+        depth: i32,
+        mut alpha: i32,
+        mut beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        if let Some(score) = final_score(board, self.lines, depth, self.computer_mark) {
+            return score;
+        }
+        if self.horizon.is_some_and(|horizon| depth >= horizon) {
+            return evaluate(board, self.lines, depth, self.computer_mark);
+        }
+        let key = self.memo.as_ref().map(|memo| memo.key(board));
+        if let (Some(memo), Some(key)) = (&self.memo, &key)
+            && let Some(&(score, bound)) = memo.scores.get(key)
+        {
+            match bound {
+                Bound::Exact => return score,
+                Bound::Lower => alpha = alpha.max(score),
+                Bound::Upper => beta = beta.min(score),
+            }
+            if alpha >= beta {
+                return score;
+            }
+        }
+        let window = (alpha, beta);
+
+        let mark = if computer_to_move {
+            self.computer_mark
+        } else {
+            switch_player(self.computer_mark)
+        };
+        let mut best = if computer_to_move { i32::MIN } else { i32::MAX };
+        for i in 0..self.order.len() {
+            let idx = self.order[i];
+            if board[idx] != EMPTY {
+                continue;
+            }
+            board[idx] = mark;
+            let score = self.minimax(board, !computer_to_move, depth + 1, alpha, beta);
+            board[idx] = EMPTY;
+            if computer_to_move {
+                best = best.max(score);
+            } else {
+                best = best.min(score);
+            }
+            if self.prune {
+                if computer_to_move {
+                    alpha = alpha.max(best);
+                } else {
+                    beta = beta.min(best);
+                }
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        if let (Some(memo), Some(key)) = (&mut self.memo, key) {
+            let bound = if best <= window.0 {
+                Bound::Upper
+            } else if best >= window.1 {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            memo.scores.insert(key, (best, bound));
+        }
+        best
+    }
+}
+
+// `evaluate` for a finished game, None while it's still going. The search asks
+// this first at every position, so only the ones it stops at early pay for
+// counting the open lines.
+fn final_score(
+    board: &[char],
+    lines: &[Vec<usize>],
+    depth: i32,
+    maximizing_mark: char,
+) -> Option<i32> {
+    match game_result(board, lines) {
+        GameResult::Ongoing => None,
+        GameResult::Draw => Some(0),
+        result if result.winner() == Some(maximizing_mark) => Some(WIN_SCORE - depth),
+        _ => Some(depth - WIN_SCORE),
+    }
+}
+
+// How good `board` is for `maximizing_mark`, `depth` moves into a search.
+// A finished game scores WIN_SCORE less the depth for a win, the negative of
+// that for a loss and 0 for a draw. A position the search stops short of the
+// end at counts every line still open to only one side for that side, the
+// square of its marks so that nearly full lines outweigh several barely
+// started ones.
+fn evaluate(board: &[char], lines: &[Vec<usize>], depth: i32, maximizing_mark: char) -> i32 {
+    if let Some(score) = final_score(board, lines, depth, maximizing_mark) {
+        return score;
+    }
+    let opponent = switch_player(maximizing_mark);
+    lines
+        .iter()
+        .map(|line| {
+            let count = |mark| line.iter().filter(|&&idx| board[idx] == mark).count() as i32;
+            match (count(maximizing_mark), count(opponent)) {
+                (mine, 0) => mine * mine,
+                (0, theirs) => -theirs * theirs,
+                _ => 0,
+            }
+        })
+        .sum()
+}
+
+// How a memo score relates to the true one: a pruned search that left its
+// window only knows the score is at least (Lower) or at most (Upper) this.
+#[derive(Clone, Copy)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+// Scores of positions already searched, filed under the smallest of their
+// rotations and reflections so that mirrored lines of play are searched once.
+// A score counts plies from the root, so a memo only serves a single search.
+struct SearchMemo {
+    symmetries: Vec<Vec<usize>>,
+    scores: HashMap<Vec<char>, (i32, Bound)>,
+}
+
+impl SearchMemo {
+    fn new(size: usize, lines: &[Vec<usize>]) -> Self {
+        SearchMemo {
+            symmetries: board_symmetries(size, lines),
+            scores: HashMap::new(),
+        }
+    }
+
+    fn key(&self, board: &[char]) -> Vec<char> {
+        self.symmetries
+            .iter()
+            .map(|map| {
+                let mut image = vec![EMPTY; board.len()];
+                for (idx, &to) in map.iter().enumerate() {
+                    image[to] = board[idx];
+                }
+                image
+            })
+            .min()
+            .unwrap_or_else(|| board.to_vec())
+    }
+}
+
+// The rotations and reflections of a size × size board, each as a map from a
+// cell to where it lands, keeping only those that carry every winning line onto
+// another one.
+fn board_symmetries(size: usize, lines: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = size - 1;
+    // Identity, three quarter turns, then the four mirror images.
+    let transform = |kind: usize, r: usize, c: usize| match kind {
+        0 => (r, c),
+        1 => (c, n - r),
+        2 => (n - r, n - c),
+        3 => (n - c, r),
+        4 => (r, n - c),
+        5 => (n - r, c),
+        6 => (c, r),
+        _ => (n - c, n - r),
+    };
+    let sorted = |line: &[usize]| {
+        let mut line = line.to_vec();
+        line.sort_unstable();
+        line
+    };
+    let line_set: HashSet<Vec<usize>> = lines.iter().map(|line| sorted(line)).collect();
+    (0..8)
+        .map(|kind| {
+            (0..size * size)
+                .map(|idx| {
+                    let (row, col) = transform(kind, idx / size, idx % size);
+                    row * size + col
+                })
+                .collect::<Vec<usize>>()
+        })
+        .filter(|map| {
+            lines.iter().all(|line| {
+                let image: Vec<usize> = line.iter().map(|&idx| map[idx]).collect();
+                line_set.contains(&sorted(&image))
+            })
+        })
+        .collect()
+}
+
+// Early positions on the classic board and the reply to each, row by row with
+// '.' for a free cell. Rotations and mirror images count as the same position.
+const OPENING_BOOK: [(&str, usize); 5] = [
+    (".........", 4), // the centre is in the most lines
+    ("X........", 4), // after a corner, anything but the centre loses
+    (".X.......", 4),
+    ("....X....", 0), // after the centre, an edge loses
+    ("X...O...X", 1), // opposite corners: a corner reply walks into a fork
+];
+
+// The book reply when it's `computer_mark`'s turn in one of the booked
+// positions. Only meaningful on a plain 3×3 board; callers check the lines.
+fn lookup_opening(board: &[char], computer_mark: char) -> Option<usize> {
+    if board.len() != 9 || player_to_move(board) != computer_mark {
+        return None;
+    }
+    let lines = win_lines(3, 3, Variant::Standard);
+    board_symmetries(3, &lines).into_iter().find_map(|map| {
+        OPENING_BOOK.iter().find_map(|&(cells, reply)| {
+            cells
+                .chars()
+                .enumerate()
+                .all(|(idx, c)| board[map[idx]] == if c == '.' { EMPTY } else { c })
+                .then(|| map[reply])
+        })
+    })
+}
+
+// The book move for Medium and Hard, on the classic board only.
+fn book_move(board: &[char], lines: &[Vec<usize>], computer_mark: char) -> Option<usize> {
+    lookup_opening(board, computer_mark).filter(|_| lines == win_lines(3, 3, Variant::Standard))
+}
+
+// Completes a line of the computer's, else blocks one of the human's.
+fn win_or_block(
+    board: &[char],
+    lines: &[Vec<usize>],
+    computer_mark: char,
+    human_mark: char,
+) -> Option<(usize, MoveReason)> {
+    for (mark, reason) in [
+        (computer_mark, MoveReason::Win),
+        (human_mark, MoveReason::Block),
+    ] {
+        for line in two_in_a_rows(board, lines, mark) {
+            if let Some(&idx) = line.iter().find(|&&i| board[i] == EMPTY) {
+                return Some((idx, reason));
+            }
+        }
     }
     None
 }
+
+// Wins if it can, blocks if it must, then forks or heads off the human's fork;
+// otherwise plays anywhere.
+fn get_ai_move_blocking(
+    board: &[char],
+    lines: &[Vec<usize>],
+    computer_mark: char,
+    human_mark: char,
+    rng: &mut impl Rng,
+) -> (usize, MoveReason) {
+    if let Some(idx) = book_move(board, lines, computer_mark) {
+        return (idx, MoveReason::Book);
+    }
+    if let Some(found) = win_or_block(board, lines, computer_mark, human_mark) {
+        return found;
+    }
+    if let Some(&idx) = fork_cells(board, lines, computer_mark).first() {
+        return (idx, MoveReason::DoubleThreat);
+    }
+    match fork_cells(board, lines, human_mark)[..] {
+        [] => {}
+        [idx] => return (idx, MoveReason::Fork),
+        // Only one of several fork cells can be taken, and taking the wrong one
+        // loses (corners against X on opposite corners). Forcing a block
+        // somewhere harmless keeps the human busy instead.
+        [first, ..] => {
+            let forcing = forcing_move(board, lines, computer_mark, human_mark);
+            return forcing.map_or((first, MoveReason::Fork), |idx| (idx, MoveReason::Force));
+        }
+    }
+
+    (get_ai_move_random(board, rng), MoveReason::Random)
+}
+
+// Free cells where `mark` would open two threats at once.
+fn fork_cells(board: &[char], lines: &[Vec<usize>], mark: char) -> Vec<usize> {
+    (0..board.len())
+        .filter(|&idx| board[idx] == EMPTY)
+        .filter(|&idx| {
+            let mut next = board.to_vec();
+            next[idx] = mark;
+            live_threats(&next, lines, mark) >= 2
+        })
+        .collect()
+}
+
+// A move that makes a threat whose block doesn't give the human a fork.
+fn forcing_move(
+    board: &[char],
+    lines: &[Vec<usize>],
+    computer_mark: char,
+    human_mark: char,
+) -> Option<usize> {
+    (0..board.len())
+        .filter(|&idx| board[idx] == EMPTY)
+        .find(|&idx| {
+            let mut next = board.to_vec();
+            next[idx] = computer_mark;
+            let blocks: Vec<usize> = two_in_a_rows(&next, lines, computer_mark)
+                .iter()
+                .filter_map(|line| line.iter().copied().find(|&i| next[i] == EMPTY))
+                .collect();
+            !blocks.is_empty()
+                && blocks.iter().all(|&block| {
+                    let mut after = next.clone();
+                    after[block] = human_mark;
+                    live_threats(&after, lines, human_mark) < 2
+                })
+        })
+}
+
+// The classic ladder: win, block, take away a fork, then centre, corner, edge.
+fn get_ai_move_heuristic(
+    board: &[char],
+    lines: &[Vec<usize>],
+    computer_mark: char,
+    human_mark: char,
+    rng: &mut impl Rng,
+) -> (usize, MoveReason) {
+    if let Some(idx) = book_move(board, lines, computer_mark) {
+        return (idx, MoveReason::Book);
+    }
+    if let Some(found) = win_or_block(board, lines, computer_mark, human_mark) {
+        return found;
+    }
+    let free = (0..board.len()).filter(|&idx| board[idx] == EMPTY);
+    // A cell where the human would open two threats at once can't be defended later.
+    let fork = free.clone().find(|&idx| {
+        let mut next = board.to_vec();
+        next[idx] = human_mark;
+        live_threats(&next, lines, human_mark) >= 2
+    });
+    if let Some(idx) = fork {
+        return (idx, MoveReason::Fork);
+    }
+    let rank = square_rank(board_size(board));
+    let best = free.clone().map(&rank).min().unwrap_or(0);
+    let pos = free
+        .filter(|&idx| rank(idx) == best)
+        .choose(rng)
+        .expect("heuristic AI called on a full board");
+    (pos, MoveReason::Position)
+}
+
+// 0 for the centre square(s), 1 for corners, 2 for everything else.
+fn square_rank(size: usize) -> impl Fn(usize) -> u8 {
+    move |idx| {
+        let (row, col) = (idx / size, idx % size);
+        let middle = |i: usize| i == (size - 1) / 2 || i == size / 2;
+        let edge = |i: usize| i == 0 || i == size - 1;
+        if middle(row) && middle(col) {
+            0
+        } else if edge(row) && edge(col) {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+// Lines `mark` could complete on its next move.
+fn live_threats(board: &[char], lines: &[Vec<usize>], mark: char) -> usize {
+    two_in_a_rows(board, lines, mark)
+        .iter()
+        .filter(|line| line.iter().any(|&idx| board[idx] == EMPTY))
+        .count()
+}
+
+// Lines one mark short of a win for `mark`; the last cell is empty (a live
+// threat) or taken by the opponent (a blocked one).
+fn two_in_a_rows(board: &[char], lines: &[Vec<usize>], mark: char) -> Vec<Vec<usize>> {
+    lines
+        .iter()
+        .filter(|line| line.iter().filter(|&&i| board[i] == mark).count() == line.len() - 1)
+        .cloned()
+        .collect()
+}
+
+fn all_two_in_a_rows(board: &[char], lines: &[Vec<usize>]) -> Vec<(char, Vec<usize>)> {
+    [PLAYER_X, PLAYER_O]
+        .iter()
+        .flat_map(|&mark| {
+            two_in_a_rows(board, lines, mark)
+                .into_iter()
+                .map(move |line| (mark, line))
+        })
+        .collect()
+}
+
+// Rewinds a finished board to where the game began (which may come from the editor).
+fn starting_position(board: &[char], history: &[(char, usize)]) -> Vec<char> {
+    let mut position = board.to_vec();
+    for &(_, idx) in history {
+        position[idx] = EMPTY;
+    }
+    position
+}
+
+// Result for `to_move` under perfect play from both sides: 1 win, 0 draw, -1 loss.
+fn solve(board: &mut [char], to_move: char, lines: &[Vec<usize>]) -> i32 {
+    match game_result(board, lines) {
+        GameResult::Ongoing => {}
+        GameResult::Draw => return 0,
+        won => return if won.winner() == Some(to_move) { 1 } else { -1 },
+    }
+    let mut best = -1;
+    for idx in 0..board.len() {
+        if board[idx] != EMPTY {
+            continue;
+        }
+        board[idx] = to_move;
+        best = best.max(-solve(board, switch_player(to_move), lines));
+        board[idx] = EMPTY;
+        if best == 1 {
+            break;
+        }
+    }
+    best
+}
+
+// First move with the best perfect-play result for `to_move`.
+fn best_move(board: &[char], to_move: char, lines: &[Vec<usize>]) -> usize {
+    score_moves(board, to_move, lines)
+        .into_iter()
+        .max_by_key(|&(idx, result)| (result, std::cmp::Reverse(idx)))
+        .map(|(idx, _)| idx)
+        .expect("best_move called on a full board")
+}
+
+// Every legal move for `to_move` with the result it leads to under perfect play.
+fn score_moves(board: &[char], to_move: char, lines: &[Vec<usize>]) -> Vec<(usize, i32)> {
+    let mut board = board.to_vec();
+    let mut scores = Vec::new();
+    for idx in 0..board.len() {
+        if board[idx] != EMPTY {
+            continue;
+        }
+        board[idx] = to_move;
+        scores.push((idx, -solve(&mut board, switch_player(to_move), lines)));
+        board[idx] = EMPTY;
+    }
+    scores
+}
+
+// Best and worst result still reachable for `human` (1 win, 0 draw, -1 loss),
+// assuming the computer always answers with its strongest move.
+fn result_range(
+    board: &mut [char],
+    to_move: char,
+    human: char,
+    lines: &[Vec<usize>],
+) -> (i32, i32) {
+    match game_result(board, lines) {
+        GameResult::Ongoing => {}
+        GameResult::Draw => return (0, 0),
+        won => {
+            let result = if won.winner() == Some(human) { 1 } else { -1 };
+            return (result, result);
+        }
+    }
+
+    let mut children = Vec::new();
+    for idx in 0..board.len() {
+        if board[idx] != EMPTY {
+            continue;
+        }
+        board[idx] = to_move;
+        children.push(result_range(board, switch_player(to_move), human, lines));
+        board[idx] = EMPTY;
+    }
+
+    if to_move == human {
+        let best = children.iter().map(|&(b, _)| b).max().unwrap();
+        let worst = children.iter().map(|&(_, w)| w).min().unwrap();
+        (best, worst)
+    } else {
+        // The computer only ever picks one of the moves that hold the human to `best`.
+        let best = children.iter().map(|&(b, _)| b).min().unwrap();
+        let worst = children
+            .iter()
+            .filter(|&&(b, _)| b == best)
+            .map(|&(_, w)| w)
+            .min()
+            .unwrap();
+        (best, worst)
+    }
+}
+
+// ======================================
+// FILES
+// ======================================
+
+// Files kept in the home directory between runs.
+fn home_file(name: &str) -> Result<std::path::PathBuf> {
+    let home = std::env::var_os("HOME")
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "HOME is not set"))?;
+    Ok(std::path::Path::new(&home).join(name))
+}
+
+// No file means no defaults. A file that can't be used is skipped whole, with
+// a warning, so a typo never turns half the settings on.
+fn load_config() -> Config {
+    let Ok(path) = home_file(".tictactoe.toml") else {
+        return Config::default();
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Config::default(),
+        Err(err) => {
+            eprintln!("Ignoring {}: {}", path.display(), err);
+            return Config::default();
+        }
+    };
+    parse_config(&text).unwrap_or_else(|message| {
+        eprintln!("Ignoring {}: {}", path.display(), message);
+        Config::default()
+    })
+}
+
+// Kept next to the scores but in a file of its own, so an older score file
+// still reads.
+fn fastest_win_path() -> Result<std::path::PathBuf> {
+    home_file(".tictactoe_record.json")
+}
+
+// None until the first human win, and for a file that can't be read.
+fn load_fastest_win() -> Option<FastestWin> {
+    let text = fs::read_to_string(fastest_win_path().ok()?).ok()?;
+    parse_fastest_win(&text)
+}
+
+fn save_fastest_win(win: FastestWin) -> Result<()> {
+    fs::write(fastest_win_path()?, format_fastest_win(win))
+}
+
+fn format_fastest_win(win: FastestWin) -> String {
+    format!("{{\"millis\": {}, \"moves\": {}}}\n", win.millis, win.moves)
+}
+
+fn parse_fastest_win(text: &str) -> Option<FastestWin> {
+    let text = text.trim();
+    if !text.starts_with('{') || !text.ends_with('}') {
+        return None;
+    }
+    Some(FastestWin {
+        millis: json_number(text, "millis")?,
+        moves: json_number(text, "moves")? as usize,
+    })
+}
+
+// Keeps `win` if it is the first on record or quicker than the best so far,
+// and says so for the winner's line.
+fn beat_fastest_win(best: &mut Option<FastestWin>, win: FastestWin) -> Option<String> {
+    let seconds = |win: FastestWin| format!("{:.1}s", win.millis as f64 / 1000.0);
+    let note = match *best {
+        None => fill(text().first_record, &[&seconds(win)]),
+        Some(old) if win.millis < old.millis => {
+            fill(text().new_record, &[&seconds(win), &seconds(old)])
+        }
+        Some(_) => return None,
+    };
+    *best = Some(win);
+    Some(note)
+}
+
+fn scores_path() -> Result<std::path::PathBuf> {
+    home_file(".tictactoe_scores.json")
+}
+
+fn load_scores() -> Result<(Score, Score, Score)> {
+    let text = match fs::read_to_string(scores_path()?) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Default::default()),
+        Err(err) => return Err(err),
+    };
+    parse_scores(&text).ok_or_else(|| Error::new(ErrorKind::InvalidData, "corrupt score file"))
+}
+
+fn save_scores((x, o, draws): (Score, Score, Score)) -> Result<()> {
+    fs::write(scores_path()?, format_scores(x, o, draws))
+}
+
+fn format_scores(x: Score, o: Score, draws: Score) -> String {
+    format!("{{\"x\": {}, \"o\": {}, \"draws\": {}}}\n", x, o, draws)
+}
+
+// Reads back what `format_scores` wrote; anything else counts as corrupt.
+fn parse_scores(text: &str) -> Option<(Score, Score, Score)> {
+    // A count too big for a Score is kept at the most it can hold rather than
+    // losing the other two with it.
+    let field =
+        |key: &str| json_number(text, key).map(|n| Score(u32::try_from(n).unwrap_or(u32::MAX)));
+    let text = text.trim();
+    if !text.starts_with('{') || !text.ends_with('}') {
+        return None;
+    }
+    Some((field("x")?, field("o")?, field("draws")?))
+}
+
+// The whole number after `"key":` in one of the small JSON files above.
+fn json_number(text: &str, key: &str) -> Option<u64> {
+    let quoted = format!("\"{}\"", key);
+    let rest = &text[text.find(&quoted)? + quoted.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+// "XO.XO..X." — marks in cell order, dots for empty cells.
+fn encode_board(board: &[char]) -> String {
+    board
+        .iter()
+        .map(|&c| {
+            if c == PLAYER_X || c == PLAYER_O {
+                c
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+// "ai" and "hard", "friend" and "-", or "ai-vs-ai" and both levels as "hard/easy".
+fn mode_fields(mode: GameMode) -> (&'static str, String) {
+    match mode {
+        GameMode::Friend => ("friend", "-".to_string()),
+        GameMode::AI(difficulty, ..) => ("ai", difficulty.to_string().to_lowercase()),
+        GameMode::AIvsAI(x_difficulty, o_difficulty) => (
+            "ai-vs-ai",
+            format!("{}/{}", x_difficulty, o_difficulty).to_lowercase(),
+        ),
+    }
+}
+
+fn winner_field(winner: Option<char>) -> String {
+    match winner {
+        Some(mark) => mark.to_string(),
+        None => "draw".to_string(),
+    }
+}
+
+// One line per game: unix time, mode, difficulty, winner, move count, final board.
+fn format_result_line(record: &GameRecord, board: &[char]) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (mode, difficulty) = mode_fields(record.mode);
+    let winner = winner_field(record.winner);
+    format!(
+        "{} mode={} difficulty={} winner={} moves={} board={}",
+        timestamp,
+        mode,
+        difficulty,
+        winner,
+        record.moves,
+        encode_board(board)
+    )
+}
+
+// Appends one finished game to ~/.tictactoe_games.txt.
+fn write_game_record(
+    board: &[char],
+    history: &[(char, usize)],
+    winner: Option<char>,
+) -> Result<()> {
+    let path = home_file(".tictactoe_games.txt")?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", format_game_record(board, history, winner))
+}
+
+// "1760000000 X won from=.........: X:5 O:1 X:9 O:3 X:7" — unix time, outcome,
+// the position the game started from (which gives the board size), then each
+// move. `board` is the final position, `history` the moves that led to it.
+fn format_game_record(board: &[char], history: &[(char, usize)], winner: Option<char>) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let outcome = match winner {
+        Some(mark) => format!("{} won", mark),
+        None => "draw".to_string(),
+    };
+    let moves: Vec<String> = history
+        .iter()
+        .map(|&(mark, idx)| format!("{}:{}", mark, idx + 1))
+        .collect();
+    format!(
+        "{} {} from={}: {}",
+        timestamp,
+        outcome,
+        encode_board(&starting_position(board, history)),
+        moves.join(" ")
+    )
+}
+
+// The last game in a record file, played out from the position it started
+// from. Records written before the start was saved begin on an empty board of
+// the chosen size.
+fn load_saved_game(
+    path: &str,
+    options: &Options,
+) -> std::result::Result<(GameState, Vec<(char, usize)>), String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let line = text
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .ok_or("the file holds no games")?;
+    let mut state = record_start(line, options)?;
+    let history = parse_game_record(line, &mut state)?;
+    Ok((state, history))
+}
+
+// The board named by a record's from= field, or an empty one without it.
+fn record_start(line: &str, options: &Options) -> std::result::Result<GameState, String> {
+    let (header, _) = line
+        .split_once(": ")
+        .ok_or("the last line isn't a game record")?;
+    let Some(cells) = header
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("from="))
+    else {
+        return Ok(GameState::new(
+            options.board_size(),
+            options.win_length(),
+            options.variant,
+        ));
+    };
+    let board = parse_board(cells).map_err(|err| format!("its start position: {}", err))?;
+    let win_length = options.win_length_on(board_size(&board));
+    GameState::from_board(board, win_length, options.variant)
+        .map_err(|err| format!("its start position: {}", err))
+}
+
+// Reads the moves of a `format_game_record` line and plays them on `state`, so
+// a move that is malformed, off the board or onto a taken cell stops with its
+// number. The marks are taken as written: a coin flip can give O the first
+// move, and a free-move pass gives one side two in a row.
+fn parse_game_record(
+    line: &str,
+    state: &mut GameState,
+) -> std::result::Result<Vec<(char, usize)>, String> {
+    let (_, moves) = line
+        .split_once(": ")
+        .ok_or("the last line isn't a game record")?;
+    let cells = state.board.len();
+    let mut history = Vec::new();
+    for (turn, token) in moves.split_whitespace().enumerate() {
+        let turn = turn + 1;
+        let parsed = token.split_once(':').and_then(|(mark, cell)| {
+            let mark = match mark {
+                "X" => PLAYER_X,
+                "O" => PLAYER_O,
+                _ => return None,
+            };
+            Some((mark, cell.parse::<usize>().ok()?))
+        });
+        let Some((mark, cell)) = parsed else {
+            return Err(format!("move {} ({}) isn't written like X:5", turn, token));
+        };
+        if !(1..=cells).contains(&cell) {
+            return Err(format!(
+                "move {} ({}) is off the {} cell board",
+                turn, token, cells
+            ));
+        }
+        state.current_player = mark;
+        state
+            .apply_move(cell - 1)
+            .map_err(|err| format!("move {} ({}) is illegal: {}", turn, token, err))?;
+        history.push((mark, cell - 1));
+    }
+    Ok(history)
+}
+
+fn append_result_line(path: &str, line: &str) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+const CSV_HEADER: &str = "timestamp,mode,difficulty,winner,moves";
+
+// Appends one finished game to the CSV file at `path`, starting a missing or
+// empty file with the header row.
+fn write_csv_row(path: &str, record: &GameRecord) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        writeln!(file, "{}", CSV_HEADER)?;
+    }
+    writeln!(file, "{}", format_csv_row(record))
+}
+
+// "1760000000,ai,hard,O,6" — the fields of `CSV_HEADER`, none of which can hold
+// a comma or a quote, so nothing needs quoting.
+fn format_csv_row(record: &GameRecord) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (mode, difficulty) = mode_fields(record.mode);
+    format!(
+        "{},{},{},{},{}",
+        timestamp,
+        mode,
+        difficulty,
+        winner_field(record.winner),
+        record.moves
+    )
+}
+
+// ======================================
+// SELF-TEST
+// ======================================
+
+type CheckResult = std::result::Result<(), String>;
+type Check = (&'static str, fn() -> CheckResult);
+
+// Runs every engine check and prints one line per check; true if all passed.
+fn run_self_test() -> bool {
+    let checks: &[Check] = &[
+        ("AI moves are legal on random boards", check_ai_moves_legal),
+        (
+            "perfect play from the empty board draws",
+            check_perfect_play_draws,
+        ),
+        ("scores saturate instead of wrapping", check_score_saturates),
+        ("the config file is read", check_config_file),
+        ("games are exported as CSV rows", check_csv_export),
+        ("only a quicker win sets a record", check_fastest_win),
+        (
+            "tournament winners stay on and losers queue",
+            check_tournament_rotation,
+        ),
+        ("players show their names", check_player_names),
+        (
+            "refused moves beep, flash or stay quiet",
+            check_refusal_feedback,
+        ),
+        ("game clocks run down on their own turns", check_game_clocks),
+        ("scripted keys play a human turn", check_scripted_input),
+        ("scripted keys play a whole game", check_scripted_game),
+        ("the play-again prompt lists its keys", check_replay_keys),
+        ("a replay ends on the quit key", check_replay_quit),
+        ("every language fills the same blanks", check_translations),
+        ("safe random never hands over a win", check_safe_random),
+        ("the help screen fits the board", check_help_screen),
+        ("key bindings refuse clashes", check_bind_key),
+        ("quicker wins score higher", check_win_scores),
+        ("the cursor wraps at all four edges", check_cursor_wraps),
+        (
+            "accuracy counts each side's best moves",
+            check_move_accuracy,
+        ),
+        (
+            "depth search sees further as it deepens",
+            check_depth_search,
+        ),
+        (
+            "held keys and releases are not read as presses",
+            check_key_repeats_ignored,
+        ),
+        (
+            "adaptive games follow the human's results",
+            check_adaptive_difficulty,
+        ),
+        ("a seeded Easy AI repeats its moves", check_seeded_random_ai),
+        (
+            "one seed replays games at every level",
+            check_seeded_session,
+        ),
+        ("--marks refuses digits and repeats", check_parse_marks),
+        (
+            "colour themes keep marks, line and grid apart",
+            check_theme_roles,
+        ),
+        ("boards draw the same as before", check_render_board),
+        ("move numbers keep both digits", check_move_numbers),
+        ("--grid box swaps every grid character", check_grid_styles),
+        (
+            "--big-board draws and clicks the big cells",
+            check_big_board,
+        ),
+        ("long move lists keep to the screen", check_move_list_fits),
+        (
+            "--start positions parse and bad ones are refused",
+            check_parse_start,
+        ),
+        (
+            "saved games parse and bad moves are refused",
+            check_parse_game_record,
+        ),
+        ("--json results are well formed", check_json_result),
+        (
+            "session stats survive an empty session",
+            check_session_stats,
+        ),
+        (
+            "minimax never loses from the empty board",
+            check_minimax_never_loses,
+        ),
+        ("minimax takes an immediate win", check_minimax_takes_win),
+        ("minimax memo matches the plain search", check_minimax_memo),
+        (
+            "alpha-beta only plays the plain search's best moves",
+            check_alpha_beta,
+        ),
+        (
+            "impossible AI varies between equal moves",
+            check_minimax_varies,
+        ),
+        (
+            "impossible against itself draws",
+            check_impossible_self_play,
+        ),
+        (
+            "hard AI wins with its own mark when it goes first",
+            check_hard_ai_computer_first,
+        ),
+        (
+            "medium AI opens centre and heads off forks",
+            check_medium_ai,
+        ),
+        (
+            "hard AI forks and survives the corner trap",
+            check_hard_ai_forks,
+        ),
+        ("opening book replies", check_opening_book),
+        (
+            "undo restores the board and the turn",
+            check_undo_restores_turn,
+        ),
+        (
+            "undo against the AI goes back to before the human's move",
+            check_undo_against_ai,
+        ),
+        ("saved scores read back", check_scores_round_trip),
+        ("game records list every move", check_game_record_format),
+        ("clicks map to the cell under them", check_cell_at),
+        (
+            "series end on a majority or after every game",
+            check_series_clinch,
+        ),
+    ];
+
+    let mut all_passed = true;
+    for &(name, check) in checks {
+        match check() {
+            Ok(()) => println!("ok    {}", name),
+            Err(msg) => {
+                all_passed = false;
+                println!("FAIL  {}: {}", name, msg);
+            }
+        }
+    }
+    all_passed
+}
+
+fn board_from(cells: &str) -> Vec<char> {
+    let mut board = empty_board(cells.chars().count().isqrt());
+    for (idx, c) in cells.chars().enumerate() {
+        if c == PLAYER_X || c == PLAYER_O {
+            board[idx] = c;
+        }
+    }
+    board
+}
+
+// A position reached by random play that isn't finished yet.
+fn random_open_board(lines: &[Vec<usize>]) -> Option<Vec<char>> {
+    let mut board = empty_board(3);
+    let mut current = PLAYER_X;
+    for _ in 0..rand::random_range(0..9) {
+        let idx = get_ai_move_random(&board, &mut rand::rng());
+        board[idx] = current;
+        if check_winner(&board, lines).is_some() || is_draw(&board) {
+            return None;
+        }
+        current = switch_player(current);
+    }
+    Some(board)
+}
+
+fn check_ai_moves_legal() -> CheckResult {
+    for variant in [Variant::Standard, Variant::Torus] {
+        let lines = win_lines(3, 3, variant);
+        for _ in 0..2000 {
+            let Some(board) = random_open_board(&lines) else {
+                continue;
+            };
+            let to_move = player_to_move(&board);
+            for (name, pos) in [
+                ("easy", get_ai_move_random(&board, &mut rand::rng())),
+                (
+                    "medium",
+                    get_ai_move_heuristic(
+                        &board,
+                        &lines,
+                        to_move,
+                        switch_player(to_move),
+                        &mut rand::rng(),
+                    )
+                    .0,
+                ),
+                (
+                    "hard",
+                    get_ai_move_blocking(
+                        &board,
+                        &lines,
+                        to_move,
+                        switch_player(to_move),
+                        &mut rand::rng(),
+                    )
+                    .0,
+                ),
+            ] {
+                if pos >= board.len() || board[pos] != EMPTY {
+                    return Err(format!(
+                        "{} AI picked cell {} on {:?}",
+                        name,
+                        pos + 1,
+                        board
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_minimax_never_loses() -> CheckResult {
+    // Every reply the human could make, with the computer answering by minimax.
+    fn explore(
+        board: &mut [char],
+        lines: &[Vec<usize>],
+        to_move: char,
+        computer: char,
+    ) -> CheckResult {
+        if let Some((winner, _)) = check_winner(board, lines) {
+            return if winner == computer {
+                Ok(())
+            } else {
+                Err(format!("computer {} lost on {:?}", computer, board))
+            };
+        }
+        if is_draw(board) {
+            return Ok(());
+        }
+        if to_move == computer {
+            let (idx, _) = get_ai_move_minimax(board, lines, computer, &mut rand::rng());
+            if board[idx] != EMPTY {
+                return Err(format!(
+                    "computer picked taken cell {} on {:?}",
+                    idx + 1,
+                    board
+                ));
+            }
+            board[idx] = computer;
+            let result = explore(board, lines, switch_player(to_move), computer);
+            board[idx] = EMPTY;
+            return result;
+        }
+        for idx in 0..board.len() {
+            if board[idx] != EMPTY {
+                continue;
+            }
+            board[idx] = to_move;
+            let result = explore(board, lines, switch_player(to_move), computer);
+            board[idx] = EMPTY;
+            result?;
+        }
+        Ok(())
+    }
+
+    let lines = win_lines(3, 3, Variant::Standard);
+    for computer in [PLAYER_X, PLAYER_O] {
+        explore(&mut empty_board(3), &lines, PLAYER_X, computer)?;
+    }
+    Ok(())
+}
+
+fn check_seeded_session() -> CheckResult {
+    // Three games in a row from one generator, each level against itself, as
+    // the cells played; Medium and Impossible break their ties at random.
+    let session = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let lines = win_lines(3, 3, Variant::Standard);
+        let mut games = Vec::new();
+        for difficulty in [Difficulty::Medium, Difficulty::Impossible, Difficulty::Easy] {
+            let mut state = GameState::new(3, 3, Variant::Standard);
+            let mut moves = Vec::new();
+            while !state.result().is_over() {
+                let mark = state.current_player;
+                let idx = pick_ai_move(&state.board, &lines, difficulty, mark, &mut rng).pos;
+                state.apply_move(idx).map_err(|err| err.to_string())?;
+                moves.push(idx);
+            }
+            games.push(moves);
+        }
+        Ok::<_, String>(games)
+    };
+    let first = session(2024)?;
+    if session(2024)? != first {
+        return Err("the same seed played a different session".to_string());
+    }
+    if (0..20).all(|seed| session(seed).is_ok_and(|games| games == first)) {
+        return Err("every seed played the same session".to_string());
+    }
+    Ok(())
+}
+
+fn check_seeded_random_ai() -> CheckResult {
+    // A whole Easy-against-Easy game from one seed, as the cells played.
+    let play = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let lines = win_lines(3, 3, Variant::Standard);
+        let mut board = empty_board(3);
+        let mut current = PLAYER_X;
+        let mut moves = Vec::new();
+        while !game_result(&board, &lines).is_over() {
+            let idx = get_ai_move_random(&board, &mut rng);
+            if board[idx] != EMPTY {
+                return Err(format!("picked the taken cell {}", idx + 1));
+            }
+            board[idx] = current;
+            moves.push(idx);
+            current = switch_player(current);
+        }
+        Ok(moves)
+    };
+    let first = play(7)?;
+    if play(7)? != first {
+        return Err("the same seed played a different game".to_string());
+    }
+    // Different seeds still spread the opening over the whole board.
+    let openings: HashSet<usize> = (0..200)
+        .map(|seed| play(seed).map(|moves| moves[0]))
+        .collect::<std::result::Result<_, _>>()?;
+    if openings.len() != 9 {
+        return Err(format!("seeds only opened on {} cells", openings.len()));
+    }
+    Ok(())
+}
+
+fn check_adaptive_difficulty() -> CheckResult {
+    let adaptive = GameMode::AI(Difficulty::Adaptive, true, 0);
+    // The human plays X: a game's result, then the level the next game gets.
+    let (win, loss) = (Some(PLAYER_X), Some(PLAYER_O));
+    let on_3x3 = [
+        (win, Difficulty::Medium),
+        (win, Difficulty::Hard),
+        (win, Difficulty::Impossible),
+        (win, Difficulty::Impossible),
+        (loss, Difficulty::Hard),
+        (None, Difficulty::Hard),
+        (loss, Difficulty::Medium),
+        (loss, Difficulty::Easy),
+        (loss, Difficulty::Easy),
+        (win, Difficulty::Medium),
+    ];
+    // Too big for Impossible, so Hard is the top.
+    let on_5x5 = [
+        (win, Difficulty::Medium),
+        (win, Difficulty::Hard),
+        (win, Difficulty::Hard),
+        (loss, Difficulty::Medium),
+    ];
+    for (size, run) in [(3, &on_3x3[..]), (5, &on_5x5[..])] {
+        let mut record = (0, 0);
+        if let GameMode::AI(level, ..) = resolve_adaptive(adaptive, record, size)
+            && level != Difficulty::Easy
+        {
+            return Err(format!("the first adaptive game is {}", level));
+        }
+        for (game, &(winner, expected)) in run.iter().enumerate() {
+            record_adaptive(&mut record, adaptive, winner, size);
+            match resolve_adaptive(adaptive, record, size) {
+                GameMode::AI(level, ..) if level == expected => {}
+                GameMode::AI(level, ..) => {
+                    return Err(format!(
+                        "{}×{} game {}: {} after {:?}, wanted {}",
+                        size,
+                        size,
+                        game + 2,
+                        level,
+                        record,
+                        expected
+                    ));
+                }
+                _ => return Err("adaptive isn't an AI game any more".to_string()),
+            }
+        }
+    }
+    // Fixed levels are left alone and don't count.
+    let mut record = (0, 0);
+    let hard = GameMode::AI(Difficulty::Hard, true, 0);
+    record_adaptive(&mut record, hard, win, 3);
+    if record != (0, 0)
+        || !matches!(
+            resolve_adaptive(hard, (3, 0), 3),
+            GameMode::AI(Difficulty::Hard, ..)
+        )
+    {
+        return Err("a fixed level moved with the results".to_string());
+    }
+    Ok(())
+}
+
+fn check_key_repeats_ignored() -> CheckResult {
+    let key = |code, modifiers, kind| Event::Key(KeyEvent::new_with_kind(code, modifiers, kind));
+    let five = KeyCode::Char('5');
+    for kind in [KeyEventKind::Repeat, KeyEventKind::Release] {
+        let input =
+            classify_event(key(five, KeyModifiers::NONE, kind)).map_err(|err| err.to_string())?;
+        if !matches!(input, Input::Ignored) {
+            return Err(format!("a {:?} of '5' was read as input", kind));
+        }
+        // Holding Ctrl+C down quits once, on the press, not again on each repeat.
+        if classify_event(key(KeyCode::Char('c'), KeyModifiers::CONTROL, kind)).is_err() {
+            return Err(format!("a {:?} of Ctrl+C quit", kind));
+        }
+    }
+    match classify_event(key(five, KeyModifiers::NONE, KeyEventKind::Press)) {
+        Ok(Input::Key(event)) if event.code == five => {}
+        _ => return Err("a press of '5' was not read".to_string()),
+    }
+    for (code, modifiers) in [
+        ('c', KeyModifiers::CONTROL),
+        ('C', KeyModifiers::CONTROL | KeyModifiers::SHIFT),
+        ('q', KeyModifiers::CONTROL),
+    ] {
+        match classify_event(key(KeyCode::Char(code), modifiers, KeyEventKind::Press)) {
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            _ => return Err(format!("a press of Ctrl+{} did not quit", code)),
+        }
+    }
+    Ok(())
+}
+
+fn check_fastest_win() -> CheckResult {
+    let win = |millis, moves| FastestWin { millis, moves };
+    let mut best = None;
+    let steps = [
+        (win(5100, 7), Some("First win on record: 5.1s.")),
+        (win(6000, 5), None),
+        (win(4250, 5), Some("New record! 4.2s, was 5.1s.")),
+    ];
+    for (game, expected) in steps {
+        let note = beat_fastest_win(&mut best, game);
+        if note.as_deref() != expected {
+            return Err(format!(
+                "{:?} after {:?}, wanted {:?}",
+                note, game, expected
+            ));
+        }
+    }
+    let saved = format_fastest_win(win(4250, 5));
+    if parse_fastest_win(&saved) != best {
+        return Err(format!("{:?} did not read back as the record", saved));
+    }
+    if parse_fastest_win("{\"millis\": 12}").is_some() {
+        return Err("a record without its move count was read".to_string());
+    }
+    Ok(())
+}
+
+fn check_config_file() -> CheckResult {
+    let text = "# defaults\nmode = \"ai\"\ndifficulty = \"Hard\"  # level\n\nfirst = \"computer\"\ntheme = \"classic\"\nsize = 4\ndim-numbers = true\n";
+    let config = parse_config(text)?;
+    if config.mode != Some(ModeFlag::Ai)
+        || config.difficulty != Some(Difficulty::Hard)
+        || config.player_first != Some(false)
+        || config.size != Some(4)
+        || !config.dim_numbers
+        || config.theme.is_none_or(|theme| theme.x != Theme::CLASSIC.x)
+    {
+        return Err("the settings were not all read".to_string());
+    }
+    for bad in ["size = 12", "colour = \"red\"", "mode = ai", "[game]"] {
+        if parse_config(bad).is_ok() {
+            return Err(format!("{:?} was accepted", bad));
+        }
+    }
+    Ok(())
+}
+
+fn check_csv_export() -> CheckResult {
+    let record = |mode, winner| GameRecord {
+        mode,
+        winner,
+        moves: 6,
+    };
+    let rows = [
+        (
+            record(GameMode::AI(Difficulty::Hard, true, 0), Some(PLAYER_O)),
+            ",ai,hard,O,6",
+        ),
+        (record(GameMode::Friend, None), ",friend,-,draw,6"),
+    ];
+    for (record, ending) in &rows {
+        let row = format_csv_row(record);
+        let (timestamp, rest) = row.split_once(',').unwrap_or_default();
+        if timestamp.parse::<u64>().is_err() || format!(",{}", rest) != *ending {
+            return Err(format!("wrote the row {:?}", row));
+        }
+    }
+    // The header goes in once, when the file is started.
+    let path = std::env::temp_dir().join(format!("tictactoe-check-{}.csv", std::process::id()));
+    let path_text = path.to_string_lossy().into_owned();
+    let _ = fs::remove_file(&path);
+    let written = rows
+        .iter()
+        .try_for_each(|(record, _)| write_csv_row(&path_text, record))
+        .and_then(|()| fs::read_to_string(&path));
+    let _ = fs::remove_file(&path);
+    let text = written.map_err(|err| err.to_string())?;
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() != 3 || lines[0] != CSV_HEADER || lines[1..].contains(&CSV_HEADER) {
+        return Err(format!("wrote the file {:?}", text));
+    }
+    let config = parse_config("csv = \"/tmp/results.csv\"")?;
+    if config.csv_path.as_deref() != Some("/tmp/results.csv") || parse_config("csv = \"\"").is_ok()
+    {
+        return Err("the config file's csv path was misread".to_string());
+    }
+    Ok(())
+}
+
+fn check_depth_search() -> CheckResult {
+    let lines = win_lines(3, 3, Variant::Standard);
+    let play = |cells: &str, depth| {
+        let board = board_from(cells);
+        let mark = player_to_move(&board);
+        get_ai_move_minimax_depth(&board, &lines, mark, depth, &mut rand::rng()).0
+    };
+    // (position, depth, the moves that depth may pick)
+    let cases = [
+        // Depth 1 is greedy: its own line first, even with X's to block.
+        ("XX.OO.X..", 1, vec![5]),
+        // ...so against opposite corners it takes a corner and walks into the
+        // fork, where two moves ahead sees the reply and plays an edge.
+        ("X...O...X", 1, vec![2, 6]),
+        ("X...O...X", 2, vec![1, 3, 5, 7]),
+        // Only cell 3 holds the draw here, and it takes four moves to see why.
+        ("...X...XO", 2, vec![4, 5]),
+        ("...X...XO", 4, vec![2]),
+    ];
+    for (cells, depth, wanted) in cases {
+        for _ in 0..10 {
+            let idx = play(cells, depth);
+            if !wanted.contains(&idx) {
+                return Err(format!("depth {} played {} on {}", depth, idx + 1, cells));
+            }
+        }
+    }
+    if parse_difficulty("Depth-3") != Some(Difficulty::Depth(3))
+        || parse_difficulty("depth-0").is_some()
+    {
+        return Err("depth levels were misread".to_string());
+    }
+    Ok(())
+}
+
+fn check_move_accuracy() -> CheckResult {
+    let lines = win_lines(3, 3, Variant::Standard);
+    // O's edge reply to the centre loses; after that nothing O does matters,
+    // and X finishes with a fork.
+    let history = [
+        (PLAYER_X, 4),
+        (PLAYER_O, 1),
+        (PLAYER_X, 0),
+        (PLAYER_O, 8),
+        (PLAYER_X, 6),
+        (PLAYER_O, 3),
+        (PLAYER_X, 2),
+    ];
+    let mut board = empty_board(3);
+    for &(mark, idx) in &history {
+        board[idx] = mark;
+    }
+    match move_accuracy(&board, &history, &lines) {
+        [(4, 4), (2, 3)] => Ok(()),
+        counts => Err(format!("counted {:?}, wanted X 4/4 and O 2/3", counts)),
+    }
+}
+
+fn check_cursor_wraps() -> CheckResult {
+    // (from, direction, wrapped, at a hard edge) on 3×3
+    let cases = [
+        (1, (-1, 0), 7, 1), // up off the top row
+        (7, (1, 0), 1, 7),  // down off the bottom
+        (3, (0, -1), 5, 3), // left off the first column
+        (5, (0, 1), 3, 5),  // right off the last
+        (4, (0, 1), 5, 5),  // inside the board both agree
+    ];
+    for (from, direction, wrapped, edge) in cases {
+        let got = (
+            move_cursor(from, 3, direction, true),
+            move_cursor(from, 3, direction, false),
+        );
+        if got != (wrapped, edge) {
+            return Err(format!(
+                "{:?} from {} went to {:?}, wanted {:?}",
+                direction,
+                from + 1,
+                (got.0 + 1, got.1 + 1),
+                (wrapped + 1, edge + 1)
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_win_scores() -> CheckResult {
+    let lines = win_lines(3, 3, Variant::Standard);
+    let won = board_from("XXXOO....");
+    let (in_two, in_four) = (
+        evaluate(&won, &lines, 2, PLAYER_X),
+        evaluate(&won, &lines, 4, PLAYER_X),
+    );
+    if in_two <= in_four || in_four <= 0 {
+        return Err(format!("a win in 2 scored {}, in 4 {}", in_two, in_four));
+    }
+    if evaluate(&won, &lines, 2, PLAYER_O) >= evaluate(&won, &lines, 4, PLAYER_O) {
+        return Err("a loss in 2 scored no worse than one in 4".to_string());
+    }
+    // X wins on 2 at once, or on its next move after 4 or 7 (a fork); the
+    // search has to see all three win and still take the quickest.
+    let board = board_from("X.X.O...O");
+    let mut search = Search::new(3, &lines, PLAYER_X, false, false);
+    let scores = search.scores(&board);
+    let score = |cell: usize| {
+        scores
+            .iter()
+            .find(|&&(idx, _)| idx == cell)
+            .map(|&(_, s)| s)
+    };
+    let (now, later) = (score(1), score(3).max(score(6)));
+    if !matches!((now, later), (Some(now), Some(later)) if now > later && later > 0) {
+        return Err(format!(
+            "scores for the quick and slow wins were {:?}",
+            (now, later)
+        ));
+    }
+    let mut rng = StdRng::seed_from_u64(0);
+    match Search::new(3, &lines, PLAYER_X, true, true).best_move(&board, &mut rng) {
+        1 => Ok(()),
+        other => Err(format!("took the slower win on {}", other + 1)),
+    }
+}
+
+// `run` from the first move to leaving after the result, drawn into a buffer:
+// X takes the top row, then 'n' quits since --no-confirm skips asking again.
+fn check_scripted_game() -> CheckResult {
+    let options = Options {
+        mode: Some(ModeFlag::Friend),
+        quit_at_once: true,
+        self_test: true,
+        think_ms: Some(0),
+        ..Options::default()
+    };
+    let mut scores = Default::default();
+    let mut output = Vec::new();
+    let mut keys = ScriptedInput::from_keys("14253n");
+    let mut rng = StdRng::seed_from_u64(0);
+    run(&mut output, &options, &mut scores, &mut rng, &mut keys).map_err(|e| e.to_string())?;
+    let (x, o, draws) = scores;
+    if (x.0, o.0, draws.0) != (1, 0, 0) || !keys.events.is_empty() {
+        return Err(format!("the game ended {}-{}-{}", x, o, draws));
+    }
+    let screen = String::from_utf8_lossy(&output);
+    if !screen.contains(&fill(text().wins, &[&"Player X"])) {
+        return Err("no winner was shown".to_string());
+    }
+    Ok(())
+}
+
+// Only keys that answer the turn straight away, since a refused move or a
+// cursor step would draw over the self-test's report.
+fn check_scripted_input() -> CheckResult {
+    let play = |keys: &mut ScriptedInput, board: &str| {
+        let state = GameState::from_board(board_from(board), 3, Variant::Standard)
+            .map_err(|err| err.to_string())?;
+        let mut cursor = 0;
+        get_human_move(
+            &mut stdout(),
+            keys,
+            &Options::default(),
+            &state,
+            &mut cursor,
+            &mut Timer::default(),
+            &mut StdRng::seed_from_u64(0),
+        )
+        .map_err(|err| err.to_string())
+    };
+    let empty = ".".repeat(16);
+    let cases = [
+        ("7", "X........", Some(6)),
+        ("12", empty.as_str(), Some(11)),
+        ("1\n", empty.as_str(), Some(0)),
+        ("u", "X........", None),
+    ];
+    for (keys, board, expected) in cases {
+        let turn = play(&mut ScriptedInput::from_keys(keys), board)?;
+        let placed = match turn {
+            Turn::Place(idx) => Some(idx),
+            Turn::Undo => None,
+            _ => return Err(format!("{:?} gave another kind of turn", keys)),
+        };
+        if placed != expected {
+            return Err(format!("{:?} placed {:?}", keys, placed));
+        }
+    }
+    match play(&mut ScriptedInput::new([]), ".........") {
+        Err(message) if message.contains("ran out") => Ok(()),
+        _ => Err("an empty script didn't end the turn with an error".to_string()),
+    }
+}
+
+fn check_replay_keys() -> CheckResult {
+    let options = Options {
+        quit_at_once: true,
+        ..Options::default()
+    };
+    let mut output = Vec::new();
+    let mut keys = ScriptedInput::from_keys("zn");
+    let post_game = ask_replay(
+        &mut output,
+        &mut keys,
+        current_layout(3),
+        &options,
+        &board_from("XXXOO...."),
+        &mut None,
+        GameMode::AI(Difficulty::Easy, true, 0),
+    )
+    .map_err(|err| err.to_string())?;
+    if !matches!(post_game, PostGame::Quit) {
+        return Err("n didn't leave".to_string());
+    }
+    let screen = String::from_utf8_lossy(&output);
+    if !screen.contains(&invalid_input(&["y", "n", "s", "c", "r"])) {
+        return Err(format!("z was answered with {:?}", screen));
+    }
+    Ok(())
+}
+
+fn check_replay_quit() -> CheckResult {
+    let options = Options {
+        keymap: Keymap {
+            quit: 'x',
+            ..Keymap::default()
+        },
+        ..Options::default()
+    };
+    let board = board_from("XXXOO....");
+    let history = [
+        (PLAYER_X, 0),
+        (PLAYER_O, 3),
+        (PLAYER_X, 1),
+        (PLAYER_O, 4),
+        (PLAYER_X, 2),
+    ];
+    let lines = win_lines(3, 3, Variant::Standard);
+    let replay = |keys: &str, output: &mut Vec<u8>| {
+        let mut keys = ScriptedInput::from_keys(keys);
+        watch_replay(output, &mut keys, &options, &board, &history, &lines, 0)
+    };
+    let mut output = Vec::new();
+    replay("x", &mut output).map_err(|_| "x didn't end the replay".to_string())?;
+    if !String::from_utf8_lossy(&output).contains(&fill(text().replay_controls, &[&'x'])) {
+        return Err("the controls don't name the quit key".to_string());
+    }
+    if replay("q", &mut Vec::new()).is_ok() {
+        return Err("q ended a replay with x as the quit key".to_string());
+    }
+    Ok(())
+}
+
+fn check_game_clocks() -> CheckResult {
+    // X is the human with a second left; O is the computer.
+    let mut timer = Timer {
+        clocks: Some([Some(Duration::from_secs(1)), None]),
+        ..Timer::default()
+    };
+    let mut since = Instant::now() - Duration::from_millis(400);
+    if timer.run_down(PLAYER_X, &mut since)
+        || timer
+            .clock(PLAYER_X)
+            .is_none_or(|left| left > Duration::from_millis(600))
+    {
+        return Err(format!("X's clock read {:?}", timer.clock(PLAYER_X)));
+    }
+    let mut since = Instant::now() - Duration::from_secs(60);
+    if timer.run_down(PLAYER_O, &mut since) || timer.clock(PLAYER_O).is_some() {
+        return Err("the computer's clock ran".to_string());
+    }
+    // The move limit ends the poll first when it's the sooner of the two.
+    timer.deadline = Some(Instant::now() + Duration::from_millis(100));
+    if timer
+        .time_left(PLAYER_X)
+        .is_none_or(|left| left > Duration::from_millis(100))
+    {
+        return Err(format!("time left read {:?}", timer.time_left(PLAYER_X)));
+    }
+    let mut since = Instant::now() - Duration::from_secs(1);
+    if !timer.run_down(PLAYER_X, &mut since) || timer.clock(PLAYER_X) != Some(Duration::ZERO) {
+        return Err("X's flag didn't fall".to_string());
+    }
+    Ok(())
+}
+
+fn check_refusal_feedback() -> CheckResult {
+    let state = GameState::from_board(board_from("X........"), 3, Variant::Standard)
+        .map_err(|err| err.to_string())?;
+    let signal = |feedback, tried| {
+        let options = Options {
+            feedback,
+            theme: Theme::CLASSIC,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        signal_refusal(&mut output, &state, tried, 4, &options).map_err(|e| e.to_string())?;
+        Ok::<_, String>(String::from_utf8_lossy(&output).into_owned())
+    };
+    if signal(Feedback::Beep, Some(0))? != BELL.to_string() {
+        return Err("beep didn't just ring the bell".to_string());
+    }
+    if !signal(Feedback::Silent, Some(0))?.is_empty() {
+        return Err("silent printed something".to_string());
+    }
+    // The taken cell is reversed once, then drawn plainly again.
+    let flash = signal(Feedback::Flash, Some(0))?;
+    if flash.contains(BELL) || flash.matches("\x1b[7m").count() != 1 {
+        return Err(format!("flashing cell 1 drew {:?}", flash));
+    }
+    // A key naming no cell flashes the grid lines instead.
+    let flash = signal(Feedback::Flash, None)?;
+    if flash.matches("\x1b[38;5;9m").count() != 1 || flash.contains("\x1b[7m") {
+        return Err(format!("flashing the grid drew {:?}", flash));
+    }
+    Ok(())
+}
+
+fn check_help_screen() -> CheckResult {
+    let mut options = Options::default();
+    let lines = help_screen(&options).lines;
+    if !lines.iter().any(|line| line.contains("1 to 9 row by row"))
+        || lines.iter().any(|line| line.contains("second digit"))
+    {
+        return Err(format!("3×3 help read {:?}", lines));
+    }
+    options.size = Some(4);
+    if !help_screen(&options)
+        .lines
+        .iter()
+        .any(|line| line.contains("second digit"))
+    {
+        return Err("4×4 help left out two-digit cells".to_string());
+    }
+    options.gravity = Some((7, 6));
+    if !help_screen(&options)
+        .lines
+        .iter()
+        .any(|line| line.contains("Columns are numbered 1 to 7"))
+    {
+        return Err("gravity help didn't number the columns".to_string());
+    }
+    if bind_key(Keymap::default(), "hint=?").is_some() {
+        return Err("'?' was bound away from the help screen".to_string());
+    }
+    Ok(())
+}
+
+fn check_bind_key() -> CheckResult {
+    let keymap = bind_key(Keymap::default(), "undo=z").ok_or("undo=z was refused")?;
+    if keymap.undo != 'z' {
+        return Err("undo=z didn't rebind undo".to_string());
+    }
+    // Digits pick cells, 'r' is taken by resign during a game and 'n' is the
+    // play-again prompt's.
+    for binding in [
+        "undo", "undo=", "undo=zz", "fly=z", "undo=5", "undo=r", "copy=n",
+    ] {
+        if bind_key(Keymap::default(), binding).is_some() {
+            return Err(format!("{:?} was accepted", binding));
+        }
+    }
+    // Replay and resign share 'r' by default, but are never read together.
+    let wasd = Keymap::default()
+        .with_movement("wasd")
+        .ok_or("no wasd layout")?;
+    if bind_key(wasd, "hint=w").is_some() || bind_key(wasd, "hint=j").is_none() {
+        return Err("hint wasn't checked against the wasd keys".to_string());
+    }
+    Ok(())
+}
+
+fn check_safe_random() -> CheckResult {
+    let lines = win_lines(3, 3, Variant::Standard);
+    for seed in 0..50 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // O must block X's top row; any other cell loses at once.
+        let (pos, reason) =
+            get_ai_move_safe_random(&board_from("XX.O....."), &lines, PLAYER_O, &mut rng);
+        if pos != 2 || reason != MoveReason::Safe {
+            return Err(format!("left X's row open by playing {}", pos + 1));
+        }
+        // Winning on 6 is as safe as blocking on 3.
+        let (pos, _) =
+            get_ai_move_safe_random(&board_from("XX.OO.X.."), &lines, PLAYER_O, &mut rng);
+        if ![2, 5].contains(&pos) {
+            return Err(format!("played {} with a win and a block free", pos + 1));
+        }
+    }
+    // Two open lines for X: nothing is safe, so any free cell goes.
+    let board = board_from("XX.X.O.O.");
+    let mut rng = StdRng::seed_from_u64(1);
+    let (pos, reason) = get_ai_move_safe_random(&board, &lines, PLAYER_O, &mut rng);
+    if board[pos] != EMPTY || reason != MoveReason::Random {
+        return Err(format!(
+            "with no safe cell it played {} ({})",
+            pos + 1,
+            text().reasons[reason as usize]
+        ));
+    }
+    Ok(())
+}
+
+fn check_translations() -> CheckResult {
+    let blanks = |strings: &Strings| -> Vec<usize> {
+        let mut templates = vec![
+            strings.win_rule,
+            strings.seed,
+            strings.difficulty_of,
+            strings.depth_prompt,
+            strings.invalid,
+            strings.player,
+            strings.friend_turn,
+            strings.your_turn,
+            strings.computer_turn,
+            strings.ai_turn,
+            strings.wins,
+            strings.play_again,
+            strings.copy,
+            strings.replay,
+            strings.goes_first,
+            strings.decided,
+            strings.choose_column,
+            strings.choose_board,
+            strings.choose_cell,
+            strings.board_decided,
+            strings.cell_taken,
+            strings.lost_on_time,
+            strings.really_resign,
+            strings.resigned,
+            strings.offers_draw,
+            strings.series_winner,
+            strings.series_score,
+            strings.first_record,
+            strings.new_record,
+            strings.adaptive_note,
+            strings.record_not_saved,
+            strings.logging_off,
+            strings.game_record_off,
+            strings.csv_off,
+            strings.computer_plays,
+            strings.not_a_cell,
+            strings.move_refused,
+            strings.retry,
+            strings.off_board,
+            strings.taken,
+            strings.game_over,
+            strings.wrong_board,
+            strings.editor_hint,
+            strings.cant_start,
+            strings.bad_counts,
+            strings.already_won,
+            strings.board_full,
+            strings.replay_step,
+            strings.replay_start,
+            strings.replay_controls,
+            strings.big_board_window,
+            strings.big_board_anyway,
+            strings.boards_heading,
+            strings.panel_controls,
+            strings.moves_heading,
+            strings.moves_earlier,
+            strings.nodes,
+            strings.explain,
+            strings.cell_at,
+            strings.placed_at,
+            strings.empty_cell,
+            strings.board_row,
+            strings.seconds_left,
+            strings.accuracy_line,
+            strings.key_moment,
+            strings.no_mistakes,
+            strings.series_running,
+            strings.drew,
+            strings.beat,
+            strings.standings_key,
+            strings.tournament_one,
+            strings.tournament_title,
+            strings.champion,
+            strings.new_tournament,
+            strings.next_match,
+            strings.next_match_key,
+            strings.games_played,
+            strings.beat_level,
+            strings.win_rates,
+            strings.streak,
+            strings.no_streak,
+            strings.longest_streak,
+        ];
+        templates.extend(strings.control_lines);
+        templates.extend(strings.level_names);
+        templates
+            .iter()
+            .map(|template| template.matches("{}").count())
+            .collect()
+    };
+    if blanks(&ENGLISH) != blanks(&SPANISH) {
+        return Err("the Spanish text has blanks the English doesn't".to_string());
+    }
+    let hint = fill(SPANISH.friend_turn, &[&"Alice (X)", &9]);
+    if hint != "Alice (X), elige casilla (1-9): " {
+        return Err(format!("the turn hint was filled as {:?}", hint));
+    }
+    for (line, room, expected) in [
+        ("¡Empate!", 8, "¡Empate!"),
+        ("¡Empate!", 5, "¡Emp…"),
+        ("¡Empate!", 0, "¡Empate!"),
+    ] {
+        if truncate(line, room) != expected {
+            return Err(format!(
+                "{:?} in {} columns became {:?}",
+                line,
+                room,
+                truncate(line, room)
+            ));
+        }
+    }
+    let config = parse_config("lang = \"es\"")?;
+    if config.language != Some(Language::Spanish) || parse_config("lang = \"fr\"").is_ok() {
+        return Err("the config file's lang was misread".to_string());
+    }
+    Ok(())
+}
+
+fn check_player_names() -> CheckResult {
+    let names = parse_names(" Alice ,Bob").ok_or("two names were refused")?;
+    for bad in [
+        "Alice",
+        "Alice,Alice",
+        "Alice,",
+        "Alice,Bob,Carol",
+        "Alice,Bartholomew",
+    ] {
+        if parse_names(bad).is_some() {
+            return Err(format!("{:?} were accepted as names", bad));
+        }
+    }
+    let theme = Theme::default();
+    let [x, o] = seat_players(Some((&names[0], &names[1])), &theme);
+    let named = (x.label(), o.tag());
+    if named != ("Alice (X)".to_string(), "Bob(O)".to_string()) {
+        return Err(format!("named players showed as {:?}", named));
+    }
+    let [x, o] = seat_players(None, &theme);
+    let plain = (x.label(), o.tag());
+    if plain != ("Player X".to_string(), "O".to_string()) {
+        return Err(format!("unnamed players showed as {:?}", plain));
+    }
+    Ok(())
+}
+
+fn check_tournament_rotation() -> CheckResult {
+    let players = parse_players("Alice, Bob ,Carol").ok_or("three names were refused")?;
+    for bad in ["Alice,Bob", "Alice,Bob,Alice", "Alice,,Bob"] {
+        if parse_players(bad).is_some() {
+            return Err(format!("{:?} were accepted as players", bad));
+        }
+    }
+    let mut tournament = Tournament::new(players, 2);
+    // Bob (X) beats Alice, Carol (X) and Bob draw, then Alice (X) beats Bob.
+    let games = [
+        (Some(PLAYER_X), ("Bob", "Alice"), "Bob beat Alice."),
+        (None, ("Carol", "Bob"), "Carol and Bob drew."),
+        (Some(PLAYER_X), ("Alice", "Bob"), "Alice beat Bob."),
+        (Some(PLAYER_O), ("Carol", "Alice"), "Alice beat Carol."),
+    ];
+    for (winner, pairing, summary) in games {
+        if tournament.names() != pairing {
+            return Err(format!(
+                "{:?} played, wanted {:?}",
+                tournament.names(),
+                pairing
+            ));
+        }
+        let got = tournament.record(winner);
+        if got != summary {
+            return Err(format!("{:?}, wanted {:?}", got, summary));
+        }
+    }
+    match tournament.champion() {
+        Some("Alice") => Ok(()),
+        other => Err(format!("champion {:?}, wanted Alice", other)),
+    }
+}
+
+fn check_json_result() -> CheckResult {
+    let history = [(PLAYER_X, 4), (PLAYER_O, 0), (PLAYER_X, 8)];
+    let mode = GameMode::AI(Difficulty::Hard, true, 0);
+    let json = format_json_result(mode, &history, Some(PLAYER_X));
+    if json != r#"{"winner":"X","moves":[4,0,8],"mode":"ai-hard"}"# {
+        return Err(format!("unexpected {}", json));
+    }
+    let json = format_json_result(GameMode::Friend, &[], None);
+    if json != r#"{"winner":null,"moves":[],"mode":"friend"}"# {
+        return Err(format!("unexpected {}", json));
+    }
+    Ok(())
+}
+
+fn check_session_stats() -> CheckResult {
+    let stats = session_stats(&[]);
+    if win_rate(stats.x_wins, stats.games) != 0 || stats.streak.is_some() {
+        return Err("an empty session has results".to_string());
+    }
+    let game = |winner, moves| GameRecord {
+        mode: GameMode::Friend,
+        winner,
+        moves,
+    };
+    let records = [
+        game(Some(PLAYER_X), 5),
+        game(Some(PLAYER_O), 6),
+        game(Some(PLAYER_O), 8),
+        game(None, 9),
+        game(Some(PLAYER_X), 7),
+        game(Some(PLAYER_X), 5),
+        game(Some(PLAYER_X), 5),
+    ];
+    let stats = session_stats(&records);
+    if stats.streak != Some((PLAYER_X, 3)) {
+        return Err(format!("streak {:?}", stats.streak));
+    }
+    if (stats.games, stats.moves) != (7, 45) {
+        return Err(format!("{} games, {} moves", stats.games, stats.moves));
+    }
+    let rates = (win_rate(stats.x_wins, 7), win_rate(stats.o_wins, 7));
+    if rates != (57, 29) {
+        return Err(format!("win rates {:?}", rates));
+    }
+    Ok(())
+}
+
+fn check_parse_game_record() -> CheckResult {
+    let history = [(PLAYER_X, 4), (PLAYER_O, 0), (PLAYER_X, 8)];
+    let line = format_game_record(&board_from("O...X...X"), &history, None);
+    let mut state = GameState::new(3, 3, Variant::Standard);
+    if parse_game_record(&line, &mut state)? != history {
+        return Err(format!("{:?} didn't read back", line));
+    }
+    // O moving first after a coin flip, then X twice thanks to a pass, on a
+    // 4×4 game that began from a set-up position.
+    let history = [(PLAYER_O, 5), (PLAYER_X, 10), (PLAYER_X, 15)];
+    let board = board_from("X....O....X....X");
+    let line = format_game_record(&board, &history, Some(PLAYER_X));
+    let options = Options::default();
+    let mut state = record_start(&line, &options)?;
+    if state.board != board_from("X...............")
+        || parse_game_record(&line, &mut state)? != history
+        || state.board != board
+    {
+        return Err(format!("{:?} didn't read back", line));
+    }
+    // Older records without the start position begin on an empty board.
+    if record_start("1 draw: X:5", &options)?.board != empty_board(3) {
+        return Err("a record without from= didn't start empty".to_string());
+    }
+    for (moves, problem) in [
+        ("X:5 O:10", "off the"),
+        ("X:5 O:5", "illegal"),
+        ("X:1 O:4 X:2 O:5 X:3 O:6", "illegal"),
+        ("X5", "written like"),
+    ] {
+        let mut state = GameState::new(3, 3, Variant::Standard);
+        match parse_game_record(&format!("1 draw: {}", moves), &mut state) {
+            Err(message) if message.contains(problem) => {}
+            other => return Err(format!("{:?} gave {:?}", moves, other)),
+        }
+    }
+    Ok(())
+}
+
+fn check_theme_roles() -> CheckResult {
+    // Monochrome and --no-color lean on glyphs and brackets instead.
+    for name in ["default", "classic", "high-contrast"] {
+        let theme = Theme::named(name).ok_or(format!("no {} theme", name))?;
+        let roles = [
+            ("X", theme.x),
+            ("O", theme.o),
+            ("winning line", theme.highlight),
+            ("grid", theme.grid),
+        ];
+        for (i, (first, color)) in roles.iter().enumerate() {
+            if let Some((second, _)) = roles[i + 1..].iter().find(|(_, other)| other == color) {
+                return Err(format!(
+                    "{} theme: {} and {} share {:?}",
+                    name, first, second, color
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_parse_start() -> CheckResult {
+    let mut options = Options::default();
+    let board = parse_start("xo.XO..X.", &mut options)?;
+    if board != board_from("XO.XO..X.") || player_to_move(&board) != PLAYER_O {
+        return Err(format!("read {:?}", board));
+    }
+    if options.board_size() != 3 {
+        return Err(format!("size {} from nine cells", options.board_size()));
+    }
+    for (text, size, problem) in [
+        ("XO.XO..X", None, "square"),
+        ("XO.XO..X?", None, "isn't X, O"),
+        ("XX.......", None, "as many marks"),
+        ("XXXOO....", None, "already won"),
+        ("X...............", Some(3), "don't fit a 3×3"),
+    ] {
+        let mut options = Options {
+            size,
+            ..Options::default()
+        };
+        match parse_start(text, &mut options) {
+            Err(message) if message.contains(problem) => {}
+            Err(message) => return Err(format!("{}: {}", text, message)),
+            Ok(_) => return Err(format!("{} was accepted", text)),
+        }
+    }
+    Ok(())
+}
+
+fn check_move_numbers() -> CheckResult {
+    let options = Options {
+        theme: Theme::NO_COLOR,
+        show_move_numbers: true,
+        ..Options::default()
+    };
+    let board = board_from("XOXOXOXOXOXO....");
+    let history: Vec<(char, usize)> = (0..12).map(|idx| (board[idx], idx)).collect();
+    let mut output = Vec::new();
+    render_board(
+        &board,
+        &mut output,
+        &history,
+        &options,
+        Highlight::default(),
+    )
+    .map_err(|e| e.to_string())?;
+    let rows = screen_rows(&output);
+    if !rows
+        .iter()
+        .any(|row| row.starts_with("  X⁹ |  O¹⁰|  X¹¹|  O¹²"))
+    {
+        return Err(format!("drew {:#?}", rows));
+    }
+    Ok(())
+}
+
+fn check_render_board() -> CheckResult {
+    // --no-color brackets the cursor and the winning line, so they show as text.
+    let options = Options {
+        theme: Theme::NO_COLOR,
+        ..Options::default()
+    };
+    let board = board_from("XO.XO.X..");
+    let snapshots = [
+        (
+            Highlight {
+                cursor: Some(8),
+                ..Highlight::default()
+            },
+            [
+                "Tic Tac Toe",
+                "========================",
+                "",
+                "  X  |  O  |  3",
+                "-----+-----+-----",
+                "  X  |  O  |  6",
+                "-----+-----+-----",
+                "  X  |  8  | [9]",
+            ],
+        ),
+        (
+            Highlight {
+                line: Some(&[0, 3, 6]),
+                ..Highlight::default()
+            },
+            [
+                "Tic Tac Toe",
+                "========================",
+                "",
+                " [X] |  O  |  3",
+                "-----+-----+-----",
+                " [X] |  O  |  6",
+                "-----+-----+-----",
+                " [X] |  8  |  9",
+            ],
+        ),
+    ];
+    for (highlight, expected) in snapshots {
+        let mut output = Vec::new();
+        render_board(&board, &mut output, &[], &options, highlight).map_err(|e| e.to_string())?;
+        let rows = screen_rows(&output);
+        if rows != expected {
+            return Err(format!("drew {:#?}", rows));
+        }
+    }
+    // The last frame of the sweep is the board drawn with the whole line.
+    let history: Vec<(char, usize)> = [0, 1, 3, 4, 6]
+        .iter()
+        .map(|&idx| (board[idx], idx))
+        .collect();
+    for options in [
+        Options {
+            show_move_numbers: true,
+            ..options
+        },
+        Options::default(),
+    ] {
+        let highlight = Highlight {
+            line: Some(&[0, 3, 6]),
+            ..Highlight::default()
+        };
+        let mut whole = Vec::new();
+        render_board(&board, &mut whole, &history, &options, highlight)
+            .map_err(|e| e.to_string())?;
+        let mut swept = Vec::new();
+        sweep_winning_line(
+            &board,
+            &mut swept,
+            &history,
+            &options,
+            highlight,
+            Duration::from_millis(1),
+        )
+        .map_err(|e| e.to_string())?;
+        if screen_rows(&swept) != screen_rows(&whole) {
+            return Err(format!("the sweep ended on {:#?}", screen_rows(&swept)));
+        }
+    }
+    // In colour only the winning line uses the highlight.
+    let mut output = Vec::new();
+    let highlight = Highlight {
+        line: Some(&[0, 3, 6]),
+        ..Highlight::default()
+    };
+    render_board(&board, &mut output, &[], &Options::default(), highlight)
+        .map_err(|e| e.to_string())?;
+    let mut green = Vec::new();
+    queue!(green, SetForegroundColor(Theme::SAFE.highlight)).map_err(|e| e.to_string())?;
+    let text = String::from_utf8_lossy(&output);
+    let count = text.matches(&*String::from_utf8_lossy(&green)).count();
+    if count != 3 {
+        return Err(format!("{} cells in the highlight colour", count));
+    }
+    Ok(())
+}
+
+fn check_move_list_fits() -> CheckResult {
+    let theme = Theme::NO_COLOR;
+    let history: Vec<(char, usize)> = (0..10)
+        .map(|idx| (if idx % 2 == 0 { PLAYER_X } else { PLAYER_O }, idx))
+        .collect();
+    // Rows given, then the heading and how many rows the list takes.
+    let cases = [(20, "Moves:", 11), (6, "Moves (6 earlier):", 5), (2, "", 0)];
+    for (rows, heading, used) in cases {
+        let mut output = Vec::new();
+        render_move_list(&mut output, (0, 0), rows, &history, &theme).map_err(|e| e.to_string())?;
+        let drawn = if output.is_empty() {
+            Vec::new()
+        } else {
+            screen_rows(&output)
+        };
+        let fits = drawn.len() == used
+            && drawn.first().is_none_or(|first| first == heading)
+            && drawn.last().is_none_or(|last| last == "10. O -> 10");
+        if !fits {
+            return Err(format!("{} rows drew {:#?}", rows, drawn));
+        }
+    }
+    Ok(())
+}
+
+fn check_big_board() -> CheckResult {
+    BIG_CELLS.store(true, Ordering::Relaxed);
+    let result = big_board_rows();
+    BIG_CELLS.store(false, Ordering::Relaxed);
+    let rows = result?;
+    let expected = [
+        "Tic Tac Toe",
+        "========================",
+        "",
+        // The winning X is shaded and bracketed, since there is no colour.
+        " ▒▒   ▒▒ |  ▄███▄  |",
+        "  ▒▒ ▒▒  | ██   ██ |",
+        "[  ▒▒▒  ]| ██   ██ |    3",
+        "  ▒▒ ▒▒  | ██   ██ |",
+        " ▒▒   ▒▒ |  ▀███▀  |",
+        "---------+---------+---------",
+        " ██   ██ |  ▄███▄  |",
+    ];
+    if !rows.starts_with(&expected.map(String::from)) {
+        return Err(format!("drew {:#?}", rows));
+    }
+    Ok(())
+}
+
+// The big screen for a few marks with the winning line through the first
+// column, checking on the way that every part of each cell can be clicked.
+fn big_board_rows() -> std::result::Result<Vec<String>, String> {
+    for idx in 0..9 {
+        let (x, y) = cell_position(idx, 3, (0, 0));
+        for (dx, dy) in [(0, 0), (-4, -2), (4, 2)] {
+            let (x, y) = (x.saturating_add_signed(dx), y.saturating_add_signed(dy));
+            if cell_at(x, y, 3) != Some(idx) {
+                return Err(format!("({}, {}) doesn't hit cell {}", x, y, idx + 1));
+            }
+        }
+    }
+    let options = Options {
+        theme: Theme::NO_COLOR,
+        ..Options::default()
+    };
+    let highlight = Highlight {
+        line: Some(&[0]),
+        ..Highlight::default()
+    };
+    let mut output = Vec::new();
+    render_board(
+        &board_from("XO.XO.X.."),
+        &mut output,
+        &[],
+        &options,
+        highlight,
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(screen_rows(&output))
+}
+
+fn check_grid_styles() -> CheckResult {
+    let board = board_from("XO.XO.X..");
+    let theme = Theme::NO_COLOR;
+    let cases = [
+        (
+            GridStyle::ASCII,
+            " X | O |\n---+---+---\n X | O |\n---+---+---\n X |   |\n",
+        ),
+        (
+            GridStyle::BOX,
+            " X │ O │\n───┼───┼───\n X │ O │\n───┼───┼───\n X │   │\n",
+        ),
+    ];
+    for (grid, expected) in cases {
+        let text = board_to_text(&board, &theme, grid);
+        if text != expected {
+            return Err(format!("copied as {:?}", text));
+        }
+    }
+    // On screen no ASCII grid character is left once box drawing is on.
+    let options = Options {
+        grid: GridStyle::BOX,
+        theme,
+        ..Options::default()
+    };
+    let mut output = Vec::new();
+    render_board(&board, &mut output, &[], &options, Highlight::default())
+        .map_err(|e| e.to_string())?;
+    let grid: String = screen_rows(&output)[3..].concat();
+    if grid.contains(['|', '-', '+']) || !grid.contains('┼') {
+        return Err(format!("drew {:#?}", screen_rows(&output)));
+    }
+    Ok(())
+}
+
+// What a terminal would show for `output`: cursor moves and clears are followed,
+// colours dropped. Leading blank rows and the shared left margin are cut, so the
+// result doesn't depend on where the board was centred.
+fn screen_rows(output: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(output);
+    let mut cells: HashMap<(usize, usize), char> = HashMap::new();
+    let (mut row, mut col) = (0, 0);
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                // Control sequence: ESC [ parameters final-letter.
+                let mut params = String::new();
+                let mut command = ' ';
+                for next in chars.by_ref().skip(1) {
+                    if next.is_ascii_alphabetic() {
+                        command = next;
+                        break;
+                    }
+                    params.push(next);
+                }
+                let numbers: Vec<usize> =
+                    params.split(';').filter_map(|n| n.parse().ok()).collect();
+                match command {
+                    'H' => {
+                        row = numbers.first().map_or(0, |r| r - 1);
+                        col = numbers.get(1).map_or(0, |c| c - 1);
+                    }
+                    'J' => cells.clear(),
+                    'K' => cells.retain(|&(r, c), _| r != row || c < col),
+                    _ => {}
+                }
+            }
+            '\n' => row += 1,
+            '\r' => col = 0,
+            _ => {
+                cells.insert((row, col), c);
+                col += 1;
+            }
+        }
+    }
+    let top = cells.keys().map(|&(r, _)| r).min().unwrap_or(0);
+    let left = cells.keys().map(|&(_, c)| c).min().unwrap_or(0);
+    let bottom = cells.keys().map(|&(r, _)| r).max().unwrap_or(0);
+    (top..=bottom)
+        .map(|r| {
+            let width = cells
+                .keys()
+                .filter(|&&(cr, _)| cr == r)
+                .map(|&(_, c)| c + 1)
+                .max();
+            let line: String = (left..width.unwrap_or(left))
+                .map(|c| cells.get(&(r, c)).copied().unwrap_or(' '))
+                .collect();
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+fn check_parse_marks() -> CheckResult {
+    if parse_marks("#@") != Some(['#', '@']) {
+        return Err("#@ wasn't accepted".to_string());
+    }
+    for pair in ["5@", "#9", "##", "#", "#@!", "# "] {
+        if parse_marks(pair).is_some() {
+            return Err(format!("{:?} was accepted", pair));
+        }
+    }
+    Ok(())
+}
+
+fn check_impossible_self_play() -> CheckResult {
+    let mut state = GameState::new(3, 3, Variant::Standard);
+    while !state.result().is_over() {
+        let ai_move = pick_ai_move(
+            &state.board,
+            state.lines(),
+            Difficulty::Impossible,
+            state.current_player,
+            &mut rand::rng(),
+        );
+        state
+            .apply_move(ai_move.pos)
+            .map_err(|err| err.to_string())?;
+    }
+    match state.result().winner() {
+        Some(winner) => Err(format!("{} won on {:?}", winner, state.board)),
+        None => Ok(()),
+    }
+}
+
+fn check_minimax_memo() -> CheckResult {
+    for variant in [Variant::Standard, Variant::Torus] {
+        let lines = win_lines(3, 3, variant);
+        let samples = std::iter::once(empty_board(3))
+            .chain((0..200).filter_map(|_| random_open_board(&lines)));
+        for board in samples {
+            let computer = player_to_move(&board);
+            let mut plain = Search::new(3, &lines, computer, false, false);
+            let mut cached = Search::new(3, &lines, computer, false, true);
+            let (plain_scores, cached_scores) = (plain.scores(&board), cached.scores(&board));
+            if cached_scores != plain_scores {
+                return Err(format!(
+                    "scores {:?} with the memo, {:?} without, on {:?}",
+                    cached_scores, plain_scores, board
+                ));
+            }
+            if cached.nodes > plain.nodes {
+                return Err(format!(
+                    "the memo visited {} nodes against {} on {:?}",
+                    cached.nodes, plain.nodes, board
+                ));
+            }
+        }
+    }
+    // The three distinct openings leave the other six as cache hits.
+    let lines = win_lines(3, 3, Variant::Standard);
+    let mut plain = Search::new(3, &lines, PLAYER_X, false, false);
+    plain.scores(&empty_board(3));
+    let mut cached = Search::new(3, &lines, PLAYER_X, false, true);
+    cached.scores(&empty_board(3));
+    if cached.nodes * 10 > plain.nodes {
+        return Err(format!(
+            "the memo only cut the empty-board search from {} to {} nodes",
+            plain.nodes, cached.nodes
+        ));
+    }
+    Ok(())
+}
+
+fn check_minimax_varies() -> CheckResult {
+    // Every opening draws with perfect play, so any of them may come first.
+    let lines = win_lines(3, 3, Variant::Standard);
+    let openings: HashSet<usize> = (0..40)
+        .map(|_| get_ai_move_minimax(&empty_board(3), &lines, PLAYER_X, &mut rand::rng()).0)
+        .collect();
+    if openings.len() < 2 {
+        return Err(format!("always opened at {:?}", openings));
+    }
+    // A single winning move is still the only one played.
+    let board = board_from("XX.OO....");
+    for _ in 0..10 {
+        let (idx, _) = get_ai_move_minimax(&board, &lines, PLAYER_X, &mut rand::rng());
+        if idx != 2 {
+            return Err(format!("played {} instead of winning at 3", idx + 1));
+        }
+    }
+    Ok(())
+}
+
+fn check_alpha_beta() -> CheckResult {
+    for variant in [Variant::Standard, Variant::Torus] {
+        let lines = win_lines(3, 3, variant);
+        let samples = std::iter::once(empty_board(3))
+            .chain((0..60).filter_map(|_| random_open_board(&lines)));
+        for board in samples {
+            let computer = player_to_move(&board);
+            let mut plain = Search::new(3, &lines, computer, false, false);
+            let scores = plain.scores(&board);
+            let top = scores.iter().map(|&(_, score)| score).max();
+            let optimal: Vec<usize> = scores
+                .iter()
+                .filter(|&&(_, score)| Some(score) == top)
+                .map(|&(idx, _)| idx)
+                .collect();
+            let mut plain = Search::new(3, &lines, computer, false, false);
+            plain.best_move(&board, &mut rand::rng());
+            let (pruned, nodes) = get_ai_move_minimax(&board, &lines, computer, &mut rand::rng());
+            if !optimal.contains(&pruned) {
+                return Err(format!(
+                    "pruned search played {}, not one of {:?}, on {:?}",
+                    pruned + 1,
+                    optimal.iter().map(|idx| idx + 1).collect::<Vec<_>>(),
+                    board
+                ));
+            }
+            if nodes > plain.nodes {
+                return Err(format!(
+                    "pruning visited {} nodes against {} on {:?}",
+                    nodes, plain.nodes, board
+                ));
+            }
+            // Full-window scores stay exact with pruning on.
+            let mut full = Search::new(3, &lines, computer, true, true);
+            if full.scores(&board) != plain.scores(&board) {
+                return Err(format!("pruned scores differ on {:?}", board));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_minimax_takes_win() -> CheckResult {
+    let lines = win_lines(3, 3, Variant::Standard);
+    // Each side could also block, but winning now must come first.
+    for (cells, computer, winning_cell) in [("XX.OO....", PLAYER_X, 2), ("XX.OO..X.", PLAYER_O, 5)]
+    {
+        let (idx, _) = get_ai_move_minimax(&board_from(cells), &lines, computer, &mut rand::rng());
+        if idx != winning_cell {
+            return Err(format!(
+                "{} played {} on {} instead of winning at {}",
+                computer,
+                idx + 1,
+                cells,
+                winning_cell + 1
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_medium_ai() -> CheckResult {
+    let lines = win_lines(3, 3, Variant::Standard);
+    match get_ai_move_heuristic(
+        &empty_board(3),
+        &lines,
+        PLAYER_X,
+        PLAYER_O,
+        &mut rand::rng(),
+    ) {
+        (4, MoveReason::Book) => {}
+        (idx, _) => return Err(format!("opened at {} instead of the centre", idx + 1)),
+    }
+    // X on two edges could fork from the corner between them.
+    match get_ai_move_heuristic(
+        &board_from(".X.X....O"),
+        &lines,
+        PLAYER_O,
+        PLAYER_X,
+        &mut rand::rng(),
+    ) {
+        (0, MoveReason::Fork) => {}
+        (idx, _) => {
+            return Err(format!(
+                "O played {} instead of taking the fork at 1",
+                idx + 1
+            ))
+        }
+    }
+    // Blocking an immediate line still comes before the fork.
+    match get_ai_move_heuristic(
+        &board_from("XX..O...."),
+        &lines,
+        PLAYER_O,
+        PLAYER_X,
+        &mut rand::rng(),
+    ) {
+        (2, MoveReason::Block) => Ok(()),
+        (idx, _) => Err(format!("O played {} instead of blocking at 3", idx + 1)),
+    }
+}
+
+fn check_hard_ai_computer_first() -> CheckResult {
+    let lines = win_lines(3, 3, Variant::Standard);
+    // Computer is X with two in the top row; the human's O also threatens the middle.
+    let board = board_from("XX.OO....");
+    match get_ai_move_blocking(&board, &lines, PLAYER_X, PLAYER_O, &mut rand::rng()) {
+        (2, MoveReason::Win) => {}
+        (idx, _) => return Err(format!("X played {} instead of winning at 3", idx + 1)),
+    }
+    // With nothing to complete, X blocks O rather than helping it.
+    let board = board_from("X..OO...X");
+    match get_ai_move_blocking(&board, &lines, PLAYER_X, PLAYER_O, &mut rand::rng()) {
+        (5, MoveReason::Block) => Ok(()),
+        (idx, _) => Err(format!("X played {} instead of blocking at 6", idx + 1)),
+    }
+}
+
+fn check_hard_ai_forks() -> CheckResult {
+    let lines = win_lines(3, 3, Variant::Standard);
+    // X can't win or be beaten at once, but the left column and a diagonal are open.
+    let board = board_from("XO..X...O");
+    match get_ai_move_blocking(&board, &lines, PLAYER_X, PLAYER_O, &mut rand::rng()) {
+        (idx, MoveReason::DoubleThreat) if fork_cells(&board, &lines, PLAYER_X).contains(&idx) => {}
+        (idx, _) => return Err(format!("X played {} instead of forking", idx + 1)),
+    }
+    // X on opposite corners forks from either free corner, so O must make
+    // X block on an edge instead; the opening book knows this one too.
+    let board = board_from("X...O...X");
+    match get_ai_move_blocking(&board, &lines, PLAYER_O, PLAYER_X, &mut rand::rng()) {
+        (idx, MoveReason::Force | MoveReason::Book) if idx % 2 == 1 => {}
+        (idx, _) => return Err(format!("O played {} into the corner trap", idx + 1)),
+    }
+    // Every X line of play from the trap, against O's random fallbacks too.
+    for start in ["X...O...X", "X...O...."] {
+        for _ in 0..20 {
+            if let Some(board) = hard_ai_loses(&mut board_from(start), &lines) {
+                return Err(format!("X beat the hard AI from {}: {}", start, board));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_opening_book() -> CheckResult {
+    let expected = [
+        (".........", PLAYER_X, vec![4]),
+        ("X........", PLAYER_O, vec![4]),
+        ("......X..", PLAYER_O, vec![4]),
+        ("...X.....", PLAYER_O, vec![4]),
+        ("....X....", PLAYER_O, vec![0]),
+        ("X...O...X", PLAYER_O, vec![1]),
+        ("..X.O.X..", PLAYER_O, vec![1, 3, 5, 7]),
+    ];
+    for (cells, mark, replies) in expected {
+        let board = board_from(cells);
+        match lookup_opening(&board, mark) {
+            Some(idx) if replies.contains(&idx) => {}
+            found => return Err(format!("book played {:?} on {}", found, cells)),
+        }
+    }
+    // Positions off the book, the wrong side to move and other rules fall through.
+    for (cells, mark) in [("XO.......", PLAYER_X), ("X........", PLAYER_X)] {
+        if let Some(idx) = lookup_opening(&board_from(cells), mark) {
+            return Err(format!("book played {} on {}", idx + 1, cells));
+        }
+    }
+    let torus = win_lines(3, 3, Variant::Torus);
+    match get_ai_move_heuristic(
+        &board_from("X........"),
+        &torus,
+        PLAYER_O,
+        PLAYER_X,
+        &mut rand::rng(),
+    ) {
+        (_, MoveReason::Book) => Err("the book was used on a torus".into()),
+        _ => Ok(()),
+    }
+}
+
+// Plays every X move against the hard AI as O and returns a board X won on.
+fn hard_ai_loses(board: &mut [char], lines: &[Vec<usize>]) -> Option<String> {
+    match game_result(board, lines) {
+        GameResult::XWins(_) => return Some(board.iter().collect()),
+        GameResult::OWins(_) | GameResult::Draw => return None,
+        GameResult::Ongoing => {}
+    }
+    if player_to_move(board) == PLAYER_O {
+        let (idx, _) = get_ai_move_blocking(board, lines, PLAYER_O, PLAYER_X, &mut rand::rng());
+        board[idx] = PLAYER_O;
+        let lost = hard_ai_loses(board, lines);
+        board[idx] = EMPTY;
+        return lost;
+    }
+    (0..board.len()).find_map(|idx| {
+        if board[idx] != EMPTY {
+            return None;
+        }
+        board[idx] = PLAYER_X;
+        let lost = hard_ai_loses(board, lines);
+        board[idx] = EMPTY;
+        lost
+    })
+}
+
+fn check_scores_round_trip() -> CheckResult {
+    let text = format_scores(Score(3), Score(0), Score(u32::MAX));
+    match parse_scores(&text) {
+        Some((Score(3), Score(0), Score(u32::MAX))) => {}
+        _ => return Err(format!("{:?} didn't read back", text)),
+    }
+    match parse_scores("{\"x\": 4294967296, \"o\": 2, \"draws\": 99999999999}") {
+        Some((Score(u32::MAX), Score(2), Score(u32::MAX))) => {}
+        _ => return Err("out-of-range counts weren't clamped".to_string()),
+    }
+    for text in [
+        "",
+        "{}",
+        "{\"x\": 1, \"o\": 2}",
+        "{\"x\": -1, \"o\": 0, \"draws\": 0}",
+        "x o draws",
+    ] {
+        if parse_scores(text).is_some() {
+            return Err(format!("{:?} was accepted", text));
+        }
+    }
+    Ok(())
+}
+
+fn check_series_clinch() -> CheckResult {
+    let mut series = Series::new(GameMode::Friend, 3);
+    for winner in [None, Some(PLAYER_X), None] {
+        series.record(winner);
+    }
+    // Three games played, so a single win decides it.
+    if series.winner() != Some(PLAYER_X) {
+        return Err("1-0 with two draws didn't end best of 3".to_string());
+    }
+    let mut series = Series::new(GameMode::Friend, 5);
+    for winner in [Some(PLAYER_O), None, Some(PLAYER_O)] {
+        series.record(winner);
+    }
+    if series.is_over() {
+        return Err("2-0 ended best of 5".to_string());
+    }
+    series.record(Some(PLAYER_O));
+    match series.winner() {
+        Some(PLAYER_O) => Ok(()),
+        _ => Err("3-0 didn't clinch best of 5".to_string()),
+    }
+}
+
+fn check_cell_at() -> CheckResult {
+    for size in [3, 5] {
+        for idx in 0..size * size {
+            let (x, y) = cell_position(idx, size, (0, 0));
+            if cell_at(x, y, size) != Some(idx) {
+                return Err(format!("the mark of cell {} isn't clickable", idx + 1));
+            }
+        }
+    }
+    // A column bar, a row rule, the title and the first column past the grid.
+    for (x, y) in [
+        (CELL_WIDTH, GRID_TOP),
+        (1, GRID_TOP + CELL_HEIGHT),
+        (1, 0),
+        (17, GRID_TOP),
+    ] {
+        if let Some(idx) = cell_at(x, y, 3) {
+            return Err(format!("({}, {}) hit cell {}", x, y, idx + 1));
+        }
+    }
+    Ok(())
+}
+
+fn check_game_record_format() -> CheckResult {
+    let history = [(PLAYER_X, 4), (PLAYER_O, 0), (PLAYER_X, 8)];
+    let line = format_game_record(&board_from("O...X...X"), &history, Some(PLAYER_X));
+    match line.split_once(' ') {
+        Some((time, rest))
+            if time.parse::<u64>().is_ok() && rest == "X won from=.........: X:5 O:1 X:9" => {}
+        _ => return Err(format!("unexpected record {:?}", line)),
+    }
+    if !format_game_record(&empty_board(3), &[], None).ends_with(" draw from=.........: ") {
+        return Err("an empty drawn game isn't recorded as a draw".to_string());
+    }
+    Ok(())
+}
+
+// Whole games against Easy with the human on either side: at every human turn
+// one undo after the reply must give back the position the human moved from,
+// and a redo the position after the reply.
+fn check_undo_against_ai() -> CheckResult {
+    let mut rng = StdRng::seed_from_u64(11);
+    for player_first in [true, false] {
+        let game_mode = GameMode::AI(Difficulty::Easy, player_first, 0);
+        for _ in 0..50 {
+            let mut state = GameState::new(3, 3, Variant::Standard);
+            let mut history = Vec::new();
+            let mut redo_stack = Vec::new();
+            let snapshot =
+                |state: &GameState| (state.board.clone(), state.current_player, state.move_count);
+            let mut play = |state: &mut GameState, history: &mut Vec<(char, usize)>| {
+                let idx = get_ai_move_random(&state.board, &mut rng);
+                history.push((state.current_player, idx));
+                state.apply_move(idx).map_err(|err| err.to_string())
+            };
+            if !is_human_turn(game_mode, state.current_player) {
+                play(&mut state, &mut history)?;
+            }
+            while !state.result().is_over() {
+                let before = snapshot(&state);
+                play(&mut state, &mut history)?;
+                if !state.result().is_over() {
+                    play(&mut state, &mut history)?;
+                }
+                let after = snapshot(&state);
+                if !undo_step(&mut state, &mut history, &mut redo_stack, game_mode) {
+                    return Err("undo refused after the human moved".to_string());
+                }
+                if snapshot(&state) != before || history.len() != before.2 {
+                    return Err(format!(
+                        "undo left {:?} with {} to move, wanted {:?} with {} to move",
+                        state.board, state.current_player, before.0, before.1
+                    ));
+                }
+                redo_step(&mut state, &mut history, &mut redo_stack, game_mode);
+                if snapshot(&state) != after || !redo_stack.is_empty() {
+                    return Err(format!("redo left {:?}, wanted {:?}", state.board, after.0));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_undo_restores_turn() -> CheckResult {
+    let game_mode = GameMode::AI(Difficulty::Easy, true, 0);
+    let mut state = GameState::new(3, 3, Variant::Standard);
+    let mut history = Vec::new();
+    let mut redo_stack = Vec::new();
+    if undo_step(&mut state, &mut history, &mut redo_stack, game_mode) {
+        return Err("undo succeeded with no moves made".to_string());
+    }
+    for idx in [4, 0] {
+        history.push((state.current_player, idx));
+        state.apply_move(idx).map_err(|err| err.to_string())?;
+    }
+    // The human's move and the computer's reply both come off.
+    if !undo_step(&mut state, &mut history, &mut redo_stack, game_mode) {
+        return Err("undo refused after a move".to_string());
+    }
+    if state.board != empty_board(3) || state.current_player != PLAYER_X || state.move_count != 0 {
+        return Err(format!(
+            "undo left {:?} with {} to move",
+            state.board, state.current_player
+        ));
+    }
+    Ok(())
+}
+
+fn check_perfect_play_draws() -> CheckResult {
+    let lines = win_lines(3, 3, Variant::Standard);
+    match result_range(&mut empty_board(3), PLAYER_X, PLAYER_X, &lines) {
+        (0, _) => Ok(()),
+        (best, _) => Err(format!("solver says X gets {} with perfect play", best)),
+    }
+}
+
+fn check_score_saturates() -> CheckResult {
+    let mut score = Score(u32::MAX);
+    score.increment();
+    if score.0 == u32::MAX {
+        Ok(())
+    } else {
+        Err(format!("u32::MAX + 1 gave {}", score))
+    }
+}